@@ -0,0 +1,88 @@
+//! Requests per second on the payload snapshot path, with readers racing each other and,
+//! in the second run, a writer filling the service in a loop.
+//!
+//! `cargo bench --bench contended`
+
+use bytes::Bytes;
+use geta::Service;
+use http::Request;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const REQUESTS: u32 = 200_000;
+
+fn main() {
+    let readers = thread::available_parallelism()
+        .map_or(4, |n| n.get())
+        .max(2);
+    println!("{readers} readers, {REQUESTS} requests each");
+    run("readers only", readers, false);
+    run("readers and a filling writer", readers, true);
+}
+
+fn run(name: &str, readers: usize, fill: bool) {
+    let service = Service::new();
+    service.fill(payload(0));
+    let service = Arc::new(service);
+
+    let done = Arc::new(AtomicBool::new(false));
+    let writer = fill.then(|| {
+        let service = service.clone();
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut fills = 0u64;
+            while !done.load(Ordering::Relaxed) {
+                fills += 1;
+                service.fill(payload(fills));
+            }
+            fills
+        })
+    });
+
+    let start = Arc::new(Barrier::new(readers + 1));
+    let threads: Vec<_> = (0..readers)
+        .map(|_| {
+            let service = service.clone();
+            let start = start.clone();
+            thread::spawn(move || {
+                let rt = tokio::runtime::Builder::new_current_thread()
+                    .build()
+                    .unwrap();
+                start.wait();
+                let begun = Instant::now();
+                rt.block_on(async {
+                    for _ in 0..REQUESTS {
+                        let res = service.call(Request::get("/").body(()).unwrap()).await;
+                        assert!(res.status().is_success());
+                    }
+                });
+                begun.elapsed()
+            })
+        })
+        .collect();
+    start.wait();
+    let elapsed: Duration = threads
+        .into_iter()
+        .map(|t| t.join().unwrap())
+        .max()
+        .unwrap();
+    done.store(true, Ordering::Relaxed);
+    let fills = writer.map(|writer| writer.join().unwrap());
+
+    let total = f64::from(REQUESTS) * readers as f64;
+    print!(
+        "{name}: {:.0} requests/s, {:.0} ns/request per reader",
+        total / elapsed.as_secs_f64(),
+        elapsed.as_nanos() as f64 / f64::from(REQUESTS),
+    );
+    match fills {
+        Some(fills) => println!(", {fills} fills"),
+        None => println!(),
+    }
+}
+
+fn payload(n: u64) -> Bytes {
+    Bytes::from(format!("<p>payload {n}</p>"))
+}
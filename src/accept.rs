@@ -0,0 +1,129 @@
+use crate::Encoding;
+use http::HeaderValue;
+
+/// The members of an `Accept` style list and whether each is acceptable, i.e. its `q` is
+/// not 0.
+fn members(list: &HeaderValue) -> impl Iterator<Item = (&str, bool)> {
+    list.to_str()
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|member| {
+            let mut params = member.split(';').map(str::trim);
+            let value = params.next().filter(|value| !value.is_empty())?;
+            let refused = params
+                .filter_map(|param| param.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+                .and_then(|(_, q)| q.trim().parse::<f32>().ok())
+                .is_some_and(|q| q <= 0.0);
+            Some((value, !refused))
+        })
+}
+
+/// Whether `accept` has a media range taking `content_type`.
+pub(crate) fn accepts_media_type(accept: &HeaderValue, content_type: &HeaderValue) -> bool {
+    let Ok(content_type) = content_type.to_str() else {
+        return true;
+    };
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    let Some((kind, _)) = essence.split_once('/') else {
+        return true;
+    };
+    members(accept).any(|(range, acceptable)| {
+        acceptable
+            && (range == "*/*"
+                || range.eq_ignore_ascii_case(essence)
+                || range
+                    .strip_suffix("/*")
+                    .is_some_and(|range| range.eq_ignore_ascii_case(kind)))
+    })
+}
+
+/// Whether `accept_encoding` rules out `identity`, with `identity;q=0`, or `*;q=0` when it
+/// does not list `identity` (RFC 9110 §12.5.3).
+pub(crate) fn refuses_identity(accept_encoding: &HeaderValue) -> bool {
+    let mut wildcard = None;
+    for (coding, acceptable) in members(accept_encoding) {
+        if coding.eq_ignore_ascii_case("identity") {
+            return !acceptable;
+        }
+        if coding == "*" {
+            wildcard = Some(acceptable);
+        }
+    }
+    wildcard == Some(false)
+}
+
+/// Whether `accept_encoding` takes the content-coding `is` recognizes: listed with a `q`
+/// other than 0, or else covered by a `*` that is not `*;q=0`.
+pub(crate) fn accepts_coding(accept_encoding: &HeaderValue, is: impl Fn(&str) -> bool) -> bool {
+    let mut wildcard = false;
+    for (coding, acceptable) in members(accept_encoding) {
+        if is(coding) {
+            return acceptable;
+        }
+        if coding == "*" {
+            wildcard = acceptable;
+        }
+    }
+    wildcard
+}
+
+/// `accepts_coding` for `encoding`, aliases included, and `identity` unless refused.
+pub(crate) fn accepts_encoding(accept_encoding: &HeaderValue, encoding: Encoding) -> bool {
+    match encoding {
+        Encoding::Identity => !refuses_identity(accept_encoding),
+        encoding => accepts_coding(accept_encoding, |coding| {
+            Encoding::parse(coding) == Some(encoding)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn accepts_media_type() {
+        let accepts = |accept: &'static str, content_type: &'static str| {
+            super::accepts_media_type(
+                &HeaderValue::from_static(accept),
+                &HeaderValue::from_static(content_type),
+            )
+        };
+        assert!(accepts("*/*", "text/html"));
+        assert!(accepts("text/*;q=0.5", "text/html; charset=utf-8"));
+        assert!(accepts("application/json, Text/HTML", "text/html"));
+        assert!(!accepts("application/json", "text/html"));
+        assert!(!accepts("text/html;q=0", "text/html"));
+        assert!(!accepts("image/*", "text/html"));
+    }
+
+    #[test]
+    fn refuses_identity() {
+        let refuses = |accept_encoding: &'static str| {
+            super::refuses_identity(&HeaderValue::from_static(accept_encoding))
+        };
+        assert!(!refuses("gzip"));
+        assert!(!refuses(""));
+        assert!(refuses("gzip, identity;q=0"));
+        assert!(refuses("br, *;q=0"));
+        assert!(!refuses("identity, *;q=0"));
+        assert!(!refuses("*"));
+    }
+
+    #[test]
+    fn accepts_encoding() {
+        let accepts = |accept_encoding: &'static str, encoding| {
+            super::accepts_encoding(&HeaderValue::from_static(accept_encoding), encoding)
+        };
+        assert!(accepts("br, gzip", Encoding::Br));
+        assert!(accepts("x-gzip;q=0.5", Encoding::Gzip));
+        assert!(!accepts("br;q=0, gzip", Encoding::Br));
+        assert!(!accepts("gzip", Encoding::Br));
+        assert!(accepts("*", Encoding::Br));
+        assert!(!accepts("br;q=0, *", Encoding::Br));
+        assert!(!accepts("gzip, *;q=0", Encoding::Br));
+        assert!(accepts("gzip", Encoding::Identity));
+        assert!(!accepts("identity;q=0", Encoding::Identity));
+    }
+}
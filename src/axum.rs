@@ -0,0 +1,43 @@
+use crate::{Body, Service, SharedService};
+use axum::response::{IntoResponse, Response};
+use bytes::Buf;
+use http::Request;
+use http_body_util::BodyExt;
+use std::future::Future;
+use std::pin::Pin;
+
+impl<T, E> From<Body<T, E>> for axum::body::Body
+where
+    T: Buf + Send + 'static,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    fn from(body: Body<T, E>) -> Self {
+        axum::body::Body::new(
+            body.map_frame(|frame| {
+                frame.map_data(|mut chunk| chunk.copy_to_bytes(chunk.remaining()))
+            }),
+        )
+    }
+}
+
+impl<T, E> IntoResponse for Body<T, E>
+where
+    T: Buf + Send + 'static,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    fn into_response(self) -> Response {
+        Response::new(self.into())
+    }
+}
+
+/// Mounts the service as an axum handler, e.g. `.route("/app.js", get(service))`.
+impl<T, S> axum::handler::Handler<(), S> for SharedService<T>
+where
+    T: Buf + Clone + Send + Sync + 'static,
+{
+    type Future = Pin<Box<dyn Future<Output = Response> + Send>>;
+
+    fn call(self, req: Request<axum::body::Body>, _state: S) -> Self::Future {
+        Box::pin(async move { Service::call(&self, req).await.map(Into::into) })
+    }
+}
@@ -1,6 +1,10 @@
+use bytes::buf::Take;
 use bytes::{Buf, Bytes};
+use futures_core::Stream;
 use http_body::{Frame, SizeHint};
+use std::collections::VecDeque;
 use std::convert::Infallible;
+use std::future::Future;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use tokio::sync::mpsc;
@@ -8,7 +12,7 @@ use tokio::sync::mpsc;
 pin_project_lite::pin_project! {
     #[derive(Debug)]
     #[project = BodyProj]
-    pub enum Body<T> {
+    pub enum Body<T, E = Infallible> {
         Empty,
         Buf {
             inner: Option<T>,
@@ -16,25 +20,56 @@ pin_project_lite::pin_project! {
         Bytes {
             inner: Option<Bytes>,
         },
+        Slice {
+            inner: Option<Take<T>>,
+        },
+        // `Buf` yielded in frames of at most `frame_size` bytes
+        Frames {
+            inner: T,
+            frame_size: usize,
+        },
+        Chunks {
+            chunks: VecDeque<BodyChunk<T>>,
+        },
+        Boxed {
+            stream: BoxStream<E>,
+        },
         Stream {
-            rx: mpsc::Receiver<Bytes>,
+            rx: mpsc::Receiver<Result<Bytes, E>>,
+            // total length of the stream, when known up front
+            len: Option<u64>,
+            // the task feeding `rx`, if any
+            task: Option<AbortOnDrop>,
         },
     }
 }
 
-impl<T> From<Bytes> for Body<T> {
+impl<T, E> From<Bytes> for Body<T, E> {
     fn from(bytes: Bytes) -> Self {
         Self::Bytes { inner: Some(bytes) }
     }
 }
 
-impl<T> From<mpsc::Receiver<Bytes>> for Body<T> {
-    fn from(rx: mpsc::Receiver<Bytes>) -> Self {
-        Self::Stream { rx }
+impl<T, E> From<mpsc::Receiver<Result<Bytes, E>>> for Body<T, E> {
+    fn from(rx: mpsc::Receiver<Result<Bytes, E>>) -> Self {
+        Self::Stream {
+            rx,
+            len: None,
+            task: None,
+        }
+    }
+}
+
+/// A type-erased stream of frames, see `Body::from_stream`.
+pub struct BoxStream<E>(Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send + Sync>>);
+
+impl<E> std::fmt::Debug for BoxStream<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("BoxStream")
     }
 }
 
-impl<T> Body<T> {
+impl<T, E> Body<T, E> {
     pub fn new(buf: T) -> Self {
         Self::Buf { inner: Some(buf) }
     }
@@ -42,12 +77,102 @@ impl<T> Body<T> {
     pub fn from_static(bytes: &'static [u8]) -> Self {
         Self::from(Bytes::from_static(bytes))
     }
+
+    /// Type-erases the body, e.g. to merge it into a router with other response bodies.
+    /// Frames become `Bytes`, which is free for `Bytes` payloads.
+    pub fn boxed(self) -> http_body_util::combinators::BoxBody<Bytes, E>
+    where
+        T: Buf + Send + Sync + 'static,
+        E: Send + 'static,
+    {
+        use http_body_util::BodyExt;
+        self.map_frame(|frame| frame.map_data(|mut data| data.copy_to_bytes(data.remaining())))
+            .boxed()
+    }
+
+    /// Like `boxed`, for bodies that are not `Sync`.
+    pub fn boxed_unsync(self) -> http_body_util::combinators::UnsyncBoxBody<Bytes, E>
+    where
+        T: Buf + Send + 'static,
+        E: Send + 'static,
+    {
+        use http_body_util::BodyExt;
+        self.map_frame(|frame| frame.map_data(|mut data| data.copy_to_bytes(data.remaining())))
+            .boxed_unsync()
+    }
+
+    /// Polls `stream` directly for frames, e.g. a download from another service. The stream
+    /// must be `Sync`, so that the body stays `Sync` as hyper and `boxed` expect.
+    pub fn from_stream<S>(stream: S) -> Self
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + Sync + 'static,
+    {
+        Self::Boxed {
+            stream: BoxStream(Box::pin(stream)),
+        }
+    }
+
+    /// Streams the file at `path` from a background task, without keeping it in memory.
+    /// Read errors end the stream with an `Err` frame.
+    #[cfg(feature = "fs")]
+    pub async fn from_file(path: impl AsRef<std::path::Path>) -> std::io::Result<Self>
+    where
+        E: From<std::io::Error> + Send + 'static,
+    {
+        use tokio::io::AsyncReadExt;
+        const READ_SIZE: usize = 64 * 1024;
+
+        let mut file = tokio::fs::File::open(path).await?;
+        let len = file.metadata().await?.len();
+        let (tx, rx) = mpsc::channel(1);
+        let task = tokio::spawn(async move {
+            loop {
+                let mut buf = bytes::BytesMut::with_capacity(READ_SIZE);
+                let chunk = match file.read_buf(&mut buf).await {
+                    Ok(0) => break,
+                    Ok(_) => Ok(buf.freeze()),
+                    Err(err) => Err(E::from(err)),
+                };
+                let failed = chunk.is_err();
+                if tx.send(chunk).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+        Ok(Self::from_task(rx, Some(len), task.abort_handle()))
+    }
 }
 
+/// Aborts the task feeding a `Body::Stream` once the body is dropped, e.g. when the client
+/// goes away mid-transfer.
 #[derive(Debug)]
-pub enum BodyChunk<T: Buf> {
+pub struct AbortOnDrop(tokio::task::AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+impl<T, E> Body<T, E> {
+    pub(crate) fn from_task(
+        rx: mpsc::Receiver<Result<Bytes, E>>,
+        len: Option<u64>,
+        task: tokio::task::AbortHandle,
+    ) -> Self {
+        Self::Stream {
+            rx,
+            len,
+            task: Some(AbortOnDrop(task)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BodyChunk<T> {
     Buf(T),
     Bytes(Bytes),
+    Slice(Take<T>),
 }
 
 impl<T: Buf> bytes::Buf for BodyChunk<T> {
@@ -55,6 +180,7 @@ impl<T: Buf> bytes::Buf for BodyChunk<T> {
         match self {
             BodyChunk::Buf(inner) => inner.remaining(),
             BodyChunk::Bytes(inner) => inner.remaining(),
+            BodyChunk::Slice(inner) => inner.remaining(),
         }
     }
 
@@ -62,6 +188,15 @@ impl<T: Buf> bytes::Buf for BodyChunk<T> {
         match self {
             BodyChunk::Buf(inner) => inner.chunk(),
             BodyChunk::Bytes(inner) => inner.chunk(),
+            BodyChunk::Slice(inner) => inner.chunk(),
+        }
+    }
+
+    fn copy_to_bytes(&mut self, len: usize) -> Bytes {
+        match self {
+            BodyChunk::Buf(inner) => inner.copy_to_bytes(len),
+            BodyChunk::Bytes(inner) => inner.copy_to_bytes(len),
+            BodyChunk::Slice(inner) => inner.copy_to_bytes(len),
         }
     }
 
@@ -69,13 +204,14 @@ impl<T: Buf> bytes::Buf for BodyChunk<T> {
         match self {
             BodyChunk::Buf(inner) => inner.advance(cnt),
             BodyChunk::Bytes(inner) => inner.advance(cnt),
+            BodyChunk::Slice(inner) => inner.advance(cnt),
         }
     }
 }
 
-impl<T: Buf> http_body::Body for Body<T> {
+impl<T: Buf, E> http_body::Body for Body<T, E> {
     type Data = BodyChunk<T>;
-    type Error = Infallible;
+    type Error = E;
 
     fn poll_frame(
         self: Pin<&mut Self>,
@@ -92,11 +228,29 @@ impl<T: Buf> http_body::Body for Body<T> {
                 None => Poll::Ready(None),
                 Some(buf) => Poll::Ready(Some(Ok(Frame::data(BodyChunk::Bytes(buf))))),
             },
-            Stream { rx } => match rx.poll_recv(cx) {
-                Poll::Pending => Poll::Pending,
-                Poll::Ready(ready) => {
-                    Poll::Ready(ready.map(|bytes| Ok(Frame::data(BodyChunk::Bytes(bytes)))))
+            Slice { inner } => match inner.take() {
+                None => Poll::Ready(None),
+                Some(buf) => Poll::Ready(Some(Ok(Frame::data(BodyChunk::Slice(buf))))),
+            },
+            Frames { inner, frame_size } => {
+                if !inner.has_remaining() {
+                    return Poll::Ready(None);
                 }
+                let n = inner.chunk().len().min(*frame_size);
+                let frame = inner.copy_to_bytes(n);
+                Poll::Ready(Some(Ok(Frame::data(BodyChunk::Bytes(frame)))))
+            }
+            Boxed { stream } => stream.0.as_mut().poll_next(cx).map(|ready| {
+                ready.map(|result| result.map(|bytes| Frame::data(BodyChunk::Bytes(bytes))))
+            }),
+            Chunks { chunks } => {
+                Poll::Ready(chunks.pop_front().map(|chunk| Ok(Frame::data(chunk))))
+            }
+            Stream { rx, .. } => match rx.poll_recv(cx) {
+                Poll::Pending => Poll::Pending,
+                Poll::Ready(ready) => Poll::Ready(
+                    ready.map(|result| result.map(|bytes| Frame::data(BodyChunk::Bytes(bytes)))),
+                ),
             },
         }
     }
@@ -106,6 +260,10 @@ impl<T: Buf> http_body::Body for Body<T> {
             Body::Empty => true,
             Body::Buf { inner } => inner.is_none(),
             Body::Bytes { inner } => inner.is_none(),
+            Body::Slice { inner } => inner.is_none(),
+            Body::Frames { inner, .. } => !inner.has_remaining(),
+            Body::Chunks { chunks } => chunks.is_empty(),
+            Body::Boxed { .. } => false,
             Body::Stream { .. } => false,
         }
     }
@@ -117,7 +275,258 @@ impl<T: Buf> http_body::Body for Body<T> {
             Body::Buf { inner: None } => SizeHint::with_exact(0),
             Body::Bytes { inner: Some(inner) } => SizeHint::with_exact(inner.remaining() as u64),
             Body::Bytes { inner: None } => SizeHint::with_exact(0),
-            Body::Stream { .. } => SizeHint::default(),
+            Body::Slice { inner: Some(inner) } => SizeHint::with_exact(inner.remaining() as u64),
+            Body::Slice { inner: None } => SizeHint::with_exact(0),
+            Body::Frames { inner, .. } => SizeHint::with_exact(inner.remaining() as u64),
+            Body::Chunks { chunks } => {
+                SizeHint::with_exact(chunks.iter().map(|c| c.remaining() as u64).sum())
+            }
+            Body::Stream { len: Some(len), .. } => SizeHint::with_exact(*len),
+            Body::Stream { len: None, .. } => SizeHint::default(),
+            Body::Boxed { .. } => SizeHint::default(),
+        }
+    }
+}
+
+/// What an inspector passed to `Body::inspect` is told about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyEvent {
+    /// A data frame of this many bytes was handed to the connection.
+    Frame(usize),
+    /// The body is done, reported exactly once. `complete` is false when it ended with an
+    /// error or was dropped early, e.g. because the client went away.
+    End { total: u64, complete: bool },
+}
+
+type Inspector = Box<dyn FnMut(BodyEvent) + Send>;
+
+/// A `Body` whose frames are reported to an inspector, see `Body::inspect`.
+pub struct Inspected<T, E = Infallible> {
+    body: Body<T, E>,
+    inspector: Inspector,
+    total: u64,
+    ended: bool,
+}
+
+impl<T, E> Body<T, E> {
+    /// Reports the size of every data frame and the end of the body to `inspector`, e.g. to
+    /// account the bytes sent per response.
+    pub fn inspect(self, inspector: impl FnMut(BodyEvent) + Send + 'static) -> Inspected<T, E> {
+        Inspected {
+            body: self,
+            inspector: Box::new(inspector),
+            total: 0,
+            ended: false,
+        }
+    }
+}
+
+impl<T, E> Inspected<T, E> {
+    fn end(&mut self, complete: bool) {
+        if !self.ended {
+            self.ended = true;
+            (self.inspector)(BodyEvent::End {
+                total: self.total,
+                complete,
+            });
+        }
+    }
+}
+
+impl<T: std::fmt::Debug, E: std::fmt::Debug> std::fmt::Debug for Inspected<T, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Inspected")
+            .field("body", &self.body)
+            .field("total", &self.total)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, E> Drop for Inspected<T, E> {
+    fn drop(&mut self) {
+        self.end(false);
+    }
+}
+
+impl<T: Buf, E> http_body::Body for Inspected<T, E> {
+    type Data = BodyChunk<T>;
+    type Error = E;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        if this.ended {
+            return Poll::Ready(None);
+        }
+        let ready = std::task::ready!(Pin::new(&mut this.body).poll_frame(cx));
+        match &ready {
+            Some(Ok(frame)) => {
+                if let Some(data) = frame.data_ref() {
+                    this.total += data.remaining() as u64;
+                    (this.inspector)(BodyEvent::Frame(data.remaining()));
+                }
+            }
+            Some(Err(_)) => this.end(false),
+            None => this.end(true),
+        }
+        Poll::Ready(ready)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.ended || self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+/// A `Body` paced to a maximum rate, see `Body::throttled`.
+pub struct Throttled<T, E = Infallible> {
+    body: Body<T, E>,
+    pending: Option<BodyChunk<T>>,
+    rate: f64,
+    burst: usize,
+    tokens: f64,
+    refilled: tokio::time::Instant,
+    sleep: Option<Pin<Box<tokio::time::Sleep>>>,
+}
+
+impl<T, E> Body<T, E> {
+    /// Limits the body to `bytes_per_sec` with a token bucket. Frames are split into pieces of
+    /// about a tenth of a second worth of bytes; pieces of `Bytes` are not copied.
+    pub fn throttled(self, bytes_per_sec: u64) -> Throttled<T, E> {
+        let rate = bytes_per_sec.max(1) as f64;
+        let burst = (bytes_per_sec / 10).max(1) as usize;
+        Throttled {
+            body: self,
+            pending: None,
+            rate,
+            burst,
+            tokens: burst as f64,
+            refilled: tokio::time::Instant::now(),
+            sleep: None,
+        }
+    }
+}
+
+// the pending chunk is never pinned
+impl<T, E> Unpin for Throttled<T, E> {}
+
+impl<T: std::fmt::Debug, E: std::fmt::Debug> std::fmt::Debug for Throttled<T, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Throttled")
+            .field("body", &self.body)
+            .field("rate", &self.rate)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Buf, E> http_body::Body for Throttled<T, E> {
+    type Data = BodyChunk<T>;
+    type Error = E;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        loop {
+            let Some(pending) = this.pending.as_mut() else {
+                match std::task::ready!(Pin::new(&mut this.body).poll_frame(cx)) {
+                    Some(Ok(frame)) => match frame.into_data() {
+                        Ok(data) if data.has_remaining() => this.pending = Some(data),
+                        Ok(_) => {}
+                        Err(frame) => return Poll::Ready(Some(Ok(frame))),
+                    },
+                    ready => return Poll::Ready(ready),
+                }
+                continue;
+            };
+
+            if let Some(sleep) = this.sleep.as_mut() {
+                std::task::ready!(sleep.as_mut().poll(cx));
+                this.sleep = None;
+            }
+
+            let now = tokio::time::Instant::now();
+            let elapsed = now.duration_since(this.refilled).as_secs_f64();
+            this.tokens = (this.tokens + elapsed * this.rate).min(this.burst as f64);
+            this.refilled = now;
+
+            let n = pending.remaining().min(this.burst);
+            if this.tokens < n as f64 {
+                let wait = (n as f64 - this.tokens) / this.rate;
+                this.sleep = Some(Box::pin(tokio::time::sleep(
+                    std::time::Duration::from_secs_f64(wait),
+                )));
+                continue;
+            }
+            this.tokens -= n as f64;
+
+            let chunk = if n == pending.remaining() {
+                this.pending.take().unwrap()
+            } else {
+                BodyChunk::Bytes(pending.copy_to_bytes(n))
+            };
+            return Poll::Ready(Some(Ok(Frame::data(chunk))));
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.pending.is_none() && self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let pending = self.pending.as_ref().map_or(0, |p| p.remaining() as u64);
+        let inner = self.body.size_hint();
+        let mut hint = SizeHint::new();
+        hint.set_lower(inner.lower() + pending);
+        if let Some(upper) = inner.upper() {
+            hint.set_upper(upper + pending);
+        }
+        hint
+    }
+}
+
+/// The data frames of a `Body` as a `Stream`, see `Body::into_data_stream`.
+#[derive(Debug)]
+pub struct DataStream<T, E = Infallible> {
+    body: Body<T, E>,
+}
+
+impl<T, E> Body<T, E> {
+    /// Yields the data of the body, skipping trailers. Chunks keep the payload type, so a
+    /// buffered payload is still handed out without copying.
+    pub fn into_data_stream(self) -> DataStream<T, E> {
+        DataStream { body: self }
+    }
+}
+
+impl<T: Buf, E> Stream for DataStream<T, E> {
+    type Item = Result<BodyChunk<T>, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let body = &mut self.get_mut().body;
+        loop {
+            return match std::task::ready!(http_body::Body::poll_frame(Pin::new(&mut *body), cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => Poll::Ready(Some(Ok(data))),
+                    Err(_) => continue,
+                },
+                Some(Err(err)) => Poll::Ready(Some(Err(err))),
+                None => Poll::Ready(None),
+            };
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if http_body::Body::is_end_stream(&self.body) {
+            (0, Some(0))
+        } else {
+            (0, None)
         }
     }
 }
@@ -0,0 +1,68 @@
+use crate::{CacheControl, Encoding, Service, SharedService};
+use bytes::Buf;
+use http::header::{HeaderName, CONTENT_TYPE};
+use http::HeaderValue;
+
+/// Configures a `Service` in one chain, started with `Service::builder()`.
+#[derive(Debug)]
+pub struct ServiceBuilder<T> {
+    service: Service<T>,
+    body: Option<T>,
+}
+
+impl<T> ServiceBuilder<T>
+where
+    T: Buf + Clone + Send + 'static,
+{
+    pub(crate) fn new(service: Service<T>) -> Self {
+        Self {
+            service,
+            body: None,
+        }
+    }
+
+    pub fn content_type(self, content_type: HeaderValue) -> Self {
+        self.header(CONTENT_TYPE, content_type)
+    }
+
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.service.headers.insert(name, value);
+        self
+    }
+
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.service.set_encoding(encoding);
+        self
+    }
+
+    pub fn cache_control(mut self, cache_control: CacheControl) -> Self {
+        self.service.set_cache_control(cache_control);
+        self
+    }
+
+    /// See `Service::set_security_headers`.
+    pub fn security_headers(mut self, content_security_policy: Option<HeaderValue>) -> Self {
+        self.service.set_security_headers(content_security_policy);
+        self
+    }
+
+    /// Applies any other setting, e.g. `.configure(|s| s.set_max_ranges(1))`.
+    pub fn configure(mut self, f: impl FnOnce(&mut Service<T>)) -> Self {
+        f(&mut self.service);
+        self
+    }
+
+    /// The payload filled in by `build`.
+    pub fn body(mut self, body: T) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    /// The configured service, which can still be refilled but no longer reconfigured.
+    pub fn build(self) -> SharedService<T> {
+        if let Some(body) = self.body {
+            self.service.fill(body);
+        }
+        self.service.into()
+    }
+}
@@ -0,0 +1,165 @@
+use http::HeaderValue;
+use std::time::Duration;
+
+/// Builder for a `Cache-Control` response header.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CacheControl {
+    public: bool,
+    private: bool,
+    no_cache: bool,
+    no_store: bool,
+    no_transform: bool,
+    must_revalidate: bool,
+    immutable: bool,
+    max_age: Option<Duration>,
+    s_maxage: Option<Duration>,
+    stale_while_revalidate: Option<Duration>,
+    stale_if_error: Option<Duration>,
+}
+
+impl CacheControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn public(mut self) -> Self {
+        self.public = true;
+        self
+    }
+
+    pub fn private(mut self) -> Self {
+        self.private = true;
+        self
+    }
+
+    pub fn no_cache(mut self) -> Self {
+        self.no_cache = true;
+        self
+    }
+
+    pub fn no_store(mut self) -> Self {
+        self.no_store = true;
+        self
+    }
+
+    pub fn no_transform(mut self) -> Self {
+        self.no_transform = true;
+        self
+    }
+
+    pub fn must_revalidate(mut self) -> Self {
+        self.must_revalidate = true;
+        self
+    }
+
+    pub fn immutable(mut self) -> Self {
+        self.immutable = true;
+        self
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Overrides max-age for shared caches only.
+    pub fn s_maxage(mut self, s_maxage: Duration) -> Self {
+        self.s_maxage = Some(s_maxage);
+        self
+    }
+
+    /// RFC 5861: caches may serve a stale response for this long while revalidating in the
+    /// background.
+    pub fn stale_while_revalidate(mut self, duration: Duration) -> Self {
+        self.stale_while_revalidate = Some(duration);
+        self
+    }
+
+    /// RFC 5861: caches may serve a stale response for this long when revalidation fails.
+    pub fn stale_if_error(mut self, duration: Duration) -> Self {
+        self.stale_if_error = Some(duration);
+        self
+    }
+
+    pub fn get_max_age(&self) -> Option<Duration> {
+        self.max_age
+    }
+
+    /// Whether a cache has to revalidate before reusing a response.
+    pub fn is_uncacheable(&self) -> bool {
+        self.no_cache || self.no_store
+    }
+
+    pub fn to_header_value(&self) -> HeaderValue {
+        let mut directives = Vec::new();
+        let flags = [
+            (self.public, "public"),
+            (self.private, "private"),
+            (self.no_cache, "no-cache"),
+            (self.no_store, "no-store"),
+            (self.no_transform, "no-transform"),
+            (self.must_revalidate, "must-revalidate"),
+        ];
+        directives.extend(
+            flags
+                .iter()
+                .filter(|(on, _)| *on)
+                .map(|(_, d)| d.to_string()),
+        );
+        if let Some(max_age) = self.max_age {
+            directives.push(format!("max-age={}", max_age.as_secs()));
+        }
+        if let Some(s_maxage) = self.s_maxage {
+            directives.push(format!("s-maxage={}", s_maxage.as_secs()));
+        }
+        if let Some(duration) = self.stale_while_revalidate {
+            directives.push(format!("stale-while-revalidate={}", duration.as_secs()));
+        }
+        if let Some(duration) = self.stale_if_error {
+            directives.push(format!("stale-if-error={}", duration.as_secs()));
+        }
+        if self.immutable {
+            directives.push("immutable".to_string());
+        }
+        HeaderValue::try_from(directives.join(", ")).unwrap()
+    }
+}
+
+impl From<&CacheControl> for HeaderValue {
+    fn from(cache_control: &CacheControl) -> Self {
+        cache_control.to_header_value()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let cc = CacheControl::new()
+            .public()
+            .max_age(Duration::from_secs(60))
+            .immutable();
+        assert_eq!(cc.to_header_value(), "public, max-age=60, immutable");
+
+        let cc = CacheControl::new().no_cache().must_revalidate();
+        assert_eq!(cc.to_header_value(), "no-cache, must-revalidate");
+        assert!(cc.is_uncacheable());
+
+        let cc = CacheControl::new()
+            .max_age(Duration::from_secs(60))
+            .stale_while_revalidate(Duration::from_secs(30))
+            .stale_if_error(Duration::from_secs(86400));
+        assert_eq!(
+            cc.to_header_value(),
+            "max-age=60, stale-while-revalidate=30, stale-if-error=86400"
+        );
+
+        let cc = CacheControl::new()
+            .public()
+            .max_age(Duration::from_secs(60))
+            .s_maxage(Duration::from_secs(3600));
+        assert_eq!(cc.to_header_value(), "public, max-age=60, s-maxage=3600");
+    }
+}
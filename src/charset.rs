@@ -0,0 +1,139 @@
+use bytes::Buf;
+use http::HeaderValue;
+
+/// `content_type` with `charset=utf-8`, replacing any other charset. `None` unless it is
+/// `text/*` or JSON, including `+json` types.
+pub(crate) fn with_utf8(content_type: &HeaderValue) -> Option<HeaderValue> {
+    let content_type = content_type.to_str().ok()?;
+    let mut params = content_type.split(';').map(str::trim);
+    let essence = params.next()?.to_ascii_lowercase();
+    let (kind, subtype) = essence.split_once('/')?;
+    if kind != "text"
+        && !(kind == "application" && (subtype == "json" || subtype.ends_with("+json")))
+    {
+        return None;
+    }
+    let mut value = essence.clone();
+    for param in params.filter(|param| !is_charset(param) && !param.is_empty()) {
+        value.push_str("; ");
+        value.push_str(param);
+    }
+    value.push_str("; charset=utf-8");
+    HeaderValue::try_from(value).ok()
+}
+
+/// Whether `content_type` declares a UTF-8 charset.
+pub(crate) fn declares_utf8(content_type: &HeaderValue) -> bool {
+    let Ok(content_type) = content_type.to_str() else {
+        return false;
+    };
+    content_type.split(';').skip(1).any(|param| {
+        let param = param.trim();
+        is_charset(param) && {
+            let charset = param["charset=".len()..].trim_matches('"');
+            charset.eq_ignore_ascii_case("utf-8") || charset.eq_ignore_ascii_case("utf8")
+        }
+    })
+}
+
+fn is_charset(param: &str) -> bool {
+    param.len() >= "charset=".len() && param[.."charset=".len()].eq_ignore_ascii_case("charset=")
+}
+
+/// Whether `body` is valid UTF-8, even with code points split across its chunks.
+pub(crate) fn is_utf8<T: Buf + Clone>(body: &T) -> bool {
+    let mut body = body.clone();
+    let mut pending = Vec::new();
+    while body.has_remaining() {
+        let chunk = body.chunk();
+        let len = chunk.len();
+        let bytes = if pending.is_empty() {
+            chunk
+        } else {
+            pending.extend_from_slice(chunk);
+            &pending[..]
+        };
+        let rest = match std::str::from_utf8(bytes) {
+            Ok(_) => Vec::new(),
+            // cut short at the end of the chunk
+            Err(err) if err.error_len().is_none() => bytes[err.valid_up_to()..].to_vec(),
+            Err(_) => return false,
+        };
+        pending = rest;
+        body.advance(len);
+    }
+    pending.is_empty()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn test() {
+        let with_utf8 = |content_type: &'static str| {
+            with_utf8(&HeaderValue::from_static(content_type))
+                .map(|v| v.to_str().unwrap().to_owned())
+        };
+        assert_eq!(with_utf8("text/html").unwrap(), "text/html; charset=utf-8");
+        assert_eq!(
+            with_utf8("Text/Plain; Charset=ISO-8859-1; format=flowed").unwrap(),
+            "text/plain; format=flowed; charset=utf-8"
+        );
+        assert_eq!(
+            with_utf8("application/json").unwrap(),
+            "application/json; charset=utf-8"
+        );
+        assert_eq!(
+            with_utf8("application/manifest+json").unwrap(),
+            "application/manifest+json; charset=utf-8"
+        );
+        assert_eq!(with_utf8("image/png"), None);
+        assert_eq!(with_utf8("application/jsonx"), None);
+
+        let declares =
+            |content_type: &'static str| declares_utf8(&HeaderValue::from_static(content_type));
+        assert!(declares("text/html; charset=utf-8"));
+        assert!(declares("text/html;charset=\"UTF-8\""));
+        assert!(!declares("text/html"));
+        assert!(!declares("text/html; charset=iso-8859-1"));
+    }
+
+    #[test]
+    fn is_utf8() {
+        #[derive(Clone)]
+        struct Chunks(Vec<Bytes>);
+
+        impl Buf for Chunks {
+            fn remaining(&self) -> usize {
+                self.0.iter().map(Bytes::len).sum()
+            }
+            fn chunk(&self) -> &[u8] {
+                self.0.first().map_or(&[], |chunk| &chunk[..])
+            }
+            fn advance(&mut self, mut cnt: usize) {
+                while cnt > 0 {
+                    let n = cnt.min(self.0[0].len());
+                    self.0[0].advance(n);
+                    if self.0[0].is_empty() {
+                        self.0.remove(0);
+                    }
+                    cnt -= n;
+                }
+            }
+        }
+
+        assert!(super::is_utf8(&Bytes::from("héllo")));
+        assert!(!super::is_utf8(&Bytes::from_static(b"\xff")));
+        assert!(!super::is_utf8(&Bytes::from_static(b"abc\xc3")));
+
+        // "é" split across chunks
+        let chunks = |chunks: &[&'static [u8]]| {
+            Chunks(chunks.iter().map(|c| Bytes::from_static(c)).collect())
+        };
+        assert!(super::is_utf8(&chunks(&[b"h\xc3", b"\xa9llo"])));
+        assert!(super::is_utf8(&chunks(&[b"\xe2", b"\x82", b"\xac"])));
+        assert!(!super::is_utf8(&chunks(&[b"h\xc3", b"llo"])));
+    }
+}
@@ -0,0 +1,575 @@
+use crate::{CompressionConfig, Encoding, Error};
+use bytes::{Buf, Bytes, BytesMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::{mpsc, OwnedSemaphorePermit};
+use tracing::error;
+
+/// Encodings a stored payload may be re-encoded into, in order of preference.
+pub(crate) const TRANSCODE_TARGETS: [Encoding; 3] =
+    [Encoding::Br, Encoding::Gzip, Encoding::Deflate];
+
+const CHUNK_SIZE: usize = 512;
+
+/// Whether the codec for `encoding` is compiled in.
+pub(crate) fn is_supported(encoding: Encoding) -> bool {
+    match encoding {
+        Encoding::Identity => true,
+        Encoding::Br => cfg!(feature = "br"),
+        Encoding::Gzip => cfg!(feature = "gzip"),
+        Encoding::Deflate => cfg!(feature = "deflate"),
+    }
+}
+
+pub(crate) fn transcode_targets() -> impl Iterator<Item = Encoding> {
+    TRANSCODE_TARGETS
+        .into_iter()
+        .filter(|&encoding| is_supported(encoding))
+}
+
+/// The length of `body` decoded from `encoding`, found by decoding it without keeping the
+/// output. `None` past `max` bytes, or if it does not decode.
+pub(crate) fn decoded_len<T: Buf + Send + 'static>(
+    body: T,
+    encoding: Encoding,
+    config: &CompressionConfig,
+    max: Option<u64>,
+) -> Option<u64> {
+    if !is_supported(encoding) {
+        return None;
+    }
+    let max = max.unwrap_or(u64::MAX);
+    let read = blocking_decoder(body.reader(), encoding, config);
+    let mut read = std::io::Read::take(read, max.saturating_add(1));
+    let len = std::io::copy(&mut read, &mut std::io::sink()).ok()?;
+    (len <= max).then_some(len)
+}
+
+/// The output of a codec task, and a handle to stop it.
+pub(crate) type Spawned = (
+    mpsc::Receiver<Result<Bytes, Error>>,
+    tokio::task::AbortHandle,
+);
+
+/// Caps on what a codec task may produce.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Limits {
+    /// Output of the decoder.
+    pub decoded: Option<u64>,
+    /// Counts the tasks that went over `decoded`.
+    pub decoded_exceeded: Arc<AtomicU64>,
+    /// Output of the task, i.e. the response body.
+    pub response: Option<u64>,
+    /// Time from spawning the task to its end.
+    pub time: Option<Duration>,
+}
+
+/// Decodes `body` into a stream. The whole output is also collected into `cache` once the
+/// decoder reaches the end.
+pub(crate) fn spawn_decoder<T: Buf + Send + 'static>(
+    body: T,
+    encoding: Encoding,
+    config: &CompressionConfig,
+    limits: Limits,
+    cache: Arc<OnceLock<Bytes>>,
+    permit: Option<OwnedSemaphorePermit>,
+    sent: Arc<AtomicU64>,
+) -> Spawned {
+    let exceeded = Exceeded {
+        error: || Error::DecodedSizeExceeded,
+        count: Some(limits.decoded_exceeded),
+    };
+    let read = limit(decoder(body, encoding, config), limits.decoded, exceeded);
+    let exceeded = Exceeded {
+        error: || Error::ResponseSizeExceeded,
+        count: None,
+    };
+    let read = limit(read, limits.response, exceeded);
+    let at = limits.time.map(|time| tokio::time::Instant::now() + time);
+    spawn(deadline(read, at), Some(cache), permit, sent, at)
+}
+
+/// Decodes `body` and re-encodes it with `target` into a stream.
+pub(crate) fn spawn_transcoder<T: Buf + Send + 'static>(
+    body: T,
+    encoding: Encoding,
+    target: Encoding,
+    config: &CompressionConfig,
+    limits: Limits,
+    permit: Option<OwnedSemaphorePermit>,
+    sent: Arc<AtomicU64>,
+) -> Spawned {
+    let exceeded = Exceeded {
+        error: || Error::DecodedSizeExceeded,
+        count: Some(limits.decoded_exceeded),
+    };
+    let read = limit(decoder(body, encoding, config), limits.decoded, exceeded);
+    let read = encoder(read, target, config);
+    let exceeded = Exceeded {
+        error: || Error::ResponseSizeExceeded,
+        count: None,
+    };
+    let read = limit(read, limits.response, exceeded);
+    let at = limits.time.map(|time| tokio::time::Instant::now() + time);
+    spawn(deadline(read, at), None, permit, sent, at)
+}
+
+/// Fails the read with `exceeded` once more than `remaining` bytes have been produced by
+/// `inner`.
+struct Limit {
+    inner: Reader,
+    remaining: u64,
+    exceeded: Exceeded,
+}
+
+/// What a `Limit` does when it is hit: fail with `error`, and bump `count`.
+struct Exceeded {
+    error: fn() -> Error,
+    count: Option<Arc<AtomicU64>>,
+}
+
+impl Limit {
+    fn consume(&mut self, n: usize) -> std::io::Result<()> {
+        match self.remaining.checked_sub(n as u64) {
+            Some(remaining) => {
+                self.remaining = remaining;
+                Ok(())
+            }
+            None => {
+                if let Some(count) = self.exceeded.count.take() {
+                    count.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(std::io::Error::other((self.exceeded.error)()))
+            }
+        }
+    }
+}
+
+fn timed_out() -> std::io::Error {
+    std::io::Error::other(Error::TimedOut)
+}
+
+type Sender = mpsc::Sender<Result<Bytes, Error>>;
+
+/// Channel of a codec task, with room for a chunk and the error that may follow it.
+fn channel() -> (Sender, mpsc::Receiver<Result<Bytes, Error>>) {
+    mpsc::channel(2)
+}
+
+/// Hands `chunk` to the body once there is room for it and an error after it, so that a
+/// client too slow to take it by `at` still gets `Error::TimedOut`. `false` when the task
+/// is to stop.
+async fn send(tx: &Sender, chunk: Bytes, at: Option<tokio::time::Instant>) -> bool {
+    let reserve = tx.reserve_many(2);
+    let permits = match at {
+        Some(at) => match tokio::time::timeout_at(at, reserve).await {
+            Ok(permits) => permits,
+            Err(_) => {
+                error!("codec task timed out waiting for the client");
+                let _ = tx.try_send(Err(Error::TimedOut));
+                return false;
+            }
+        },
+        None => reserve.await,
+    };
+    match permits {
+        Ok(mut permits) => {
+            permits.next().unwrap().send(Ok(chunk));
+            true
+        }
+        // the response was dropped
+        Err(_) => false,
+    }
+}
+
+struct Collector {
+    cache: Option<(Arc<OnceLock<Bytes>>, BytesMut)>,
+}
+
+impl Collector {
+    fn new(cache: Option<Arc<OnceLock<Bytes>>>) -> Self {
+        Self {
+            cache: cache.map(|cache| (cache, BytesMut::new())),
+        }
+    }
+
+    fn push(&mut self, chunk: &[u8]) {
+        if let Some((_, decoded)) = self.cache.as_mut() {
+            decoded.extend_from_slice(chunk);
+        }
+    }
+
+    fn finish(self) {
+        if let Some((cache, decoded)) = self.cache {
+            let _ = cache.set(decoded.freeze());
+        }
+    }
+}
+
+#[cfg(not(feature = "async-compression"))]
+type Reader = Box<dyn std::io::Read + Send>;
+
+#[cfg(not(feature = "async-compression"))]
+fn limit(read: Reader, max: Option<u64>, exceeded: Exceeded) -> Reader {
+    match max {
+        Some(remaining) => Box::new(Limit {
+            inner: read,
+            remaining,
+            exceeded,
+        }),
+        None => read,
+    }
+}
+
+#[cfg(not(feature = "async-compression"))]
+impl std::io::Read for Limit {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.consume(n)?;
+        Ok(n)
+    }
+}
+
+/// Fails reads once `at` has passed. A read in progress is not interrupted.
+#[cfg(not(feature = "async-compression"))]
+struct Deadline {
+    inner: Reader,
+    at: tokio::time::Instant,
+}
+
+#[cfg(not(feature = "async-compression"))]
+fn deadline(read: Reader, at: Option<tokio::time::Instant>) -> Reader {
+    match at {
+        Some(at) => Box::new(Deadline { inner: read, at }),
+        None => read,
+    }
+}
+
+#[cfg(not(feature = "async-compression"))]
+impl std::io::Read for Deadline {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if tokio::time::Instant::now() >= self.at {
+            return Err(timed_out());
+        }
+        self.inner.read(buf)
+    }
+}
+
+#[cfg(not(feature = "async-compression"))]
+fn decoder(
+    body: impl Buf + Send + 'static,
+    encoding: Encoding,
+    config: &CompressionConfig,
+) -> Reader {
+    blocking_decoder(body.reader(), encoding, config)
+}
+
+/// A decoder reading from `read`, for blocking codec tasks and `decoded_len`.
+#[cfg_attr(not(feature = "br"), allow(unused_variables))]
+fn blocking_decoder(
+    read: impl std::io::Read + Send + 'static,
+    encoding: Encoding,
+    config: &CompressionConfig,
+) -> Box<dyn std::io::Read + Send> {
+    match encoding {
+        #[cfg(feature = "br")]
+        Encoding::Br => Box::new(brotli_decompressor::Decompressor::new(
+            read,
+            config.brotli_buffer_size,
+        )),
+        #[cfg(feature = "gzip")]
+        // all members, as `gzip -d` does
+        Encoding::Gzip => Box::new(flate2::read::MultiGzDecoder::new(read)),
+        #[cfg(feature = "deflate")]
+        Encoding::Deflate => Box::new(flate2::read::DeflateDecoder::new(read)),
+        Encoding::Identity => Box::new(read),
+        #[allow(unreachable_patterns)]
+        _ => unreachable!("{encoding} decoder is not compiled in"),
+    }
+}
+
+#[cfg(not(feature = "async-compression"))]
+#[cfg_attr(
+    not(any(feature = "br", feature = "gzip", feature = "deflate")),
+    allow(unused_variables)
+)]
+fn encoder(read: Reader, encoding: Encoding, config: &CompressionConfig) -> Reader {
+    match encoding {
+        #[cfg(feature = "br")]
+        Encoding::Br => Box::new(brotli::CompressorReader::new(
+            read,
+            config.brotli_buffer_size,
+            config.brotli_quality,
+            config.brotli_lgwin,
+        )),
+        #[cfg(feature = "gzip")]
+        Encoding::Gzip => Box::new(flate2::read::GzEncoder::new(
+            read,
+            flate2::Compression::new(config.gzip_level),
+        )),
+        #[cfg(feature = "deflate")]
+        Encoding::Deflate => Box::new(flate2::read::DeflateEncoder::new(
+            read,
+            flate2::Compression::new(config.gzip_level),
+        )),
+        Encoding::Identity => read,
+        #[allow(unreachable_patterns)]
+        _ => unreachable!("{encoding} encoder is not compiled in"),
+    }
+}
+
+#[cfg(not(feature = "async-compression"))]
+/// `permit` is held until the task ends, at the latest `at`; `sent` counts the bytes
+/// handed to the channel.
+fn spawn(
+    mut read: Reader,
+    cache: Option<Arc<OnceLock<Bytes>>>,
+    permit: Option<OwnedSemaphorePermit>,
+    sent: Arc<AtomicU64>,
+    at: Option<tokio::time::Instant>,
+) -> Spawned {
+    let (tx, rx) = channel();
+    let handle = tokio::runtime::Handle::current();
+
+    let task = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        let mut collector = Collector::new(cache);
+        loop {
+            // a running blocking task can't be aborted, so it checks on its own
+            if tx.is_closed() {
+                return;
+            }
+            let mut buf = BytesMut::zeroed(CHUNK_SIZE);
+            let n = match read.read(buf.as_mut()) {
+                Ok(n) => n,
+                Err(err) => {
+                    error!(%err, "decoder task failed");
+                    let _ = tx.blocking_send(Err(err.into()));
+                    return;
+                }
+            };
+            if n == 0 {
+                break;
+            }
+            let chunk = buf.split_to(n).freeze();
+            collector.push(&chunk);
+            if !handle.block_on(send(&tx, chunk, at)) {
+                return;
+            }
+            sent.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        // finished before `tx` is dropped, so the cache is populated by the time the stream ends
+        collector.finish();
+    });
+
+    (rx, task.abort_handle())
+}
+
+#[cfg(feature = "async-compression")]
+type Reader = std::pin::Pin<Box<dyn tokio::io::AsyncRead + Send>>;
+
+#[cfg(feature = "async-compression")]
+fn limit(read: Reader, max: Option<u64>, exceeded: Exceeded) -> Reader {
+    match max {
+        Some(remaining) => Box::pin(Limit {
+            inner: read,
+            remaining,
+            exceeded,
+        }),
+        None => read,
+    }
+}
+
+#[cfg(feature = "async-compression")]
+impl tokio::io::AsyncRead for Limit {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        std::task::ready!(this.inner.as_mut().poll_read(cx, buf))?;
+        std::task::Poll::Ready(this.consume(buf.filled().len() - filled))
+    }
+}
+
+/// Fails a pending or later read once the sleep is over.
+#[cfg(feature = "async-compression")]
+struct Deadline {
+    inner: Reader,
+    sleep: std::pin::Pin<Box<tokio::time::Sleep>>,
+}
+
+#[cfg(feature = "async-compression")]
+fn deadline(read: Reader, at: Option<tokio::time::Instant>) -> Reader {
+    match at {
+        Some(at) => Box::pin(Deadline {
+            inner: read,
+            sleep: Box::pin(tokio::time::sleep_until(at)),
+        }),
+        None => read,
+    }
+}
+
+#[cfg(feature = "async-compression")]
+impl tokio::io::AsyncRead for Deadline {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        if std::future::Future::poll(this.sleep.as_mut(), cx).is_ready() {
+            return std::task::Poll::Ready(Err(timed_out()));
+        }
+        this.inner.as_mut().poll_read(cx, buf)
+    }
+}
+
+#[cfg(feature = "async-compression")]
+fn decoder(
+    body: impl Buf + Send + 'static,
+    encoding: Encoding,
+    _config: &CompressionConfig,
+) -> Reader {
+    let read = BufReader(body);
+    match encoding {
+        #[cfg(feature = "br")]
+        Encoding::Br => Box::pin(async_compression::tokio::bufread::BrotliDecoder::new(read)),
+        #[cfg(feature = "gzip")]
+        Encoding::Gzip => {
+            let mut decoder = async_compression::tokio::bufread::GzipDecoder::new(read);
+            // all members, as `gzip -d` does
+            decoder.multiple_members(true);
+            Box::pin(decoder)
+        }
+        #[cfg(feature = "deflate")]
+        Encoding::Deflate => Box::pin(async_compression::tokio::bufread::DeflateDecoder::new(read)),
+        Encoding::Identity => Box::pin(read),
+        #[allow(unreachable_patterns)]
+        _ => unreachable!("{encoding} decoder is not compiled in"),
+    }
+}
+
+#[cfg(feature = "async-compression")]
+#[cfg_attr(
+    not(any(feature = "br", feature = "gzip", feature = "deflate")),
+    allow(unused_variables)
+)]
+fn encoder(read: Reader, encoding: Encoding, config: &CompressionConfig) -> Reader {
+    let read = tokio::io::BufReader::new(read);
+    match encoding {
+        #[cfg(feature = "br")]
+        Encoding::Br => Box::pin(
+            async_compression::tokio::bufread::BrotliEncoder::with_params(
+                read,
+                async_compression::brotli::EncoderParams::default()
+                    .quality(async_compression::Level::Precise(
+                        config.brotli_quality as i32,
+                    ))
+                    .window_size(config.brotli_lgwin as i32),
+            ),
+        ),
+        #[cfg(feature = "gzip")]
+        Encoding::Gzip => Box::pin(
+            async_compression::tokio::bufread::GzipEncoder::with_quality(
+                read,
+                async_compression::Level::Precise(config.gzip_level as i32),
+            ),
+        ),
+        #[cfg(feature = "deflate")]
+        Encoding::Deflate => Box::pin(
+            async_compression::tokio::bufread::DeflateEncoder::with_quality(
+                read,
+                async_compression::Level::Precise(config.gzip_level as i32),
+            ),
+        ),
+        Encoding::Identity => Box::pin(read),
+        #[allow(unreachable_patterns)]
+        _ => unreachable!("{encoding} encoder is not compiled in"),
+    }
+}
+
+#[cfg(feature = "async-compression")]
+/// `permit` is held until the task ends, at the latest `at`; `sent` counts the bytes
+/// handed to the channel.
+fn spawn(
+    mut read: Reader,
+    cache: Option<Arc<OnceLock<Bytes>>>,
+    permit: Option<OwnedSemaphorePermit>,
+    sent: Arc<AtomicU64>,
+    at: Option<tokio::time::Instant>,
+) -> Spawned {
+    use tokio::io::AsyncReadExt;
+
+    let (tx, rx) = channel();
+
+    let task = tokio::spawn(async move {
+        let _permit = permit;
+        let mut collector = Collector::new(cache);
+        loop {
+            let mut buf = BytesMut::with_capacity(CHUNK_SIZE);
+            let n = match read.read_buf(&mut buf).await {
+                Ok(n) => n,
+                Err(err) => {
+                    error!(%err, "decoder task failed");
+                    let _ = tx.send(Err(err.into())).await;
+                    return;
+                }
+            };
+            if n == 0 {
+                break;
+            }
+            let chunk = buf.freeze();
+            collector.push(&chunk);
+            if !send(&tx, chunk, at).await {
+                return;
+            }
+            sent.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        // finished before `tx` is dropped, so the cache is populated by the time the stream ends
+        collector.finish();
+    });
+
+    (rx, task.abort_handle())
+}
+
+/// Exposes a `Buf` as an `AsyncBufRead` without copying.
+#[cfg(feature = "async-compression")]
+struct BufReader<T>(T);
+
+// the inner `Buf` is never pinned
+#[cfg(feature = "async-compression")]
+impl<T> Unpin for BufReader<T> {}
+
+#[cfg(feature = "async-compression")]
+impl<T: Buf> tokio::io::AsyncRead for BufReader<T> {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        let inner = &mut self.get_mut().0;
+        let chunk = inner.chunk();
+        let n = chunk.len().min(buf.remaining());
+        buf.put_slice(&chunk[..n]);
+        inner.advance(n);
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "async-compression")]
+impl<T: Buf> tokio::io::AsyncBufRead for BufReader<T> {
+    fn poll_fill_buf(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<&[u8]>> {
+        std::task::Poll::Ready(Ok(self.get_mut().0.chunk()))
+    }
+
+    fn consume(self: std::pin::Pin<&mut Self>, amt: usize) {
+        self.get_mut().0.advance(amt);
+    }
+}
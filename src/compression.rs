@@ -0,0 +1,127 @@
+use http::header::CONTENT_TYPE;
+use http::HeaderMap;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionConfig {
+    pub brotli_quality: u32,
+    pub brotli_lgwin: u32,
+    pub brotli_buffer_size: usize,
+    pub gzip_level: u32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            brotli_quality: 5,
+            brotli_lgwin: 22,
+            brotli_buffer_size: 4096,
+            gzip_level: 6,
+        }
+    }
+}
+
+/// Decides whether a payload may be compressed on the fly, based on the response headers.
+#[derive(Clone)]
+pub enum CompressPolicy {
+    Always,
+    Never,
+    ByContentType,
+    Custom(Arc<dyn Fn(&HeaderMap) -> bool + Send + Sync>),
+}
+
+impl std::fmt::Debug for CompressPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Always => f.write_str("Always"),
+            Self::Never => f.write_str("Never"),
+            Self::ByContentType => f.write_str("ByContentType"),
+            Self::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+impl Default for CompressPolicy {
+    fn default() -> Self {
+        Self::by_content_type()
+    }
+}
+
+impl CompressPolicy {
+    /// Skips media types that are already compressed, such as `image/png`, `video/*` or
+    /// `application/zip`.
+    pub fn by_content_type() -> Self {
+        Self::ByContentType
+    }
+
+    pub fn custom(f: impl Fn(&HeaderMap) -> bool + Send + Sync + 'static) -> Self {
+        Self::Custom(Arc::new(f))
+    }
+
+    pub fn should_compress(&self, headers: &HeaderMap) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::ByContentType => headers
+                .get(CONTENT_TYPE)
+                .is_none_or(|content_type| is_compressible(content_type.as_bytes())),
+            Self::Custom(f) => f(headers),
+        }
+    }
+}
+
+fn is_compressible(content_type: &[u8]) -> bool {
+    let essence = content_type
+        .split(|&b| b == b';')
+        .next()
+        .unwrap_or_default()
+        .trim_ascii()
+        .to_ascii_lowercase();
+    let (top, sub) = match essence.iter().position(|&b| b == b'/') {
+        Some(i) => (&essence[..i], &essence[i + 1..]),
+        None => (&essence[..], &b""[..]),
+    };
+    match top {
+        b"image" => sub == b"svg+xml" || sub == b"bmp" || sub == b"x-icon",
+        b"video" | b"audio" => false,
+        b"font" => sub != b"woff" && sub != b"woff2",
+        b"application" => !matches!(
+            sub,
+            b"zip"
+                | b"gzip"
+                | b"x-gzip"
+                | b"zstd"
+                | b"x-bzip2"
+                | b"x-xz"
+                | b"x-7z-compressed"
+                | b"x-rar-compressed"
+                | b"vnd.rar"
+                | b"font-woff"
+        ),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use http::HeaderValue;
+
+    #[test]
+    fn by_content_type() {
+        let policy = CompressPolicy::by_content_type();
+        let should_compress = |content_type: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+            policy.should_compress(&headers)
+        };
+        assert!(should_compress("text/html; charset=utf-8"));
+        assert!(should_compress("application/json"));
+        assert!(should_compress("image/svg+xml"));
+        assert!(!should_compress("image/png"));
+        assert!(!should_compress("Video/MP4"));
+        assert!(!should_compress("application/zip"));
+        assert!(!should_compress("font/woff2"));
+        assert!(policy.should_compress(&HeaderMap::new()));
+    }
+}
@@ -0,0 +1,129 @@
+use crate::ETag;
+use http::header::{IF_MATCH, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_UNMODIFIED_SINCE};
+use http::{HeaderMap, HeaderValue};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    Proceed,
+    NotModified,
+    PreconditionFailed,
+}
+
+/// Evaluates the preconditions of a GET or HEAD request against the selected representation,
+/// in the order of RFC 9110 §13.2.2:
+///
+/// 1. `If-Match`, or `If-Unmodified-Since` when `If-Match` is absent
+/// 2. `If-None-Match`, or `If-Modified-Since` when `If-None-Match` is absent
+pub(crate) fn evaluate(
+    headers: &HeaderMap,
+    etag: &ETag,
+    last_modified: Option<SystemTime>,
+) -> Outcome {
+    if let Some(if_match) = headers.get(IF_MATCH) {
+        if !etag.matches_strong(if_match.as_bytes()) {
+            return Outcome::PreconditionFailed;
+        }
+    } else if let (Some(date), Some(last_modified)) = (
+        headers.get(IF_UNMODIFIED_SINCE).and_then(http_date),
+        last_modified,
+    ) {
+        if last_modified > date {
+            return Outcome::PreconditionFailed;
+        }
+    }
+
+    if let Some(if_none_match) = headers.get(IF_NONE_MATCH) {
+        if etag.matches(if_none_match.as_bytes()) {
+            return Outcome::NotModified;
+        }
+    } else if let (Some(date), Some(last_modified)) = (
+        headers.get(IF_MODIFIED_SINCE).and_then(http_date),
+        last_modified,
+    ) {
+        if last_modified <= date {
+            return Outcome::NotModified;
+        }
+    }
+
+    Outcome::Proceed
+}
+
+/// Preconditions of a request for which no representation exists. Only `If-Match` can fail:
+/// it requires a current representation, even for `*`.
+pub(crate) fn evaluate_missing(headers: &HeaderMap) -> Outcome {
+    if headers.contains_key(IF_MATCH) {
+        Outcome::PreconditionFailed
+    } else {
+        Outcome::Proceed
+    }
+}
+
+/// An invalid date makes the header be ignored.
+fn http_date(value: &HeaderValue) -> Option<SystemTime> {
+    value
+        .to_str()
+        .ok()
+        .and_then(|date| httpdate::parse_http_date(date).ok())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn precedence() {
+        let etag = ETag::from_buf(&b"payload"[..]);
+        let last_modified = SystemTime::UNIX_EPOCH + Duration::from_secs(784111777);
+        let before = "Sun, 06 Nov 1994 08:49:36 GMT";
+        let same = "Sun, 06 Nov 1994 08:49:37 GMT";
+
+        let eval = |pairs: &[(http::header::HeaderName, &str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(name, value.parse().unwrap());
+            }
+            evaluate(&headers, &etag, Some(last_modified))
+        };
+        let tag = etag.as_header_value().to_str().unwrap();
+
+        assert_eq!(eval(&[]), Outcome::Proceed);
+
+        // If-Match overrides If-Unmodified-Since
+        assert_eq!(
+            eval(&[(IF_MATCH, tag), (IF_UNMODIFIED_SINCE, before)]),
+            Outcome::Proceed
+        );
+        assert_eq!(
+            eval(&[(IF_MATCH, r#""other""#), (IF_UNMODIFIED_SINCE, same)]),
+            Outcome::PreconditionFailed
+        );
+        assert_eq!(
+            eval(&[(IF_UNMODIFIED_SINCE, before)]),
+            Outcome::PreconditionFailed
+        );
+
+        // If-None-Match overrides If-Modified-Since
+        assert_eq!(
+            eval(&[(IF_NONE_MATCH, r#""other""#), (IF_MODIFIED_SINCE, same)]),
+            Outcome::Proceed
+        );
+        assert_eq!(
+            eval(&[(IF_NONE_MATCH, tag), (IF_MODIFIED_SINCE, before)]),
+            Outcome::NotModified
+        );
+        assert_eq!(eval(&[(IF_MODIFIED_SINCE, same)]), Outcome::NotModified);
+        assert_eq!(eval(&[(IF_MODIFIED_SINCE, before)]), Outcome::Proceed);
+
+        // If-Match is evaluated before If-None-Match
+        assert_eq!(
+            eval(&[(IF_MATCH, r#""other""#), (IF_NONE_MATCH, tag)]),
+            Outcome::PreconditionFailed
+        );
+
+        // invalid dates are ignored
+        assert_eq!(eval(&[(IF_MODIFIED_SINCE, "soon")]), Outcome::Proceed);
+        assert_eq!(eval(&[(IF_UNMODIFIED_SINCE, "soon")]), Outcome::Proceed);
+    }
+}
@@ -0,0 +1,108 @@
+use http::HeaderValue;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Picks the `Content-Type` of a file from its extension, out of a built-in table that
+/// `insert` and the hook set by `set_hook` take precedence over.
+#[derive(Debug, Clone, Default)]
+pub struct ContentTypes {
+    extensions: HashMap<String, HeaderValue>,
+    hook: Option<fn(&Path) -> Option<HeaderValue>>,
+}
+
+impl ContentTypes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maps `extension`, without the dot and in any case, to `content_type`.
+    pub fn insert(&mut self, extension: &str, content_type: HeaderValue) {
+        self.extensions
+            .insert(extension.to_ascii_lowercase(), content_type);
+    }
+
+    /// Consulted first for every file. `None` falls back to the extension.
+    pub fn set_hook(&mut self, hook: Option<fn(&Path) -> Option<HeaderValue>>) {
+        self.hook = hook;
+    }
+
+    pub fn guess(&self, path: &Path) -> Option<HeaderValue> {
+        if let Some(content_type) = self.hook.and_then(|hook| hook(path)) {
+            return Some(content_type);
+        }
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        if let Some(content_type) = self.extensions.get(&extension) {
+            return Some(content_type.clone());
+        }
+        builtin(&extension).map(HeaderValue::from_static)
+    }
+}
+
+fn builtin(extension: &str) -> Option<&'static str> {
+    let content_type = match extension {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "txt" => "text/plain; charset=utf-8",
+        "md" => "text/markdown; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "xml" => "application/xml",
+        "json" | "map" => "application/json",
+        "webmanifest" => "application/manifest+json",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "mp3" => "audio/mpeg",
+        "ogg" => "audio/ogg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => return None,
+    };
+    Some(content_type)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn guess() {
+        let mut content_types = ContentTypes::new();
+        let guess = |content_types: &ContentTypes, path: &str| content_types.guess(Path::new(path));
+
+        assert_eq!(
+            guess(&content_types, "a/b.JS").unwrap(),
+            "text/javascript; charset=utf-8"
+        );
+        assert_eq!(guess(&content_types, "b.woff2").unwrap(), "font/woff2");
+        assert_eq!(guess(&content_types, "README"), None);
+        assert_eq!(guess(&content_types, "b.unknown"), None);
+
+        content_types.insert("JS", HeaderValue::from_static("application/javascript"));
+        assert_eq!(
+            guess(&content_types, "b.js").unwrap(),
+            "application/javascript"
+        );
+
+        content_types.set_hook(Some(|path| {
+            (path.file_name()? == "README").then(|| HeaderValue::from_static("text/plain"))
+        }));
+        assert_eq!(guess(&content_types, "README").unwrap(), "text/plain");
+        assert_eq!(
+            guess(&content_types, "b.js").unwrap(),
+            "application/javascript"
+        );
+    }
+}
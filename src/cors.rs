@@ -0,0 +1,170 @@
+use crate::{Body, Error};
+use bytes::Buf;
+use http::header::{
+    HeaderName, ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+    ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS,
+    ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD, ETAG,
+    ORIGIN, VARY,
+};
+use http::{HeaderMap, HeaderValue, Method, Response, StatusCode};
+use std::time::Duration;
+
+/// The origins a `Cors` lets read responses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AllowOrigin {
+    /// Any origin, as `*`.
+    Any,
+    /// This origin only, e.g. `https://example.com`.
+    Exact(HeaderValue),
+    /// Any of these origins, sent back one at a time.
+    List(Vec<HeaderValue>),
+    /// Whatever origin the request comes from.
+    Reflect,
+}
+
+/// Cross-origin resource sharing for `Service::set_cors` and `Router::with_cors`.
+///
+/// Preflight `OPTIONS` requests are answered with the requested method, if served, and the
+/// requested headers echoed. `ETag` is exposed by default, so that scripts can revalidate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cors {
+    allow_origin: AllowOrigin,
+    expose_headers: Vec<HeaderName>,
+    max_age: Option<Duration>,
+    allow_credentials: bool,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self {
+            allow_origin: AllowOrigin::Any,
+            expose_headers: vec![ETAG],
+            max_age: None,
+            allow_credentials: false,
+        }
+    }
+}
+
+impl Cors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn allow_origin(mut self, allow_origin: AllowOrigin) -> Self {
+        self.allow_origin = allow_origin;
+        self
+    }
+
+    /// The response headers scripts can read besides the CORS-safelisted ones, replacing
+    /// the default `ETag`.
+    pub fn expose_headers(mut self, headers: impl IntoIterator<Item = HeaderName>) -> Self {
+        self.expose_headers = headers.into_iter().collect();
+        self
+    }
+
+    /// How long a browser may cache a preflight response.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Lets requests with credentials through. Needs origins other than `AllowOrigin::Any`,
+    /// as `*` is not accepted with credentials; `Service::set_cors` and `Router::with_cors`
+    /// panic otherwise.
+    pub fn allow_credentials(mut self) -> Self {
+        self.allow_credentials = true;
+        self
+    }
+
+    /// Panics on settings browsers would reject, before any request is served.
+    pub(crate) fn assert_usable(&self) {
+        assert!(
+            !(self.allow_credentials && self.allow_origin == AllowOrigin::Any),
+            "Cors::allow_credentials needs AllowOrigin::Exact, List or Reflect, not Any"
+        );
+    }
+
+    /// The `Access-Control-Allow-Origin` for a request from `origin`, if it is allowed.
+    fn allowed(&self, origin: &HeaderValue) -> Option<HeaderValue> {
+        match &self.allow_origin {
+            AllowOrigin::Any => Some(HeaderValue::from_static("*")),
+            AllowOrigin::Reflect => Some(origin.clone()),
+            AllowOrigin::Exact(allowed) => (allowed == origin).then(|| origin.clone()),
+            AllowOrigin::List(allowed) => allowed.contains(origin).then(|| origin.clone()),
+        }
+    }
+
+    /// Whether responses depend on the request origin.
+    fn varies(&self) -> bool {
+        self.allow_origin != AllowOrigin::Any
+    }
+
+    /// Adds the CORS headers of a response other than a preflight.
+    pub(crate) fn apply(&self, origin: Option<&HeaderValue>, headers: &mut HeaderMap) {
+        if self.varies() {
+            headers.append(VARY, HeaderValue::from_static("origin"));
+        }
+        let Some(allowed) = origin.and_then(|origin| self.allowed(origin)) else {
+            return;
+        };
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allowed);
+        if self.allow_credentials {
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+        if !self.expose_headers.is_empty() {
+            let names: Vec<_> = self.expose_headers.iter().map(HeaderName::as_str).collect();
+            headers.insert(
+                ACCESS_CONTROL_EXPOSE_HEADERS,
+                HeaderValue::try_from(names.join(", ")).unwrap(),
+            );
+        }
+    }
+
+    /// The answer to a preflight request for a resource served with `methods`. Without CORS
+    /// headers when the origin or method is not allowed, which the browser takes as a denial.
+    pub(crate) fn preflight<T: Buf>(
+        &self,
+        headers: &HeaderMap,
+        methods: &[Method],
+    ) -> Response<Body<T, Error>> {
+        let mut res = Response::builder().status(StatusCode::NO_CONTENT);
+        let vary = if self.varies() {
+            "origin, access-control-request-method, access-control-request-headers"
+        } else {
+            "access-control-request-method, access-control-request-headers"
+        };
+        res = res.header(VARY, vary);
+
+        let allowed = headers.get(ORIGIN).and_then(|origin| self.allowed(origin));
+        let method = headers
+            .get(ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|method| Method::from_bytes(method.as_bytes()).ok())
+            .filter(|method| methods.contains(method));
+        let (Some(allowed), Some(method)) = (allowed, method) else {
+            return res.body(Body::Empty).unwrap();
+        };
+        res = res
+            .header(ACCESS_CONTROL_ALLOW_ORIGIN, allowed)
+            .header(ACCESS_CONTROL_ALLOW_METHODS, method.as_str());
+        if let Some(requested) = headers.get(ACCESS_CONTROL_REQUEST_HEADERS) {
+            res = res.header(ACCESS_CONTROL_ALLOW_HEADERS, requested);
+        }
+        if self.allow_credentials {
+            res = res.header(ACCESS_CONTROL_ALLOW_CREDENTIALS, "true");
+        }
+        if let Some(max_age) = self.max_age {
+            res = res.header(ACCESS_CONTROL_MAX_AGE, max_age.as_secs());
+        }
+        res.body(Body::Empty).unwrap()
+    }
+}
+
+/// An `OPTIONS` request a browser sends before a cross-origin one.
+pub(crate) fn is_preflight(method: &Method, headers: &HeaderMap) -> bool {
+    method == Method::OPTIONS
+        && headers.contains_key(ORIGIN)
+        && headers.contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+}
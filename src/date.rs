@@ -0,0 +1,26 @@
+use http::HeaderValue;
+use std::cell::RefCell;
+use std::time::{Duration, SystemTime};
+
+thread_local! {
+    /// The last value formatted on this thread, and the second it is for.
+    static DATE: RefCell<(u64, HeaderValue)> = const {
+        RefCell::new((0, HeaderValue::from_static("")))
+    };
+}
+
+/// The current time as an RFC 9110 `Date`, formatted at most once per second and thread.
+pub(crate) fn now() -> HeaderValue {
+    let now = SystemTime::now();
+    let secs = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs();
+    DATE.with_borrow_mut(|(cached, date)| {
+        if *cached != secs {
+            *date = HeaderValue::try_from(httpdate::fmt_http_date(now)).unwrap();
+            *cached = secs;
+        }
+        date.clone()
+    })
+}
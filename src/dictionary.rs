@@ -0,0 +1,91 @@
+use crate::encoding::codings;
+use bytes::Buf;
+use http::header::InvalidHeaderValue;
+use http::HeaderValue;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DictionaryEncoding {
+    Dcb,
+    Dcz,
+}
+
+impl std::fmt::Display for DictionaryEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl DictionaryEncoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Dcb => "dcb",
+            Self::Dcz => "dcz",
+        }
+    }
+
+    pub fn is_contained_in(&self, target: impl AsRef<[u8]>) -> bool {
+        let name = self.as_str().as_bytes();
+        codings(target.as_ref()).any(|coding| coding.eq_ignore_ascii_case(name))
+    }
+}
+
+impl From<DictionaryEncoding> for HeaderValue {
+    fn from(encoding: DictionaryEncoding) -> Self {
+        HeaderValue::from_static(encoding.as_str())
+    }
+}
+
+/// SHA-256 of a dictionary, in the `:base64:` form clients send in `Available-Dictionary`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DictionaryHash(pub String);
+
+impl DictionaryHash {
+    pub fn from_buf<T: Buf>(mut buf: T) -> Self {
+        let mut ctx = aws_lc_rs::digest::Context::new(&aws_lc_rs::digest::SHA256);
+        while buf.has_remaining() {
+            let chunk = buf.chunk();
+            ctx.update(chunk);
+            buf.advance(chunk.len());
+        }
+        Self(format!(
+            ":{}:",
+            crate::base64::encode(ctx.finish().as_ref())
+        ))
+    }
+
+    pub fn matches(&self, available_dictionary: &[u8]) -> bool {
+        available_dictionary.trim_ascii() == self.0.as_bytes()
+    }
+}
+
+pub(crate) fn use_as_dictionary(match_pattern: &str) -> Result<HeaderValue, InvalidHeaderValue> {
+    let mut value = String::with_capacity(match_pattern.len() + 8);
+    value.push_str("match=\"");
+    for c in match_pattern.chars() {
+        if c == '"' || c == '\\' {
+            value.push('\\');
+        }
+        value.push(c);
+    }
+    value.push('"');
+    HeaderValue::try_from(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let hash = DictionaryHash::from_buf(&b"dictionary"[..]);
+        assert!(hash.matches(hash.0.as_bytes()));
+        assert!(!hash.matches(b":AAAA:"));
+
+        let hv = HeaderValue::from_static("gzip, dcb, br");
+        assert!(DictionaryEncoding::Dcb.is_contained_in(&hv));
+        assert!(!DictionaryEncoding::Dcz.is_contained_in(&hv));
+
+        assert_eq!(use_as_dictionary("/api/*").unwrap(), r#"match="/api/*""#);
+        assert!(use_as_dictionary("/api/\n").is_err());
+    }
+}
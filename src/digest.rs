@@ -0,0 +1,182 @@
+use crate::base64;
+use bytes::Buf;
+use http::HeaderValue;
+use std::sync::OnceLock;
+
+/// A hash algorithm of `Repr-Digest` and `Content-Digest` (RFC 9530).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestAlgorithm {
+    Sha256,
+    Sha512,
+}
+
+impl DigestAlgorithm {
+    pub const ALL: [DigestAlgorithm; 2] = [DigestAlgorithm::Sha256, DigestAlgorithm::Sha512];
+
+    /// The key in the digest fields, e.g. `sha-256`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DigestAlgorithm::Sha256 => "sha-256",
+            DigestAlgorithm::Sha512 => "sha-512",
+        }
+    }
+
+    fn algorithm(&self) -> &'static aws_lc_rs::digest::Algorithm {
+        match self {
+            DigestAlgorithm::Sha256 => &aws_lc_rs::digest::SHA256,
+            DigestAlgorithm::Sha512 => &aws_lc_rs::digest::SHA512,
+        }
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            DigestAlgorithm::Sha256 => 0,
+            DigestAlgorithm::Sha512 => 1,
+        }
+    }
+}
+
+pub(crate) fn digest<T: Buf>(algorithm: DigestAlgorithm, mut buf: T) -> aws_lc_rs::digest::Digest {
+    let mut ctx = aws_lc_rs::digest::Context::new(algorithm.algorithm());
+    while buf.has_remaining() {
+        let chunk = buf.chunk();
+        ctx.update(chunk);
+        buf.advance(chunk.len());
+    }
+    ctx.finish()
+}
+
+/// The algorithms to send a digest field with, given the `Want-*-Digest` field of the request
+/// (RFC 9530 §4): those it asks for, in order of preference, or else the `configured` ones it
+/// does not refuse.
+pub(crate) fn negotiate(
+    configured: &[DigestAlgorithm],
+    want: Option<&HeaderValue>,
+) -> Vec<DigestAlgorithm> {
+    let Some(want) = want.and_then(|want| want.to_str().ok()) else {
+        return configured.to_vec();
+    };
+    let mut preferences = Vec::new();
+    for member in want.split(',') {
+        let member = member.split(';').next().unwrap_or_default();
+        let Some((key, preference)) = member.split_once('=') else {
+            continue;
+        };
+        let key = key.trim();
+        let Some(algorithm) = DigestAlgorithm::ALL
+            .into_iter()
+            .find(|algorithm| key.eq_ignore_ascii_case(algorithm.as_str()))
+        else {
+            continue;
+        };
+        match preference.trim().parse::<u8>() {
+            Ok(preference @ 0..=10) => preferences.push((algorithm, preference)),
+            _ => continue,
+        }
+    }
+    // stable, so ties keep the order of the field
+    preferences.sort_by_key(|&(_, preference)| std::cmp::Reverse(preference));
+    let wanted: Vec<_> = preferences
+        .iter()
+        .filter(|&&(_, preference)| preference > 0)
+        .map(|&(algorithm, _)| algorithm)
+        .collect();
+    if !wanted.is_empty() {
+        return wanted;
+    }
+    configured
+        .iter()
+        .copied()
+        .filter(|algorithm| !preferences.iter().any(|(refused, _)| refused == algorithm))
+        .collect()
+}
+
+/// The digests of one payload, in base64, each computed on first use.
+#[derive(Debug, Default)]
+pub(crate) struct Digests([OnceLock<String>; 2]);
+
+impl Digests {
+    /// Starts with the SHA-256 that was computed for the ETag.
+    pub(crate) fn with_sha256(digest: &aws_lc_rs::digest::Digest) -> Self {
+        let digests = Self::default();
+        let _ = digests.0[DigestAlgorithm::Sha256.index()].set(base64::encode(digest.as_ref()));
+        digests
+    }
+
+    /// The digest of `body` with `algorithm`, in base64.
+    pub(crate) fn get<T: Buf + Clone>(&self, algorithm: DigestAlgorithm, body: &T) -> &str {
+        self.0[algorithm.index()]
+            .get_or_init(|| base64::encode(digest(algorithm, body.clone()).as_ref()))
+    }
+
+    /// The value of a digest field over `body` with `algorithms`, e.g.
+    /// `sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:`.
+    pub(crate) fn header<T: Buf + Clone>(
+        &self,
+        algorithms: &[DigestAlgorithm],
+        body: &T,
+    ) -> Option<HeaderValue> {
+        if algorithms.is_empty() {
+            return None;
+        }
+        let members: Vec<_> = algorithms
+            .iter()
+            .map(|&algorithm| format!("{}=:{}:", algorithm.as_str(), self.get(algorithm, body)))
+            .collect();
+        HeaderValue::try_from(members.join(", ")).ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn test() {
+        // RFC 9530 appendix D
+        let body = Bytes::from_static(b"{\"hello\": \"world\"}");
+        let digests = Digests::default();
+        assert_eq!(
+            digests.header(&[DigestAlgorithm::Sha256], &body).unwrap(),
+            "sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:"
+        );
+        assert_eq!(
+            digests.header(&DigestAlgorithm::ALL, &body).unwrap(),
+            "sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:, \
+             sha-512=:WZDPaVn/7XgHaAy8pmojAkGWoRx2UFChF41A2svX+TaPm+AbwAgBWnrIiYllu7BNNyealdVLvRwEmTHWXvJwew==:"
+        );
+        assert_eq!(digests.header(&[], &body), None);
+
+        // reused as it is
+        let sha256 = digest(DigestAlgorithm::Sha256, &b"other"[..]);
+        let digests = Digests::with_sha256(&sha256);
+        assert_eq!(
+            digests.header(&[DigestAlgorithm::Sha256], &body),
+            Digests::default().header(&[DigestAlgorithm::Sha256], &Bytes::from_static(b"other"))
+        );
+    }
+
+    #[test]
+    fn negotiate() {
+        use DigestAlgorithm::*;
+        let negotiate = |configured: &[DigestAlgorithm], want: &'static str| {
+            super::negotiate(configured, Some(&HeaderValue::from_static(want)))
+        };
+
+        assert_eq!(super::negotiate(&[Sha256], None), [Sha256]);
+        assert_eq!(negotiate(&[Sha256], "sha-512=3"), [Sha512]);
+        assert_eq!(
+            negotiate(&[Sha256], "sha-256=1, sha-512=3"),
+            [Sha512, Sha256]
+        );
+        assert_eq!(
+            negotiate(&[Sha256], "SHA-256=5, sha-512=5"),
+            [Sha256, Sha512]
+        );
+        assert_eq!(negotiate(&[Sha256], "md5=10, sha-512=x"), [Sha256]);
+        assert_eq!(negotiate(&[Sha256, Sha512], "sha-256=0"), [Sha512]);
+        assert_eq!(negotiate(&[Sha256], "sha-256=11"), [Sha256]);
+        assert_eq!(negotiate(&[Sha256], "sha-512=2;p"), [Sha512]);
+    }
+}
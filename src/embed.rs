@@ -0,0 +1,92 @@
+use crate::files::{self, Manifest, MANIFEST};
+use crate::{ContentTypes, Router, Service};
+use bytes::Bytes;
+use http::header::CONTENT_TYPE;
+use http::HeaderMap;
+use include_dir::{Dir, DirEntry, File};
+use std::path::Path;
+use std::time::SystemTime;
+
+impl Router<Bytes> {
+    /// Routes every file embedded with `include_dir!`, the way `StaticDir::open` does for a
+    /// directory on disk. The payloads are not copied; their ETags are computed here, unless
+    /// recorded by `precompress`.
+    pub fn from_include_dir(dir: &'static Dir<'static>) -> Self {
+        Self::from_include_dir_with(dir, &ContentTypes::new(), &files::INDEX_FILES)
+    }
+
+    /// Like `from_include_dir`, with the `Content-Type` of each file picked by
+    /// `content_types` and index files as with `StaticDirBuilder::index_files`.
+    pub fn from_include_dir_with(
+        dir: &'static Dir<'static>,
+        content_types: &ContentTypes,
+        index_files: &[&str],
+    ) -> Self {
+        let mut files = Vec::new();
+        walk(dir, &mut files);
+
+        let manifest = dir
+            .get_file(MANIFEST)
+            .and_then(File::contents_utf8)
+            .map(Manifest::parse)
+            .unwrap_or_default();
+        let exists = |path: &Path| dir.get_file(path).is_some();
+        let mut router = Router::new();
+        for file in files {
+            let path = file.path();
+            if path == Path::new(MANIFEST) || files::is_precompressed(path, exists) {
+                continue;
+            }
+            let route = files::route(dir.path(), path);
+            let service = load(dir, file, content_types, &manifest);
+            if files::is_index(path, index_files, exists) {
+                let index = files::dir_route(&route);
+                router.insert(index, files::index_service(&service, index));
+            }
+            router.insert(route, service);
+        }
+        router
+    }
+}
+
+fn walk(dir: &'static Dir<'static>, files: &mut Vec<&'static File<'static>>) {
+    for entry in dir.entries() {
+        match entry {
+            DirEntry::Dir(dir) => walk(dir, files),
+            DirEntry::File(file) => files.push(file),
+        }
+    }
+}
+
+fn load(
+    dir: &'static Dir<'static>,
+    file: &'static File<'static>,
+    content_types: &ContentTypes,
+    manifest: &Manifest,
+) -> Service<Bytes> {
+    let mut service = Service::new();
+    if let Some(content_type) = content_types.guess(file.path()) {
+        service.headers.insert(CONTENT_TYPE, content_type);
+    }
+
+    let exists = |path: &Path| dir.get_file(path).is_some();
+    let variants = files::precompressed(file.path(), exists)
+        .into_iter()
+        .map(|(path, encoding)| {
+            let body = Bytes::from_static(dir.get_file(&path).unwrap().contents());
+            let etag = manifest.etag(&path, body.len());
+            service.encoding_variant(encoding, body, etag)
+        })
+        .collect();
+    let body = Bytes::from_static(file.contents());
+    let etag = manifest.etag(file.path(), body.len());
+    let modified = file.metadata().map(|metadata| metadata.modified());
+    service.fill_dated(
+        body,
+        etag,
+        HeaderMap::new(),
+        modified.unwrap_or_else(SystemTime::now),
+        variants,
+    );
+    service
+}
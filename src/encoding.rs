@@ -26,15 +26,31 @@ impl Encoding {
         }
     }
 
+    pub fn parse(value: impl AsRef<[u8]>) -> Option<Self> {
+        let value = value.as_ref().trim_ascii();
+        [
+            (&b"identity"[..], Self::Identity),
+            (b"br", Self::Br),
+            (b"gzip", Self::Gzip),
+            (b"x-gzip", Self::Gzip),
+            (b"deflate", Self::Deflate),
+        ]
+        .into_iter()
+        .find_map(|(name, encoding)| value.eq_ignore_ascii_case(name).then_some(encoding))
+    }
+
     pub fn is_contained_in(&self, target: impl AsRef<[u8]>) -> bool {
-        let pat = self.as_bytes();
-        target
-            .as_ref()
-            .windows(pat.len())
-            .any(|window| window == pat)
+        codings(target.as_ref()).any(|coding| Self::parse(coding) == Some(*self))
     }
 }
 
+/// Content-coding tokens of an `Accept-Encoding` style list, with parameters stripped.
+pub(crate) fn codings(list: &[u8]) -> impl Iterator<Item = &[u8]> {
+    list.split(|&b| b == b',')
+        .filter_map(|coding| coding.split(|&b| b == b';').next())
+        .map(|coding| coding.trim_ascii())
+}
+
 impl From<Encoding> for http::HeaderValue {
     fn from(encoding: Encoding) -> Self {
         http::HeaderValue::from_static(encoding.as_str())
@@ -53,5 +69,20 @@ mod test {
         assert!(!Encoding::Identity.is_contained_in(&hv));
         assert!(!Encoding::Deflate.is_contained_in(&hv));
         // assert!(!Encoding::Zstd.is_contained_in(&hv));
+
+        let hv = http::HeaderValue::from_static("x-gzip;q=0.5, deflate");
+        assert!(Encoding::Gzip.is_contained_in(&hv));
+        assert!(Encoding::Deflate.is_contained_in(&hv));
+        assert!(!Encoding::Br.is_contained_in(&hv));
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(Encoding::parse("br"), Some(Encoding::Br));
+        assert_eq!(Encoding::parse(" GZIP "), Some(Encoding::Gzip));
+        assert_eq!(Encoding::parse("x-gzip"), Some(Encoding::Gzip));
+        assert_eq!(Encoding::parse("deflate"), Some(Encoding::Deflate));
+        assert_eq!(Encoding::parse("identity"), Some(Encoding::Identity));
+        assert_eq!(Encoding::parse("compress"), None);
     }
 }
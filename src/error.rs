@@ -0,0 +1,44 @@
+use std::fmt;
+
+/// Failure of a body streamed out of a decoder task.
+#[derive(Debug)]
+pub enum Error {
+    /// The stored payload could not be decoded or re-encoded.
+    Codec(std::io::Error),
+    /// Decoding produced more than the limit set by `Service::set_max_decoded_size`.
+    DecodedSizeExceeded,
+    /// The response body went over the limit set by `Service::set_max_response_size`.
+    ResponseSizeExceeded,
+    /// The codec task ran past the limit set by `Service::set_codec_timeout`.
+    TimedOut,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Codec(err) => write!(f, "codec error: {err}"),
+            Error::DecodedSizeExceeded => f.write_str("decoded size limit exceeded"),
+            Error::ResponseSizeExceeded => f.write_str("response size limit exceeded"),
+            Error::TimedOut => f.write_str("codec task timed out"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Codec(err) => Some(err),
+            Error::DecodedSizeExceeded | Error::ResponseSizeExceeded | Error::TimedOut => None,
+        }
+    }
+}
+
+/// Unwraps an `Error` that travelled through a reader as an `io::Error`.
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        match err.downcast::<Error>() {
+            Ok(err) => err,
+            Err(err) => Error::Codec(err),
+        }
+    }
+}
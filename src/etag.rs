@@ -1,26 +1,35 @@
+use crate::digest::digest;
+use crate::DigestAlgorithm;
 use bytes::Buf;
 use http::HeaderValue;
+use std::time::SystemTime;
 
-#[derive(Debug, Clone)]
-pub(crate) struct ETag(pub HeaderValue);
+const WEAK_PREFIX: &[u8] = b"W/";
+
+/// How `Service::fill` derives the validator of a payload.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ETagMode {
+    /// SHA-256 of the payload.
+    #[default]
+    Content,
+    /// A counter bumped on every fill, prefixed with an id unique to the `Service` instance.
+    /// Avoids hashing, but replicas serving the same payload get different validators.
+    Generation,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ETag(pub(crate) HeaderValue);
 
 impl ETag {
     pub const fn empty() -> Self {
         Self(HeaderValue::from_static(r#""""#))
     }
 
-    pub fn from_buf<T: Buf>(mut buf: T) -> Self {
-        let mut ctx = aws_lc_rs::digest::Context::new(&aws_lc_rs::digest::SHA256);
-        while buf.has_remaining() {
-            let chunk = buf.chunk();
-            ctx.update(chunk);
-            buf.advance(chunk.len());
-        }
-        let digest = ctx.finish();
-        Self::from_digest(digest)
+    pub fn from_buf<T: Buf>(buf: T) -> Self {
+        Self::from_digest(&digest(DigestAlgorithm::Sha256, buf))
     }
 
-    pub fn from_digest(digest: aws_lc_rs::digest::Digest) -> Self {
+    pub(crate) fn from_digest(digest: &aws_lc_rs::digest::Digest) -> Self {
         use std::io::Write;
         const QUOTE: u8 = br#"""#[0];
         let digest = digest.as_ref();
@@ -33,10 +42,292 @@ impl ETag {
         Self(etag.try_into().unwrap())
     }
 
-    pub fn matches(&self, if_none_match_header: &[u8]) -> bool {
+    pub(crate) fn from_generation(instance: u64, generation: u64) -> Self {
+        Self(
+            format!(r#""{instance:016x}-{generation}""#)
+                .try_into()
+                .unwrap(),
+        )
+    }
+
+    /// Appends `-{suffix}` to the opaque tag, e.g. `"abc"` becomes `"abc-br"`.
+    pub(crate) fn with_suffix(&self, suffix: &str) -> Self {
+        let etag = self.0.as_bytes();
+        let (tag, quote) = etag.split_at(etag.len() - 1);
+        let etag = [tag, b"-", suffix.as_bytes(), quote].concat();
+        Self(etag.try_into().unwrap())
+    }
+
+    pub fn as_header_value(&self) -> &HeaderValue {
+        &self.0
+    }
+
+    pub fn is_weak(&self) -> bool {
+        self.0.as_bytes().starts_with(WEAK_PREFIX)
+    }
+
+    /// Marks the validator as weak, i.e. `W/"..."`.
+    pub fn into_weak(self) -> Self {
+        if self.is_weak() {
+            return self;
+        }
+        let mut etag = Vec::with_capacity(self.0.len() + WEAK_PREFIX.len());
+        etag.extend_from_slice(WEAK_PREFIX);
+        etag.extend_from_slice(self.0.as_bytes());
+        Self(etag.try_into().unwrap())
+    }
+
+    /// The quoted opaque tag, without the weakness indicator.
+    fn opaque(&self) -> &[u8] {
         let etag = self.0.as_bytes();
-        if_none_match_header
-            .windows(self.0.len())
-            .any(|window| window == etag)
+        etag.strip_prefix(WEAK_PREFIX).unwrap_or(etag)
+    }
+
+    /// Strong comparison (RFC 9110 §8.8.3.2): both validators are strong and identical.
+    pub fn strong_eq(&self, other: &ETag) -> bool {
+        !self.is_weak() && !other.is_weak() && self.opaque() == other.opaque()
+    }
+
+    /// Weak comparison (RFC 9110 §8.8.3.2): the opaque tags are identical.
+    pub fn weak_eq(&self, other: &ETag) -> bool {
+        self.opaque() == other.opaque()
+    }
+
+    /// Whether the `If-None-Match` header lists this validator, using weak comparison. `*`
+    /// matches any validator.
+    pub fn matches(&self, if_none_match_header: &[u8]) -> bool {
+        if is_any(if_none_match_header) {
+            return true;
+        }
+        let opaque = self.opaque();
+        EntityTags::new(if_none_match_header).any(|(_, tag)| tag == opaque)
+    }
+
+    /// Whether the `If-Match` header lists this validator, using strong comparison. `*`
+    /// matches any validator.
+    pub fn matches_strong(&self, if_match_header: &[u8]) -> bool {
+        if is_any(if_match_header) {
+            return true;
+        }
+        if self.is_weak() {
+            return false;
+        }
+        let opaque = self.opaque();
+        EntityTags::new(if_match_header).any(|(weak, tag)| !weak && tag == opaque)
+    }
+
+    /// Whether an `If-Range` header still refers to this representation (RFC 9110 §13.1.5).
+    /// An entity-tag must match strongly; an HTTP-date must equal `last_modified` exactly.
+    /// A `false` result means the range is to be ignored and the full representation sent.
+    pub fn matches_if_range(
+        &self,
+        if_range_header: &[u8],
+        last_modified: Option<SystemTime>,
+    ) -> bool {
+        let header = if_range_header.trim_ascii();
+        if header.starts_with(b"\"") || header.starts_with(WEAK_PREFIX) {
+            let mut tags = EntityTags::new(header);
+            return match (tags.next(), tags.next()) {
+                (Some((false, tag)), None) => !self.is_weak() && tag == self.opaque(),
+                _ => false,
+            };
+        }
+        let date = std::str::from_utf8(header)
+            .ok()
+            .and_then(|date| httpdate::parse_http_date(date).ok());
+        matches!((date, last_modified), (Some(date), Some(last_modified)) if date == last_modified)
+    }
+}
+
+fn is_any(header: &[u8]) -> bool {
+    header.trim_ascii() == b"*"
+}
+
+/// Parses a comma-separated list of entity-tags into `(weak, quoted opaque tag)` pairs.
+/// Parsing stops at the first malformed element.
+struct EntityTags<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> EntityTags<'a> {
+    fn new(header: &'a [u8]) -> Self {
+        Self { rest: header }
+    }
+}
+
+impl<'a> Iterator for EntityTags<'a> {
+    type Item = (bool, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self
+            .rest
+            .iter()
+            .position(|&b| !matches!(b, b' ' | b'\t' | b','))?;
+        let rest = &self.rest[start..];
+        let (weak, rest) = match rest.strip_prefix(WEAK_PREFIX) {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let end = match rest {
+            [b'"', tail @ ..] => tail.iter().position(|&b| b == b'"')? + 2,
+            _ => {
+                self.rest = &[];
+                return None;
+            }
+        };
+        let (tag, rest) = rest.split_at(end);
+        if !rest.is_empty() && !matches!(rest[0], b' ' | b'\t' | b',') {
+            self.rest = &[];
+            return None;
+        }
+        self.rest = rest;
+        Some((weak, tag))
+    }
+}
+
+impl From<ETag> for HeaderValue {
+    fn from(etag: ETag) -> Self {
+        etag.0
+    }
+}
+
+impl TryFrom<HeaderValue> for ETag {
+    type Error = InvalidETag;
+
+    /// Accepts a single entity-tag such as `"abc"` or `W/"abc"`.
+    fn try_from(value: HeaderValue) -> Result<Self, Self::Error> {
+        let bytes = value.as_bytes();
+        let mut tags = EntityTags::new(bytes);
+        match tags.next() {
+            Some((weak, tag))
+                if tag.len() + usize::from(weak) * WEAK_PREFIX.len() == bytes.len() =>
+            {
+                Ok(Self(value))
+            }
+            _ => Err(InvalidETag),
+        }
+    }
+}
+
+impl std::str::FromStr for ETag {
+    type Err = InvalidETag;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        HeaderValue::from_str(s)
+            .map_err(|_| InvalidETag)
+            .and_then(Self::try_from)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidETag;
+
+impl std::fmt::Display for InvalidETag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("invalid entity-tag")
+    }
+}
+
+impl std::error::Error for InvalidETag {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn weak() {
+        let strong = ETag::from_buf(&b"payload"[..]);
+        let weak = strong.clone().into_weak();
+        assert!(!strong.is_weak());
+        assert!(weak.is_weak());
+        assert!(weak.0.as_bytes().starts_with(br#"W/""#));
+        assert_eq!(weak.clone().into_weak(), weak);
+
+        assert!(strong.strong_eq(&strong));
+        assert!(!strong.strong_eq(&weak));
+        assert!(!weak.strong_eq(&weak));
+        assert!(strong.weak_eq(&weak));
+        assert!(weak.weak_eq(&weak));
+
+        let header = [b"W/", strong.0.as_bytes()].concat();
+        assert!(strong.matches(&header));
+        assert!(weak.matches(strong.0.as_bytes()));
+        assert!(weak.with_suffix("br").is_weak());
+        assert!(!strong.with_suffix("br").weak_eq(&strong));
+        assert!(!strong.matches_strong(&header));
+        assert!(strong.matches_strong(strong.0.as_bytes()));
+        assert!(!weak.matches_strong(strong.0.as_bytes()));
+    }
+
+    #[test]
+    fn list() {
+        let tags = |header: &'static [u8]| EntityTags::new(header).collect::<Vec<_>>();
+        assert_eq!(
+            tags(br#""a", W/"b" ,"c,d""#),
+            [
+                (false, &br#""a""#[..]),
+                (true, &br#""b""#[..]),
+                (false, &br#""c,d""#[..])
+            ]
+        );
+        assert_eq!(tags(br#""a", b, "c""#), [(false, &br#""a""#[..])]);
+        assert_eq!(tags(br#""a"b"#), []);
+        assert_eq!(tags(br#""unterminated"#), []);
+        assert_eq!(tags(b""), []);
+
+        // a tag that is a substring of another one does not match
+        let etag = ETag(HeaderValue::from_static(r#""abc""#));
+        assert!(!etag.matches(br#""xabcx""#));
+        assert!(!etag.matches(br#""abc"def""#));
+        assert!(etag.matches(br#""xabcx", W/"abc""#));
+        assert!(!etag.matches_strong(br#"W/"abc""#));
+        assert!(etag.matches_strong(b"*"));
+    }
+
+    #[test]
+    fn parse() {
+        assert!(r#""abc""#.parse::<ETag>().is_ok());
+        assert!(r#"W/"abc""#.parse::<ETag>().unwrap().is_weak());
+        assert!(r#""""#.parse::<ETag>().is_ok());
+        assert_eq!(
+            ETag(HeaderValue::from_static(r#"W/"abc""#)).with_suffix("gzip"),
+            r#"W/"abc-gzip""#.parse().unwrap()
+        );
+        assert_eq!("abc".parse::<ETag>(), Err(InvalidETag));
+        assert_eq!(r#""a", "b""#.parse::<ETag>(), Err(InvalidETag));
+        assert_eq!(r#" "abc""#.parse::<ETag>(), Err(InvalidETag));
+    }
+
+    #[test]
+    fn any() {
+        let etag = ETag::from_buf(&b"payload"[..]);
+        assert!(etag.matches(b"*"));
+        assert!(etag.matches(b" * "));
+        assert!(ETag::empty().matches(b"*"));
+    }
+
+    #[test]
+    fn if_range() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let etag = ETag::from_buf(&b"payload"[..]);
+        let weak_header = [b"W/", etag.0.as_bytes()].concat();
+        assert!(etag.matches_if_range(etag.0.as_bytes(), None));
+        assert!(!etag.matches_if_range(&weak_header, None));
+        assert!(!etag
+            .clone()
+            .into_weak()
+            .matches_if_range(etag.0.as_bytes(), None));
+        assert!(!etag.matches_if_range(br#""other""#, None));
+        assert!(!etag.matches_if_range(b"*", None));
+        let list = [etag.0.as_bytes(), b", \"other\""].concat();
+        assert!(!etag.matches_if_range(&list, None));
+
+        let last_modified = UNIX_EPOCH + Duration::from_secs(784111777);
+        let date = b"Sun, 06 Nov 1994 08:49:37 GMT";
+        assert!(etag.matches_if_range(date, Some(last_modified)));
+        assert!(!etag.matches_if_range(date, Some(last_modified + Duration::from_secs(1))));
+        assert!(!etag.matches_if_range(date, None));
+        assert!(!etag.matches_if_range(b"yesterday", Some(last_modified)));
     }
 }
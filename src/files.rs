@@ -0,0 +1,120 @@
+#[cfg(feature = "include_dir")]
+use crate::ETag;
+use crate::{Encoding, Service};
+use bytes::Bytes;
+use http::header::CONTENT_LOCATION;
+use http::HeaderValue;
+#[cfg(feature = "include_dir")]
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// File names served at the path of their directory, unless configured otherwise.
+pub(crate) const INDEX_FILES: [&str; 1] = ["index.html"];
+
+/// Whether `path` is the index file of its directory: the first of `index_files` that
+/// `exists` finds there.
+pub(crate) fn is_index<S: AsRef<str>>(
+    path: &Path,
+    index_files: &[S],
+    exists: impl Fn(&Path) -> bool,
+) -> bool {
+    let (Some(name), Some(dir)) = (path.file_name(), path.parent()) else {
+        return false;
+    };
+    let present = index_files
+        .iter()
+        .map(AsRef::as_ref)
+        .find(|index| exists(&dir.join(index)));
+    present.is_some_and(|present| name == present)
+}
+
+/// The service of the directory route `dir`, sharing the payload of its index file's
+/// `service` and pointing `Content-Location` at the directory.
+pub(crate) fn index_service(service: &Service<Bytes>, dir: &str) -> Service<Bytes> {
+    let mut index = Service::new();
+    index.headers = service.headers.clone();
+    if let Ok(location) = HeaderValue::try_from(dir) {
+        index.headers.insert(CONTENT_LOCATION, location);
+    }
+    index.fill_shared(service);
+    index
+}
+
+/// `/sub/` for the route `/sub/index.html`.
+pub(crate) fn dir_route(route: &str) -> &str {
+    route.trim_end_matches(|c| c != '/')
+}
+
+/// Pre-compressed siblings looked for next to each file, in order of preference.
+pub(crate) const PRECOMPRESSED: [(&str, Encoding); 2] =
+    [("br", Encoding::Br), ("gz", Encoding::Gzip)];
+
+/// `/sub/app.js` for `root/sub/app.js`.
+pub(crate) fn route(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let mut route = String::new();
+    for component in relative.components() {
+        route.push('/');
+        route.push_str(&component.as_os_str().to_string_lossy());
+    }
+    route
+}
+
+/// A pre-compressed sibling of another file, served through that one.
+pub(crate) fn is_precompressed(path: &Path, exists: impl Fn(&Path) -> bool) -> bool {
+    PRECOMPRESSED.iter().any(|(extension, _)| {
+        path.extension().is_some_and(|e| e == *extension) && exists(&path.with_extension(""))
+    })
+}
+
+/// The siblings of `path` served as its pre-compressed variants, and their encodings.
+pub(crate) fn precompressed(
+    path: &Path,
+    exists: impl Fn(&Path) -> bool,
+) -> Vec<(PathBuf, Encoding)> {
+    PRECOMPRESSED
+        .iter()
+        .map(|&(extension, encoding)| (sibling(path, extension), encoding))
+        .filter(|(sibling, _)| exists(sibling))
+        .collect()
+}
+
+/// `app.js.br` for `app.js`.
+pub(crate) fn sibling(path: &Path, extension: &str) -> PathBuf {
+    let mut sibling = path.as_os_str().to_owned();
+    sibling.push(".");
+    sibling.push(extension);
+    sibling.into()
+}
+
+/// The manifest `precompress` writes at the root of the directory.
+pub(crate) const MANIFEST: &str = "geta-manifest.txt";
+
+/// ETags recorded by `precompress`, by path relative to the directory. Each line is
+/// `{len} {etag} {path}`. Only trusted for embedded trees, which can't change after the
+/// build; files on disk are hashed on load.
+#[cfg(feature = "include_dir")]
+#[derive(Debug, Default)]
+pub(crate) struct Manifest(HashMap<PathBuf, (usize, ETag)>);
+
+#[cfg(feature = "include_dir")]
+impl Manifest {
+    /// Malformed lines are skipped.
+    pub(crate) fn parse(manifest: &str) -> Self {
+        let entries = manifest.lines().filter_map(|line| {
+            let mut fields = line.splitn(3, ' ');
+            let len = fields.next()?.parse().ok()?;
+            let etag = fields.next()?.parse().ok()?;
+            Some((PathBuf::from(fields.next()?), (len, etag)))
+        });
+        Self(entries.collect())
+    }
+
+    /// The recorded ETag of `path`, unless the file no longer has the recorded length.
+    pub(crate) fn etag(&self, path: &Path, len: usize) -> Option<ETag> {
+        self.0
+            .get(path)
+            .filter(|(recorded, _)| *recorded == len)
+            .map(|(_, etag)| etag.clone())
+    }
+}
@@ -0,0 +1,103 @@
+use http::HeaderValue;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Hex digits of the payload hash put into fingerprinted paths.
+const HASH_LEN: usize = 8;
+
+/// `/app.3f9ac2d1.js` for `/app.js`, with the hash taken from the opaque tag of `etag`.
+pub(crate) fn fingerprint(path: &str, etag: &HeaderValue) -> Option<String> {
+    let tag = etag.to_str().ok()?;
+    let hash: String = tag
+        .strip_prefix("W/")
+        .unwrap_or(tag)
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .take(HASH_LEN)
+        .collect();
+    if hash.is_empty() {
+        return None;
+    }
+    let name_start = path.rfind('/').map_or(0, |i| i + 1);
+    let fingerprinted = match path[name_start..].rfind('.') {
+        Some(0) | None => format!("{path}.{hash}"),
+        Some(dot) => {
+            let (stem, extension) = path.split_at(name_start + dot);
+            format!("{stem}.{hash}{extension}")
+        }
+    };
+    Some(fingerprinted)
+}
+
+/// `{"/app.js":"/app.3f9ac2d1.js"}`, sorted by logical path.
+pub(crate) fn manifest_json(fingerprints: &BTreeMap<String, String>) -> String {
+    let mut json = String::from("{");
+    for (i, (path, fingerprinted)) in fingerprints.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        json.push_str(&json_string(path));
+        json.push(':');
+        json.push_str(&json_string(fingerprinted));
+    }
+    json.push('}');
+    json
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c < ' ' => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fingerprint() {
+        let etag = HeaderValue::from_static(r#""3f9ac2d1e5b7""#);
+        let fingerprint = |path| super::fingerprint(path, &etag);
+
+        assert_eq!(fingerprint("/app.js").unwrap(), "/app.3f9ac2d1.js");
+        assert_eq!(
+            fingerprint("/a.b/app.min.js").unwrap(),
+            "/a.b/app.min.3f9ac2d1.js"
+        );
+        assert_eq!(fingerprint("/LICENSE").unwrap(), "/LICENSE.3f9ac2d1");
+        assert_eq!(fingerprint("/.env").unwrap(), "/.env.3f9ac2d1");
+
+        let weak = HeaderValue::from_static(r#"W/"3f9ac2d1e5b7""#);
+        assert_eq!(
+            super::fingerprint("/app.js", &weak).unwrap(),
+            "/app.3f9ac2d1.js"
+        );
+        assert_eq!(
+            super::fingerprint("/app.js", &HeaderValue::from_static(r#""""#)),
+            None
+        );
+    }
+
+    #[test]
+    fn manifest_json() {
+        let fingerprints = BTreeMap::from([
+            ("/b.css".to_owned(), "/b.1.css".to_owned()),
+            ("/a\"quote\".js".to_owned(), "/a\"quote\".2.js".to_owned()),
+        ]);
+        assert_eq!(
+            super::manifest_json(&fingerprints),
+            r#"{"/a\"quote\".js":"/a\"quote\".2.js","/b.css":"/b.1.css"}"#
+        );
+    }
+}
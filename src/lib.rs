@@ -1,12 +1,87 @@
+mod accept;
+#[cfg(feature = "axum")]
+mod axum;
+mod base64;
 mod body;
+mod builder;
+mod cache_control;
+mod charset;
+mod codec;
+mod compression;
+mod conditional;
+#[cfg(any(feature = "fs", feature = "include_dir"))]
+mod content_types;
+mod cors;
+mod date;
+mod dictionary;
+mod digest;
+#[cfg(feature = "include_dir")]
+mod embed;
 mod encoding;
+mod error;
 mod etag;
+#[cfg(any(feature = "fs", feature = "include_dir"))]
+mod files;
+mod fingerprint;
+#[cfg(feature = "fs")]
+mod listing;
+mod metrics;
+mod middleware;
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "fs")]
+mod precompress;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+mod range;
+mod router;
+#[cfg(feature = "server")]
+mod server;
+mod server_timing;
 mod service;
+mod shared;
+mod signature;
+mod sniff;
+mod span;
+#[cfg(feature = "fs")]
+mod static_dir;
+#[cfg(feature = "tower")]
+mod tower;
+#[cfg(feature = "watch")]
+mod watch;
 
-pub use body::{Body, BodyChunk};
+pub use body::{Body, BodyChunk, BodyEvent, BoxStream, DataStream, Inspected, Throttled};
+pub use builder::ServiceBuilder;
+pub use cache_control::CacheControl;
+pub use compression::{CompressPolicy, CompressionConfig};
+#[cfg(any(feature = "fs", feature = "include_dir"))]
+pub use content_types::ContentTypes;
+pub use cors::{AllowOrigin, Cors};
+pub use dictionary::DictionaryEncoding;
+pub use digest::DigestAlgorithm;
 pub use encoding::Encoding;
-use etag::ETag;
-pub use service::Service;
+pub use error::Error;
+pub use etag::{ETag, ETagMode, InvalidETag};
+#[cfg(feature = "fs")]
+pub use listing::Listing;
+pub use metrics::Metrics;
+pub use middleware::Middleware;
+#[cfg(feature = "mmap")]
+pub use mmap::MmapBuf;
+#[cfg(feature = "fs")]
+pub use precompress::precompress;
+#[cfg(feature = "prometheus")]
+pub use prometheus::MetricsService;
+pub use router::Router;
+#[cfg(feature = "server")]
+pub use server::{serve, serve_listener};
+pub use service::{ResponseInfo, Saturation, Service};
+pub use shared::SharedService;
+pub use signature::{InvalidSigningKey, Signer};
+#[cfg(feature = "fs")]
+pub use static_dir::{StaticDir, StaticDirBuilder};
+#[cfg(feature = "watch")]
+pub use watch::Watch;
 
 #[cfg(test)]
 mod test;
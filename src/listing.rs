@@ -0,0 +1,149 @@
+use crate::fingerprint::json_string;
+use bytes::Bytes;
+use http::HeaderValue;
+use std::fmt::Write;
+
+/// Format of the listings `StaticDir` generates for directories without an index file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Listing {
+    Html,
+    /// An array of `{"name", "size", "etag"}` objects; subdirectories have a trailing `/`
+    /// and neither size nor ETag.
+    Json,
+}
+
+impl Listing {
+    pub(crate) fn content_type(self) -> HeaderValue {
+        HeaderValue::from_static(match self {
+            Self::Html => "text/html; charset=utf-8",
+            Self::Json => "application/json",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Entry {
+    pub name: String,
+    pub size: Option<u64>,
+    pub etag: Option<HeaderValue>,
+}
+
+/// Renders the entries of the directory at `route`, sorted by name.
+pub(crate) fn render(format: Listing, route: &str, mut entries: Vec<Entry>) -> Bytes {
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut out = String::new();
+    match format {
+        Listing::Html => {
+            let route = html(route);
+            out.push_str("<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">");
+            let _ = write!(out, "<title>Index of {route}</title></head>\n<body>");
+            let _ = writeln!(out, "<h1>Index of {route}</h1>\n<table>");
+            out.push_str("<tr><th>Name</th><th>Size</th><th>ETag</th></tr>\n");
+            for entry in &entries {
+                let href = href(&entry.name);
+                let name = html(&entry.name);
+                let size = entry.size.map(|size| size.to_string()).unwrap_or_default();
+                let etag = entry.etag.as_ref().and_then(|etag| etag.to_str().ok());
+                let etag = html(etag.unwrap_or_default());
+                let _ = writeln!(
+                    out,
+                    "<tr><td><a href=\"{href}\">{name}</a></td><td>{size}</td><td>{etag}</td></tr>"
+                );
+            }
+            out.push_str("</table></body></html>\n");
+        }
+        Listing::Json => {
+            out.push('[');
+            for (i, entry) in entries.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                let _ = write!(out, "{{\"name\":{}", json_string(&entry.name));
+                if let Some(size) = entry.size {
+                    let _ = write!(out, ",\"size\":{size}");
+                }
+                if let Some(etag) = entry.etag.as_ref().and_then(|etag| etag.to_str().ok()) {
+                    let _ = write!(out, ",\"etag\":{}", json_string(etag));
+                }
+                out.push('}');
+            }
+            out.push_str("]\n");
+        }
+    }
+    out.into()
+}
+
+/// `name` as a relative URL path, every byte but unreserved ones and `/` percent-encoded,
+/// so that `a#b.txt` or `c:d` is not read as a fragment or a scheme.
+fn href(name: &str) -> String {
+    let mut href = String::with_capacity(name.len());
+    for &b in name.as_bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                href.push(b as char)
+            }
+            b => {
+                let _ = write!(href, "%{b:02X}");
+            }
+        }
+    }
+    href
+}
+
+fn html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let entries = || {
+            vec![
+                Entry {
+                    name: "sub/".to_owned(),
+                    size: None,
+                    etag: None,
+                },
+                Entry {
+                    name: "a<b>.txt".to_owned(),
+                    size: Some(3),
+                    etag: Some(HeaderValue::from_static(r#""abc""#)),
+                },
+            ]
+        };
+
+        assert_eq!(
+            super::render(Listing::Json, "/", entries()),
+            r#"[{"name":"a<b>.txt","size":3,"etag":"\"abc\""},{"name":"sub/"}]"#.to_owned() + "\n"
+        );
+
+        let html = super::render(Listing::Html, "/", entries());
+        let html = std::str::from_utf8(&html).unwrap();
+        assert!(html.contains("<title>Index of /</title>"));
+        assert!(html.contains(
+            "<tr><td><a href=\"a%3Cb%3E.txt\">a&lt;b&gt;.txt</a></td><td>3</td><td>&quot;abc&quot;</td></tr>"
+        ));
+        assert!(html.contains("<tr><td><a href=\"sub/\">sub/</a></td><td></td><td></td></tr>"));
+    }
+
+    #[test]
+    fn href() {
+        assert_eq!(super::href("a#b.txt"), "a%23b.txt");
+        assert_eq!(super::href("c:d?e f"), "c%3Ad%3Fe%20f");
+        assert_eq!(super::href("caf\u{e9}/"), "caf%C3%A9/");
+    }
+}
@@ -0,0 +1,66 @@
+use crate::Encoding;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+const ENCODINGS: [Encoding; 4] = [
+    Encoding::Identity,
+    Encoding::Br,
+    Encoding::Gzip,
+    Encoding::Deflate,
+];
+
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    pub requests: AtomicU64,
+    pub not_modified: AtomicU64,
+    pub method_not_allowed: AtomicU64,
+    pub codec_tasks: AtomicU64,
+    /// Shared with codec tasks, which bump it when they go over the decoded size limit.
+    pub decoded_size_exceeded: Arc<AtomicU64>,
+    bytes_served: [Arc<AtomicU64>; 4],
+}
+
+impl Counters {
+    pub fn incr(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The counter of bytes served in `encoding`, shared with codec tasks.
+    pub fn bytes_served(&self, encoding: Encoding) -> &Arc<AtomicU64> {
+        let index = ENCODINGS.iter().position(|&e| e == encoding).unwrap();
+        &self.bytes_served[index]
+    }
+
+    pub fn snapshot(&self, payload_size: usize) -> Metrics {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        Metrics {
+            requests: load(&self.requests),
+            not_modified: load(&self.not_modified),
+            method_not_allowed: load(&self.method_not_allowed),
+            codec_tasks: load(&self.codec_tasks),
+            decoded_size_exceeded: load(&self.decoded_size_exceeded),
+            bytes_served: ENCODINGS.map(|encoding| (encoding, load(self.bytes_served(encoding)))),
+            payload_size,
+        }
+    }
+}
+
+/// Counters of a `Service` since it was created, from `Service::metrics`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Metrics {
+    /// Requests answered by `call`.
+    pub requests: u64,
+    pub not_modified: u64,
+    pub method_not_allowed: u64,
+    /// Decoder and transcoder tasks spawned.
+    pub codec_tasks: u64,
+    /// Codec tasks stopped by `Service::set_max_decoded_size`.
+    pub decoded_size_exceeded: u64,
+    /// Body bytes handed to responses, by content-coding. Bodies of known size are counted
+    /// when the response is built, streams as they flow. Dictionary-compressed bodies are
+    /// not counted.
+    pub bytes_served: [(Encoding, u64); 4],
+    /// Size of the stored payload, 0 while empty.
+    pub payload_size: usize,
+}
@@ -0,0 +1,32 @@
+use crate::{Body, Error};
+use http::{request, Response};
+use std::ops::ControlFlow;
+
+/// Runs around `Service::call`, registered with `Service::add_middleware`.
+pub trait Middleware<T>: Send + Sync {
+    /// Runs before the request is answered; `Break` answers it with the given response
+    /// instead, skipping everything else.
+    fn before(&self, req: &request::Parts) -> ControlFlow<Response<Body<T, Error>>> {
+        let _ = req;
+        ControlFlow::Continue(())
+    }
+
+    /// Runs on the response, once it is complete.
+    fn after(&self, res: &mut Response<Body<T, Error>>) {
+        let _ = res;
+    }
+}
+
+pub(crate) struct Middlewares<T>(pub Vec<Box<dyn Middleware<T>>>);
+
+impl<T> Default for Middlewares<T> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<T> std::fmt::Debug for Middlewares<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Middlewares({})", self.0.len())
+    }
+}
@@ -0,0 +1,62 @@
+use bytes::Buf;
+use memmap2::Mmap;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A read-only memory map of a file, served from the page cache instead of being copied
+/// into memory. Clones share the map.
+#[derive(Debug, Clone)]
+pub struct MmapBuf {
+    map: Arc<Mmap>,
+    pos: usize,
+    end: usize,
+}
+
+impl MmapBuf {
+    /// Maps the file at `path`.
+    ///
+    /// # Safety
+    ///
+    /// See `from_file`.
+    pub unsafe fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        // SAFETY: passed on to the caller
+        unsafe { Self::from_file(&File::open(path)?) }
+    }
+
+    /// Maps `file`.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be truncated or written to, by this process or any other, for as
+    /// long as the map or a clone of it is alive: reads of a truncated part are undefined
+    /// behavior, faulting with `SIGBUS` on Unix, and writes show through the map, leaving
+    /// the `ETag` stale. Deleting or renaming the file is fine.
+    pub unsafe fn from_file(file: &File) -> io::Result<Self> {
+        // SAFETY: the map is read-only, and the caller upholds that the file is not
+        // modified while mapped
+        let map = unsafe { Mmap::map(file)? };
+        let end = map.len();
+        Ok(Self {
+            map: Arc::new(map),
+            pos: 0,
+            end,
+        })
+    }
+}
+
+impl Buf for MmapBuf {
+    fn remaining(&self) -> usize {
+        self.end - self.pos
+    }
+
+    fn chunk(&self) -> &[u8] {
+        &self.map[self.pos..self.end]
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        assert!(cnt <= self.remaining(), "advance past the end of the map");
+        self.pos += cnt;
+    }
+}
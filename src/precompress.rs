@@ -0,0 +1,100 @@
+use crate::files::{self, MANIFEST, PRECOMPRESSED};
+use crate::static_dir::walk;
+use crate::{codec, CompressPolicy, CompressionConfig, ContentTypes, ETag, Encoding};
+use http::header::CONTENT_TYPE;
+use http::HeaderMap;
+use std::io;
+#[cfg(any(feature = "br", feature = "gzip"))]
+use std::io::Write;
+use std::path::Path;
+
+/// Writes a pre-compressed sibling (`app.js.br`, `app.js.gz`) of every compressible file
+/// under `root`, so that `StaticDir` and `Router::from_include_dir` serve them without
+/// compressing. Meant for a build script, over a copy of the assets in `OUT_DIR`.
+///
+/// A sibling is only kept when smaller than its file, and the output depends on nothing but
+/// the contents and `config`. The ETags of everything served are recorded in a manifest,
+/// which spares hashing when the tree is embedded; it must be regenerated whenever the files
+/// change. `StaticDir` hashes the files on disk regardless, as they can be edited in place.
+pub fn precompress(root: impl AsRef<Path>, config: &CompressionConfig) -> io::Result<()> {
+    let root = root.as_ref();
+    let mut paths = Vec::new();
+    walk(root, &mut paths, &mut Vec::new())?;
+    paths.retain(|path| *path != root.join(MANIFEST));
+    paths.retain(|path| !files::is_precompressed(path, Path::is_file));
+
+    let content_types = ContentTypes::new();
+    let mut manifest = Vec::new();
+    for path in &paths {
+        let data = std::fs::read(path)?;
+        manifest.push((
+            files::route(root, path),
+            data.len(),
+            ETag::from_buf(&data[..]),
+        ));
+
+        let mut headers = HeaderMap::new();
+        if let Some(content_type) = content_types.guess(path) {
+            headers.insert(CONTENT_TYPE, content_type);
+        }
+        let compressible = CompressPolicy::by_content_type().should_compress(&headers);
+        for (extension, encoding) in PRECOMPRESSED {
+            if !codec::is_supported(encoding) {
+                continue;
+            }
+            let sibling = files::sibling(path, extension);
+            let compressed = compressible
+                .then(|| compress(&data, encoding, config))
+                .transpose()?;
+            match compressed.filter(|compressed| compressed.len() < data.len()) {
+                Some(compressed) => {
+                    std::fs::write(&sibling, &compressed)?;
+                    let etag = ETag::from_buf(&compressed[..]);
+                    manifest.push((files::route(root, &sibling), compressed.len(), etag));
+                }
+                // so that a stale one is not served
+                None if sibling.is_file() => std::fs::remove_file(&sibling)?,
+                None => {}
+            }
+        }
+    }
+
+    manifest.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut out = String::new();
+    for (route, len, etag) in manifest {
+        let etag = etag
+            .as_header_value()
+            .to_str()
+            .unwrap_or_default()
+            .to_owned();
+        out.push_str(&format!("{len} {etag} {}\n", &route[1..]));
+    }
+    std::fs::write(root.join(MANIFEST), out)
+}
+
+#[cfg_attr(not(any(feature = "br", feature = "gzip")), allow(unused_variables))]
+fn compress(data: &[u8], encoding: Encoding, config: &CompressionConfig) -> io::Result<Vec<u8>> {
+    match encoding {
+        #[cfg(feature = "br")]
+        Encoding::Br => {
+            let mut writer = brotli::CompressorWriter::new(
+                Vec::new(),
+                config.brotli_buffer_size,
+                config.brotli_quality,
+                config.brotli_lgwin,
+            );
+            writer.write_all(data)?;
+            Ok(writer.into_inner())
+        }
+        #[cfg(feature = "gzip")]
+        Encoding::Gzip => {
+            let mut writer = flate2::write::GzEncoder::new(
+                Vec::new(),
+                flate2::Compression::new(config.gzip_level),
+            );
+            writer.write_all(data)?;
+            writer.finish()
+        }
+        _ => unreachable!("{encoding} is not a pre-compressed encoding"),
+    }
+}
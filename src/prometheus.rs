@@ -0,0 +1,101 @@
+use crate::{Body, CacheControl, Error, Metrics, Service, SharedService};
+use bytes::{Buf, Bytes};
+use http::header::CONTENT_TYPE;
+use http::{HeaderValue, Request, Response};
+use std::fmt::Write;
+
+impl Metrics {
+    /// Renders the counters in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> Bytes {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(&str, u64)]| {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            for (labels, value) in samples {
+                let _ = writeln!(out, "{name}{labels} {value}");
+            }
+        };
+        metric(
+            "geta_requests_total",
+            "counter",
+            "Requests answered.",
+            &[("", self.requests)],
+        );
+        metric(
+            "geta_not_modified_total",
+            "counter",
+            "Requests answered with 304.",
+            &[("", self.not_modified)],
+        );
+        metric(
+            "geta_method_not_allowed_total",
+            "counter",
+            "Requests answered with 405.",
+            &[("", self.method_not_allowed)],
+        );
+        metric(
+            "geta_codec_tasks_total",
+            "counter",
+            "Decoder and transcoder tasks spawned.",
+            &[("", self.codec_tasks)],
+        );
+        metric(
+            "geta_decoded_size_exceeded_total",
+            "counter",
+            "Codec tasks stopped by the decoded size limit.",
+            &[("", self.decoded_size_exceeded)],
+        );
+        let labels = self
+            .bytes_served
+            .map(|(encoding, _)| format!("{{encoding=\"{encoding}\"}}"));
+        let samples: Vec<_> = labels
+            .iter()
+            .zip(self.bytes_served)
+            .map(|(labels, (_, bytes))| (labels.as_str(), bytes))
+            .collect();
+        metric(
+            "geta_bytes_served_total",
+            "counter",
+            "Body bytes served, by content-coding.",
+            &samples,
+        );
+        metric(
+            "geta_payload_bytes",
+            "gauge",
+            "Size of the stored payload.",
+            &[("", self.payload_size as u64)],
+        );
+        out.into()
+    }
+}
+
+/// Serves the metrics of a service in the Prometheus format, e.g. at `/metrics`. They are
+/// rendered afresh for every request.
+#[derive(Debug)]
+pub struct MetricsService<T> {
+    source: SharedService<T>,
+    service: Service<Bytes>,
+}
+
+impl<T> MetricsService<T>
+where
+    T: Buf + Clone + Send + 'static,
+{
+    pub fn new(source: impl Into<SharedService<T>>) -> Self {
+        let mut service = Service::new();
+        service.headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; version=0.0.4"),
+        );
+        service.set_cache_control(CacheControl::new().no_store());
+        Self {
+            source: source.into(),
+            service,
+        }
+    }
+
+    pub async fn call<B>(&self, req: Request<B>) -> Response<Body<Bytes, Error>> {
+        self.service.fill(self.source.metrics().to_prometheus());
+        self.service.call(req).await
+    }
+}
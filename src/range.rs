@@ -0,0 +1,185 @@
+use crate::body::BodyChunk;
+use bytes::{Buf, Bytes};
+use http::HeaderValue;
+use std::collections::VecDeque;
+use std::ops::Range;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ByteRanges {
+    /// The satisfiable ranges, in request order. Never empty.
+    Satisfiable(Vec<Range<usize>>),
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header (RFC 9110 §14.1.2) against a representation of `len` bytes.
+/// Unsatisfiable ranges in the list are dropped. `None` means the header is to be ignored:
+/// it is malformed or uses another unit.
+pub(crate) fn parse(header: &[u8], len: usize) -> Option<ByteRanges> {
+    let header = std::str::from_utf8(header).ok()?.trim();
+    let (unit, specs) = header.split_once('=')?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+    let mut ranges = Vec::new();
+    for spec in specs.split(',') {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            continue;
+        }
+        if let Some(range) = parse_spec(spec, len)? {
+            ranges.push(range);
+        }
+    }
+    if ranges.is_empty() {
+        return Some(ByteRanges::Unsatisfiable);
+    }
+    Some(ByteRanges::Satisfiable(ranges))
+}
+
+/// Sorts the ranges and merges the ones that overlap or touch.
+pub(crate) fn coalesce(mut ranges: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    ranges.sort_by_key(|range| range.start);
+    let mut coalesced: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match coalesced.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => coalesced.push(range),
+        }
+    }
+    coalesced
+}
+
+/// `None` if malformed, `Some(None)` if unsatisfiable.
+fn parse_spec(spec: &str, len: usize) -> Option<Option<Range<usize>>> {
+    let (first, last) = spec.split_once('-')?;
+    let digits = |s: &str| -> Option<usize> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        // positions beyond usize can't be satisfied anyway
+        Some(s.parse().unwrap_or(usize::MAX))
+    };
+
+    let range = match (digits(first), last) {
+        (Some(first), "") => first..len,
+        (Some(first), last) => {
+            let last = digits(last)?;
+            if last < first {
+                return None;
+            }
+            first..last.saturating_add(1).min(len)
+        }
+        (None, _) if first.is_empty() => {
+            let suffix = digits(last)?;
+            if suffix == 0 {
+                return Some(None);
+            }
+            len.saturating_sub(suffix)..len
+        }
+        (None, _) => return None,
+    };
+    if range.start >= len {
+        return Some(None);
+    }
+    Some(Some(range))
+}
+
+/// Lays out a `multipart/byteranges` body (RFC 9110 §14.6) over slices of `body`, so the
+/// payload itself is not copied.
+pub(crate) fn multipart<T: Buf + Clone>(
+    body: &T,
+    ranges: &[Range<usize>],
+    content_type: Option<&HeaderValue>,
+    boundary: &str,
+) -> VecDeque<BodyChunk<T>> {
+    let len = body.remaining();
+    let mut chunks = VecDeque::with_capacity(ranges.len() * 3 + 1);
+    for range in ranges {
+        let mut head = format!("--{boundary}\r\n");
+        if let Some(content_type) = content_type.and_then(|v| v.to_str().ok()) {
+            head.push_str(&format!("Content-Type: {content_type}\r\n"));
+        }
+        head.push_str(&format!(
+            "Content-Range: bytes {}-{}/{len}\r\n\r\n",
+            range.start,
+            range.end - 1
+        ));
+        chunks.push_back(BodyChunk::Bytes(head.into()));
+
+        let mut part = body.clone();
+        part.advance(range.start);
+        chunks.push_back(BodyChunk::Slice(part.take(range.len())));
+        chunks.push_back(BodyChunk::Bytes(Bytes::from_static(b"\r\n")));
+    }
+    chunks.push_back(BodyChunk::Bytes(format!("--{boundary}--\r\n").into()));
+    chunks
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test() {
+        use ByteRanges::*;
+        let parse = |header: &str| parse(header.as_bytes(), 1000);
+
+        assert_eq!(parse("bytes=0-499"), Some(Satisfiable(vec![0..500])));
+        assert_eq!(parse("bytes=500-999"), Some(Satisfiable(vec![500..1000])));
+        assert_eq!(parse("bytes=500-5000"), Some(Satisfiable(vec![500..1000])));
+        assert_eq!(parse("bytes=900-"), Some(Satisfiable(vec![900..1000])));
+        assert_eq!(parse("bytes=-100"), Some(Satisfiable(vec![900..1000])));
+        assert_eq!(parse("bytes=-5000"), Some(Satisfiable(vec![0..1000])));
+        assert_eq!(parse("Bytes = 0-0"), Some(Satisfiable(vec![0..1])));
+
+        assert_eq!(parse("bytes=1000-"), Some(Unsatisfiable));
+        assert_eq!(parse("bytes=99999999999999999999999-"), Some(Unsatisfiable));
+        assert_eq!(parse("bytes=-0"), Some(Unsatisfiable));
+        assert_eq!(super::parse(b"bytes=0-", 0), Some(Unsatisfiable));
+        assert_eq!(super::parse(b"bytes=-1", 0), Some(Unsatisfiable));
+
+        assert_eq!(
+            parse("bytes=0-1, 5-6,,-1"),
+            Some(Satisfiable(vec![0..2, 5..7, 999..1000]))
+        );
+        assert_eq!(parse("bytes=2000-3000, 0-1"), Some(Satisfiable(vec![0..2])));
+
+        assert_eq!(parse("bytes=0-1, 5-1"), None);
+        assert_eq!(parse("bytes=-"), None);
+        assert_eq!(parse("bytes=a-b"), None);
+        assert_eq!(parse("bytes=+1-2"), None);
+        assert_eq!(parse("items=0-1"), None);
+        assert_eq!(parse("0-1"), None);
+    }
+
+    #[test]
+    fn coalesce() {
+        assert_eq!(
+            super::coalesce(vec![10..20, 0..5, 5..8, 15..30, 40..41]),
+            [0..8, 10..30, 40..41]
+        );
+        assert_eq!(super::coalesce(vec![0..10, 0..10, 2..3]), [0..10]);
+    }
+
+    #[test]
+    fn multipart() {
+        let body = Bytes::from_static(b"0123456789");
+        let content_type = HeaderValue::from_static("text/plain");
+        let chunks = super::multipart(&body, &[0..2, 8..10], Some(&content_type), "XYZ");
+        let mut joined = Vec::new();
+        for mut chunk in chunks {
+            while chunk.has_remaining() {
+                let n = chunk.chunk().len();
+                joined.extend_from_slice(chunk.chunk());
+                chunk.advance(n);
+            }
+        }
+        assert_eq!(
+            String::from_utf8(joined).unwrap(),
+            "--XYZ\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\r\n\
+             --XYZ\r\nContent-Type: text/plain\r\nContent-Range: bytes 8-9/10\r\n\r\n89\r\n\
+             --XYZ--\r\n"
+        );
+    }
+}
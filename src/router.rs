@@ -0,0 +1,197 @@
+use crate::cors;
+use crate::date;
+use crate::fingerprint::{fingerprint, manifest_json};
+use crate::{Body, Cors, Error, Service};
+use bytes::{Buf, Bytes};
+use http::header::{CONTENT_LOCATION, DATE, ORIGIN};
+use http::{HeaderValue, Request, Response};
+use std::collections::{BTreeMap, HashMap};
+
+/// Services keyed by request path. Unknown paths are answered with 404.
+#[derive(Debug)]
+pub struct Router<T> {
+    prefix: String,
+    routes: HashMap<String, Service<T>>,
+    fallback: Option<String>,
+    fingerprints: BTreeMap<String, String>,
+    cors: Option<Cors>,
+}
+
+impl<T> Default for Router<T> {
+    fn default() -> Self {
+        Self {
+            prefix: String::new(),
+            routes: HashMap::new(),
+            fallback: None,
+            fingerprints: BTreeMap::new(),
+            cors: None,
+        }
+    }
+}
+
+impl<T> Router<T>
+where
+    T: Buf + Clone + Send + 'static,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves the routes under `prefix`, e.g. `/static` behind a reverse proxy. The prefix is
+    /// stripped from request paths before lookup, and prepended to `Content-Location` values
+    /// that are absolute paths.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.trim_end_matches('/').to_owned();
+        self
+    }
+
+    /// Serves the route at `path` for unknown paths, as a single-page app needs for its
+    /// client-side routes. Paths ending in a file extension, e.g. `/missing.js`, still get 404.
+    pub fn with_fallback(mut self, path: &str) -> Self {
+        self.fallback = Some(path.to_owned());
+        self
+    }
+
+    /// Answers cross-origin requests to every route as `cors` allows, 404s included, so the
+    /// services need no `Service::set_cors` of their own. Panics if `cors` allows credentials
+    /// from any origin.
+    pub fn with_cors(mut self, cors: Cors) -> Self {
+        cors.assert_usable();
+        self.cors = Some(cors);
+        self
+    }
+
+    /// Mounts `service` at `path`, returning the service it replaces.
+    pub fn insert(&mut self, path: impl Into<String>, service: Service<T>) -> Option<Service<T>> {
+        self.routes.insert(path.into(), service)
+    }
+
+    /// Mounts `service` at `path` with a hash of its payload added, e.g. `/app.3f9ac2d1.js`
+    /// for `/app.js`, and returns that path. The hash comes from the `ETag`, so it follows the
+    /// content unless `ETagMode::Generation` is set. `Service::immutable_asset` suits these.
+    ///
+    /// `None`, and nothing is mounted, for a service that was never filled.
+    pub fn insert_fingerprinted(&mut self, path: &str, service: Service<T>) -> Option<String> {
+        let fingerprinted = fingerprint(path, &service.etag()?)?;
+        self.routes.insert(fingerprinted.clone(), service);
+        self.fingerprints
+            .insert(path.to_owned(), fingerprinted.clone());
+        Some(fingerprinted)
+    }
+
+    /// The path `insert_fingerprinted` mounted `path` at.
+    pub fn fingerprinted(&self, path: &str) -> Option<&str> {
+        self.fingerprints.get(path).map(String::as_str)
+    }
+
+    /// The paths of `insert_fingerprinted` as a JSON object, e.g.
+    /// `{"/app.js":"/app.3f9ac2d1.js"}`, for templates to look up.
+    pub fn fingerprint_manifest(&self) -> Bytes {
+        manifest_json(&self.fingerprints).into()
+    }
+
+    pub fn remove(&mut self, path: &str) -> Option<Service<T>> {
+        self.routes.remove(path)
+    }
+
+    /// The service mounted at `path`, e.g. to `fill` it with a new payload.
+    pub fn get(&self, path: &str) -> Option<&Service<T>> {
+        self.routes.get(path)
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.routes.keys().map(String::as_str)
+    }
+
+    /// Serves the route of the request path, tried as is and then percent-decoded, e.g.
+    /// `/a%20b.txt` for `/a b.txt`.
+    pub async fn call<B>(&self, req: Request<B>) -> Response<Body<T, Error>> {
+        let service = self.strip_prefix(req.uri().path()).and_then(|path| {
+            self.get(path)
+                .or_else(|| self.get(&percent_decode(path)?))
+                .or_else(|| self.fallback(path))
+        });
+        let preflight = cors::is_preflight(req.method(), req.headers());
+        let origin = req.headers().get(ORIGIN).cloned();
+        let mut res = match (service, &self.cors) {
+            (Some(service), Some(cors)) if preflight => {
+                return cors.preflight(req.headers(), &service.methods());
+            }
+            (Some(service), _) => self.call_service(service, req).await,
+            (None, _) => not_found(),
+        };
+        if let Some(cors) = &self.cors {
+            cors.apply(origin.as_ref(), res.headers_mut());
+        }
+        res
+    }
+
+    async fn call_service<B>(
+        &self,
+        service: &Service<T>,
+        req: Request<B>,
+    ) -> Response<Body<T, Error>> {
+        let mut res = service.call(req).await;
+        if !self.prefix.is_empty() {
+            if let Some(location) = res.headers_mut().get_mut(CONTENT_LOCATION) {
+                if location.as_bytes().starts_with(b"/") {
+                    let mut prefixed = self.prefix.as_bytes().to_vec();
+                    prefixed.extend_from_slice(location.as_bytes());
+                    *location = HeaderValue::from_bytes(&prefixed).unwrap();
+                }
+            }
+        }
+        res
+    }
+
+    fn fallback(&self, path: &str) -> Option<&Service<T>> {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        if name.contains('.') {
+            return None;
+        }
+        self.get(self.fallback.as_deref()?)
+    }
+
+    /// `/static/app.js` is `/app.js` under the prefix `/static`; `/static` itself is `/`.
+    fn strip_prefix<'a>(&self, path: &'a str) -> Option<&'a str> {
+        match path.strip_prefix(self.prefix.as_str())? {
+            "" => Some("/"),
+            rest if rest.starts_with('/') => Some(rest),
+            _ => None,
+        }
+    }
+}
+
+/// `path` with its `%XX` escapes decoded, if it has any and they decode to UTF-8.
+fn percent_decode(path: &str) -> Option<String> {
+    if !path.contains('%') {
+        return None;
+    }
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok());
+        match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(b) if bytes[i] == b'%' => {
+                decoded.push(b);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn not_found<T: Buf>() -> Response<Body<T, Error>> {
+    Response::builder()
+        .status(http::StatusCode::NOT_FOUND)
+        .header(DATE, date::now())
+        .body(Body::from_static(b"Not found"))
+        .unwrap()
+}
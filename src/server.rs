@@ -0,0 +1,61 @@
+use crate::{Body, Error, SharedService};
+use bytes::Buf;
+use http::{Request, Response};
+use hyper_util::rt::TokioIo;
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tracing::{info, warn};
+
+/// Serves `service` over HTTP/1.1 on `addr` until accepting a connection fails.
+pub async fn serve<T>(
+    addr: impl ToSocketAddrs,
+    service: impl Into<SharedService<T>>,
+) -> std::io::Result<()>
+where
+    T: Buf + Clone + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(addr).await?;
+    serve_listener(listener, service).await
+}
+
+/// Like `serve`, on a listener bound by the caller.
+pub async fn serve_listener<T>(
+    listener: TcpListener,
+    service: impl Into<SharedService<T>>,
+) -> std::io::Result<()>
+where
+    T: Buf + Clone + Send + Sync + 'static,
+{
+    let service = service.into();
+    info!(addr = %listener.local_addr()?, "listening");
+    loop {
+        let (stream, remote) = listener.accept().await?;
+        let service = service.clone();
+        tokio::spawn(async move {
+            if let Err(err) = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                warn!(%remote, %err, "connection failed");
+            }
+        });
+    }
+}
+
+/// Lets a `SharedService` be handed to hyper's `serve_connection` as is.
+impl<T, B> hyper::service::Service<Request<B>> for SharedService<T>
+where
+    T: Buf + Clone + Send + Sync + 'static,
+{
+    type Response = Response<Body<T, Error>>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Infallible>> + Send>>;
+
+    fn call(&self, req: Request<B>) -> Self::Future {
+        let service = self.clone();
+        let (parts, _) = req.into_parts();
+        Box::pin(async move { Ok(service.call_parts(&parts).await) })
+    }
+}
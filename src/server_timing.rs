@@ -0,0 +1,29 @@
+use http::HeaderValue;
+use std::time::Duration;
+
+/// A metric of a `Server-Timing` field, e.g. `decode;dur=0.125;desc="cached"`, with the
+/// duration in milliseconds.
+pub(crate) fn metric(name: &str, duration: Duration, desc: Option<&str>) -> HeaderValue {
+    let mut metric = format!("{name};dur={:.3}", duration.as_secs_f64() * 1000.0);
+    if let Some(desc) = desc {
+        metric.push_str(&format!(";desc=\"{desc}\""));
+    }
+    HeaderValue::try_from(metric).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(
+            metric("etag", Duration::from_micros(1500), None),
+            "etag;dur=1.500"
+        );
+        assert_eq!(
+            metric("decode", Duration::from_nanos(125), Some("spawned")),
+            "decode;dur=0.000;desc=\"spawned\""
+        );
+    }
+}
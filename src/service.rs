@@ -1,22 +1,177 @@
-use crate::{Body, ETag, Encoding};
-use bytes::{Buf, Bytes, BytesMut};
-use http::header::{ACCEPT_ENCODING, CONTENT_ENCODING, ETAG, IF_NONE_MATCH};
-use http::{HeaderMap, HeaderValue, Method, Request, Response};
-use std::sync::RwLock;
-use tokio::sync::mpsc;
-use tracing::{info, warn};
+use crate::accept;
+use crate::charset;
+use crate::codec;
+use crate::conditional::{self, Outcome};
+use crate::cors;
+use crate::date;
+use crate::dictionary::{self, DictionaryHash};
+use crate::digest::{self, Digests};
+use crate::metrics::Counters;
+use crate::middleware::Middlewares;
+use crate::range::{self, ByteRanges};
+use crate::server_timing;
+use crate::signature::{Signature, Signer};
+use crate::sniff::sniff;
+use crate::span;
+use crate::{
+    Body, CacheControl, CompressPolicy, CompressionConfig, Cors, DictionaryEncoding,
+    DigestAlgorithm, ETag, ETagMode, Encoding, Error, Metrics, Middleware, ServiceBuilder,
+};
+use arc_swap::ArcSwap;
+use bytes::{Buf, Bytes};
+use http::header::{
+    HeaderName, InvalidHeaderValue, ACCEPT, ACCEPT_ENCODING, ACCEPT_RANGES, AUTHORIZATION,
+    CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_LOCATION, CONTENT_RANGE,
+    CONTENT_SECURITY_POLICY, CONTENT_TYPE, DATE, ETAG, EXPIRES, IF_RANGE, LAST_MODIFIED, LINK,
+    LOCATION, ORIGIN, RANGE, REFERRER_POLICY, VARY, X_CONTENT_TYPE_OPTIONS,
+};
+use http::{request, response, HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{watch, OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, error, info, warn, Instrument};
+
+const AVAILABLE_DICTIONARY: HeaderName = HeaderName::from_static("available-dictionary");
+const USE_AS_DICTIONARY: HeaderName = HeaderName::from_static("use-as-dictionary");
+const CDN_CACHE_CONTROL: HeaderName = HeaderName::from_static("cdn-cache-control");
+const SURROGATE_CONTROL: HeaderName = HeaderName::from_static("surrogate-control");
+const REPR_DIGEST: HeaderName = HeaderName::from_static("repr-digest");
+const CONTENT_DIGEST: HeaderName = HeaderName::from_static("content-digest");
+const WANT_REPR_DIGEST: HeaderName = HeaderName::from_static("want-repr-digest");
+const WANT_CONTENT_DIGEST: HeaderName = HeaderName::from_static("want-content-digest");
+const SERVER_TIMING: HeaderName = HeaderName::from_static("server-timing");
+const CROSS_ORIGIN_RESOURCE_POLICY: HeaderName =
+    HeaderName::from_static("cross-origin-resource-policy");
 
 #[derive(Debug)]
 pub struct Service<T> {
     pub headers: HeaderMap,
     encoding: Encoding,
-    payload: RwLock<Payload<T>>,
+    cache_control: Option<CacheControl>,
+    expires: bool,
+    compression: CompressionConfig,
+    compress_policy: CompressPolicy,
+    max_decoded_size: Option<u64>,
+    max_response_size: Option<u64>,
+    codec_timeout: Option<Duration>,
+    weak_etag: bool,
+    etag_mode: ETagMode,
+    etag_suffix: bool,
+    vary_accept_encoding: bool,
+    strict_negotiation: bool,
+    sniff_content_type: bool,
+    validate_utf8: bool,
+    repr_digest: Vec<DigestAlgorithm>,
+    signer: Option<Signer>,
+    accept_ranges: bool,
+    max_ranges: usize,
+    frame_size: Option<usize>,
+    generation_header: Option<HeaderName>,
+    request_id_header: Option<HeaderName>,
+    server_timing: bool,
+    on_empty: (StatusCode, Bytes),
+    write_token: Option<String>,
+    max_write_size: usize,
+    header_hook: Option<fn(&request::Parts, &mut HeaderMap)>,
+    cors: Option<Cors>,
+    observer: Option<Observer<T>>,
+    codec_tasks: Option<(Arc<Semaphore>, Saturation)>,
+    counters: Counters,
+    status_headers: HashMap<StatusCode, HeaderMap>,
+    middlewares: Middlewares<T>,
+    instance: u64,
+    generation: AtomicU64,
+    payload: ArcSwap<Payload<T>>,
+    changes: watch::Sender<Option<ETag>>,
 }
 
-#[derive(Debug)]
+type ObserverFn<T> = dyn Fn(&request::Parts, &Response<Body<T, Error>>) + Send + Sync;
+
+struct Observer<T>(Box<ObserverFn<T>>);
+
+impl<T> std::fmt::Debug for Observer<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Observer")
+    }
+}
+
+// always behind an `Arc`
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone)]
 enum Payload<T> {
     Empty,
-    Filled { etag: ETag, body: T },
+    /// Answered with `status` and `Location`, see `redirect`.
+    Redirect {
+        status: StatusCode,
+        location: HeaderValue,
+    },
+    Filled {
+        etag: ETag,
+        body: T,
+        generation: u64,
+        dictionary_variants: Vec<DictionaryVariant<T>>,
+        encoding_variants: Vec<EncodingVariant<T>>,
+        digests: Arc<Digests>,
+        decoded: Arc<OnceLock<Bytes>>,
+        /// The length of `body` decoded, recorded at fill.
+        decoded_len: Option<u64>,
+        last_modified: Option<SystemTime>,
+        filled_at: Instant,
+        /// Overrides `Service::headers` of the same name.
+        headers: HeaderMap,
+        signature: Option<Signature>,
+    },
+}
+
+/// What a fill stores, before the payload is built from it.
+struct Fill<T> {
+    body: T,
+    etag: ETag,
+    digests: Digests,
+    generation: u64,
+    /// Overrides `Service::headers` of the same name.
+    headers: HeaderMap,
+    last_modified: SystemTime,
+    encoding_variants: Vec<EncodingVariant<T>>,
+}
+
+struct Selected<T> {
+    headers: HeaderMap,
+    etag: ETag,
+    body: T,
+    generation: u64,
+    dictionary_encoding: Option<DictionaryEncoding>,
+    has_dictionary_variants: bool,
+    /// Encoding of the pre-compressed variant selected in place of the payload.
+    precompressed: Option<Encoding>,
+    /// Encodings of the pre-compressed variants of the payload.
+    variant_encodings: Vec<Encoding>,
+    /// Of the selected body, unless it is a dictionary variant.
+    digests: Option<Arc<Digests>>,
+    decoded: Arc<OnceLock<Bytes>>,
+    decoded_len: Option<u64>,
+    last_modified: Option<SystemTime>,
+    /// Of the payload, unless a variant is selected.
+    signature: Option<Signature>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct EncodingVariant<T> {
+    pub encoding: Encoding,
+    pub etag: ETag,
+    pub body: T,
+    pub digests: Arc<Digests>,
+}
+
+#[derive(Debug, Clone)]
+struct DictionaryVariant<T> {
+    dictionary: DictionaryHash,
+    encoding: DictionaryEncoding,
+    etag: ETag,
+    body: T,
 }
 
 impl<T> Default for Service<T> {
@@ -24,7 +179,42 @@ impl<T> Default for Service<T> {
         Self {
             headers: HeaderMap::new(),
             encoding: Encoding::Identity,
-            payload: RwLock::new(Payload::Empty),
+            cache_control: None,
+            expires: false,
+            compression: CompressionConfig::default(),
+            compress_policy: CompressPolicy::default(),
+            max_decoded_size: None,
+            max_response_size: None,
+            codec_timeout: None,
+            weak_etag: false,
+            etag_mode: ETagMode::default(),
+            etag_suffix: true,
+            vary_accept_encoding: false,
+            strict_negotiation: false,
+            sniff_content_type: true,
+            validate_utf8: false,
+            repr_digest: Vec::new(),
+            signer: None,
+            accept_ranges: true,
+            max_ranges: 16,
+            frame_size: None,
+            generation_header: None,
+            request_id_header: None,
+            server_timing: false,
+            on_empty: (StatusCode::NO_CONTENT, Bytes::new()),
+            write_token: None,
+            max_write_size: 16 << 20,
+            header_hook: None,
+            cors: None,
+            observer: None,
+            codec_tasks: None,
+            counters: Counters::default(),
+            status_headers: HashMap::new(),
+            middlewares: Middlewares::default(),
+            instance: random_u64(),
+            generation: AtomicU64::new(0),
+            payload: ArcSwap::from_pointee(Payload::Empty),
+            changes: watch::Sender::new(None),
         }
     }
 }
@@ -37,6 +227,44 @@ where
         Self::default()
     }
 
+    pub fn builder() -> ServiceBuilder<T> {
+        ServiceBuilder::new(Self::new())
+    }
+
+    /// Preset for fingerprinted assets whose content never changes under the same URL:
+    /// cached for a year as `immutable`, with a strong ETag and `Vary: accept-encoding`.
+    pub fn immutable_asset() -> Self {
+        let mut service = Self::new();
+        service.set_cache_control(
+            CacheControl::new()
+                .public()
+                .max_age(Duration::from_secs(31536000))
+                .immutable(),
+        );
+        service.vary_accept_encoding = true;
+        service
+    }
+
+    /// Adds to `headers` the usual protections for content loaded by browsers, e.g. an app
+    /// shell: `X-Content-Type-Options: nosniff`, `Referrer-Policy:
+    /// strict-origin-when-cross-origin`, `Cross-Origin-Resource-Policy: same-origin`, and
+    /// `content_security_policy` as `Content-Security-Policy`.
+    pub fn set_security_headers(&mut self, content_security_policy: Option<HeaderValue>) {
+        let headers = [
+            (X_CONTENT_TYPE_OPTIONS, "nosniff"),
+            (REFERRER_POLICY, "strict-origin-when-cross-origin"),
+            (CROSS_ORIGIN_RESOURCE_POLICY, "same-origin"),
+        ];
+        for (name, value) in headers {
+            self.headers.insert(name, HeaderValue::from_static(value));
+        }
+        if let Some(policy) = content_security_policy {
+            self.headers.insert(CONTENT_SECURITY_POLICY, policy);
+        }
+    }
+
+    /// The content-coding payloads are filled with. Each fill is then decoded once to record
+    /// its decoded length, sent as the `Content-Length` of decoded responses.
     pub fn set_encoding(&mut self, encoding: Encoding) {
         self.encoding = encoding;
         self.headers.insert(
@@ -45,128 +273,1657 @@ where
         );
     }
 
-    pub fn fill(&self, body: T) {
-        let etag = if body.has_remaining() {
-            ETag::from_buf(body.clone())
+    pub fn set_cache_control(&mut self, cache_control: CacheControl) {
+        self.headers
+            .insert(CACHE_CONTROL, cache_control.to_header_value());
+        self.cache_control = Some(cache_control);
+    }
+
+    /// Caching rules for edge caches, sent as both `CDN-Cache-Control` (RFC 9213) and
+    /// `Surrogate-Control`. Browsers ignore these and keep following `Cache-Control`.
+    pub fn set_cdn_cache_control(&mut self, cache_control: CacheControl) {
+        let value = cache_control.to_header_value();
+        self.headers.insert(CDN_CACHE_CONTROL, value.clone());
+        self.headers.insert(SURROGATE_CONTROL, value);
+    }
+
+    /// Emits an `Expires` header derived from the `Cache-Control` max-age, for HTTP/1.0
+    /// caches.
+    pub fn set_expires(&mut self, expires: bool) {
+        self.expires = expires;
+    }
+
+    fn expires(&self) -> Option<HeaderValue> {
+        if !self.expires {
+            return None;
+        }
+        let cache_control = self.cache_control.as_ref()?;
+        let expires = if cache_control.is_uncacheable() {
+            SystemTime::UNIX_EPOCH
         } else {
-            ETag::empty()
+            SystemTime::now() + cache_control.get_max_age()?
+        };
+        Some(httpdate::fmt_http_date(expires).try_into().unwrap())
+    }
+
+    pub fn set_compression(&mut self, compression: CompressionConfig) {
+        self.compression = compression;
+    }
+
+    /// Decides whether a payload the client can't take in the stored encoding is re-encoded
+    /// into another one, rather than decoded to identity.
+    pub fn set_compress_policy(&mut self, compress_policy: CompressPolicy) {
+        self.compress_policy = compress_policy;
+    }
+
+    /// Answers 406 when the request `Accept` takes no `Content-Type` of the payload, or
+    /// `Accept-Encoding` neither lists a content-coding it can be served in nor lets
+    /// `identity` through. The body is JSON listing what is available, e.g.
+    /// `{"content-type":"text/html","content-encoding":["br","identity"]}`, so that clients
+    /// can retry. Disabled by default, answering anyway as with no preference.
+    pub fn set_strict_negotiation(&mut self, strict_negotiation: bool) {
+        self.strict_negotiation = strict_negotiation;
+    }
+
+    /// Limits how many bytes a decoder task may produce from the stored payload. A stream that
+    /// goes over the limit is cut short.
+    pub fn set_max_decoded_size(&mut self, max_decoded_size: Option<u64>) {
+        self.max_decoded_size = max_decoded_size;
+    }
+
+    /// Refuses to send bodies over `max_response_size` bytes. A body of known size is
+    /// answered with 500 instead, for HEAD as for GET; a stream only sized as it flows ends
+    /// with `Error::ResponseSizeExceeded` once it crosses the limit.
+    pub fn set_max_response_size(&mut self, max_response_size: Option<u64>) {
+        self.max_response_size = max_response_size;
+    }
+
+    /// Limits how many decoder and transcoder tasks run at once. `saturation` decides what
+    /// happens to a request needing one while `max` are running.
+    pub fn set_max_codec_tasks(&mut self, max: Option<usize>, saturation: Saturation) {
+        self.codec_tasks = max.map(|max| (Arc::new(Semaphore::new(max)), saturation));
+    }
+
+    /// Takes a slot for a codec task, or the `Retry-After` to shed the request with.
+    async fn codec_permit(&self) -> Result<Option<OwnedSemaphorePermit>, Duration> {
+        let Some((semaphore, saturation)) = &self.codec_tasks else {
+            return Ok(None);
+        };
+        match *saturation {
+            Saturation::Queue => Ok(semaphore.clone().acquire_owned().await.ok()),
+            Saturation::Shed { retry_after } => match semaphore.clone().try_acquire_owned() {
+                Ok(permit) => Ok(Some(permit)),
+                Err(_) => {
+                    warn!("codec tasks are saturated, shedding request");
+                    Err(retry_after)
+                }
+            },
+        }
+    }
+
+    /// Ends the body of a decoder or transcoder task with `Error::TimedOut` once the task
+    /// has run for `timeout`, slow clients included.
+    pub fn set_codec_timeout(&mut self, timeout: Option<Duration>) {
+        self.codec_timeout = timeout;
+    }
+
+    fn limits(&self) -> codec::Limits {
+        codec::Limits {
+            decoded: self.max_decoded_size,
+            decoded_exceeded: self.counters.decoded_size_exceeded.clone(),
+            response: self.max_response_size,
+            time: self.codec_timeout,
+        }
+    }
+
+    /// Marks generated ETags as weak validators.
+    pub fn set_weak_etag(&mut self, weak_etag: bool) {
+        self.weak_etag = weak_etag;
+    }
+
+    pub fn set_etag_mode(&mut self, etag_mode: ETagMode) {
+        self.etag_mode = etag_mode;
+    }
+
+    /// Unless `headers` have a `Content-Type`, each identity payload gets one guessed from its
+    /// first bytes when filled, for formats such as HTML, JSON, PNG or wasm. Enabled by
+    /// default.
+    pub fn set_sniff_content_type(&mut self, sniff_content_type: bool) {
+        self.sniff_content_type = sniff_content_type;
+    }
+
+    /// Sets `charset=utf-8` on the `Content-Type` of `headers`, replacing any other charset,
+    /// if it is `text/*` or JSON.
+    pub fn set_utf8_charset(&mut self) {
+        if let Some(content_type) = self.headers.get(CONTENT_TYPE).and_then(charset::with_utf8) {
+            self.headers.insert(CONTENT_TYPE, content_type);
+        }
+    }
+
+    /// Checks at fill time that identity payloads declared as UTF-8 by their `Content-Type`
+    /// are, warning about those that are not. They are still filled. Disabled by default.
+    pub fn set_validate_utf8(&mut self, validate_utf8: bool) {
+        self.validate_utf8 = validate_utf8;
+    }
+
+    /// Sends `Repr-Digest` (RFC 9530) with `algorithms` for representations served as
+    /// stored, and `Content-Digest` as well when they are sent whole. The SHA-256 hashed for
+    /// the ETag is reused; other digests are computed once per payload, on first use. Empty
+    /// by default.
+    ///
+    /// Once enabled, `Want-Repr-Digest` and `Want-Content-Digest` pick the algorithms of
+    /// each request, including supported ones not in `algorithms`.
+    pub fn set_repr_digest(&mut self, algorithms: &[DigestAlgorithm]) {
+        self.repr_digest = algorithms.to_vec();
+    }
+
+    /// Signs the full 200 responses of the stored payload with `signer`, sending the
+    /// SHA-256 `Repr-Digest` it covers along with `Signature-Input` and `Signature`. Each
+    /// fill is signed once, with the headers it is filled with; later changes to `headers`
+    /// take effect on the next fill.
+    ///
+    /// A response whose covered headers were changed since, by the header hook, status
+    /// headers or middleware, is sent without the signature.
+    pub fn set_signer(&mut self, signer: Option<Signer>) {
+        self.signer = signer;
+    }
+
+    /// Serves `Range` requests on identity payloads, advertised with `Accept-Ranges: bytes`.
+    /// Enabled by default. Responses that can't be sliced carry `Accept-Ranges: none`.
+    pub fn set_accept_ranges(&mut self, accept_ranges: bool) {
+        self.accept_ranges = accept_ranges;
+    }
+
+    /// Hands a stored payload to the connection in frames of at most `frame_size` bytes
+    /// instead of a single one. Frames are cheap clones of the payload when it is `Bytes`;
+    /// other buffers are copied one frame at a time. `None` or 0 leaves it as one frame.
+    pub fn set_frame_size(&mut self, frame_size: Option<usize>) {
+        self.frame_size = frame_size.filter(|&n| n > 0);
+    }
+
+    /// Caps the number of ranges served in one response, counted after overlapping and
+    /// adjacent ranges are merged. A request asking for more gets the full body. Defaults
+    /// to 16.
+    pub fn set_max_ranges(&mut self, max_ranges: usize) {
+        self.max_ranges = max_ranges;
+    }
+
+    /// Whether a representation decoded or transcoded from the stored payload gets its own
+    /// validator, suffixed with its content-coding (e.g. `"...-gzip"`). Enabled by default;
+    /// when disabled, all representations share the stored payload's ETag and are told
+    /// apart only by `Vary`.
+    pub fn set_etag_suffix(&mut self, etag_suffix: bool) {
+        self.etag_suffix = etag_suffix;
+    }
+
+    /// Sends the generation of the served payload in a header named `name`, e.g.
+    /// `x-geta-generation`.
+    pub fn set_generation_header(&mut self, name: Option<HeaderName>) {
+        self.generation_header = name;
+    }
+
+    /// Echoes the request header named `name`, e.g. `x-request-id`, on the response, or a
+    /// random one when the request has none, and records it as `request_id` on the `call`
+    /// span, so that requests can be followed across proxies.
+    pub fn set_request_id_header(&mut self, name: Option<HeaderName>) {
+        self.request_id_header = name;
+    }
+
+    /// Sends `Server-Timing` with the time spent selecting the representation (`negotiate`),
+    /// evaluating the preconditions (`etag`) and, when it comes to that, starting the decode
+    /// fallback (`decode`, described as `cached` or `spawned`). The decoding itself runs
+    /// while the body is streamed. Browsers only expose it cross-origin with
+    /// `Timing-Allow-Origin`.
+    pub fn set_server_timing(&mut self, server_timing: bool) {
+        self.server_timing = server_timing;
+    }
+
+    /// The response to requests while no payload is filled, e.g. 404 for a missing asset or
+    /// 503 until it is warmed up. Defaults to 204 without a body.
+    pub fn set_on_empty(&mut self, status: StatusCode, body: Bytes) {
+        self.on_empty = (status, body);
+    }
+
+    fn redirection(&self, status: StatusCode, location: &HeaderValue) -> Response<Body<T, Error>> {
+        let mut res = Response::builder()
+            .status(status)
+            .header(LOCATION, location)
+            .header(CONTENT_LENGTH, 0);
+        if let Some(cache_control) = self.headers.get(CACHE_CONTROL) {
+            res = res.header(CACHE_CONTROL, cache_control);
+        }
+        res.body(Body::Empty).unwrap()
+    }
+
+    fn empty(&self, head: bool) -> Response<Body<T, Error>> {
+        let (status, ref body) = self.on_empty;
+        let res = Response::builder().status(status);
+        if body.is_empty() {
+            return res.body(Body::Empty).unwrap();
+        }
+        let res = res.body(Body::from(body.clone())).unwrap();
+        if head {
+            without_body(res)
+        } else {
+            res
+        }
+    }
+
+    /// Adjusts the headers of each response to its request, e.g. a `Content-Disposition`
+    /// picked from the query. Runs on the headers of a 200 or 304 before the body is chosen.
+    pub fn set_header_hook(&mut self, hook: Option<fn(&request::Parts, &mut HeaderMap)>) {
+        self.header_hook = hook;
+    }
+
+    /// Answers cross-origin requests as `cors` allows, preflights included. Disabled by
+    /// default. Panics if `cors` allows credentials from any origin.
+    pub fn set_cors(&mut self, cors: Option<Cors>) {
+        if let Some(cors) = &cors {
+            cors.assert_usable();
+        }
+        self.cors = cors;
+    }
+
+    /// Headers for responses of one status only, replacing those of the same name, e.g. a
+    /// debug header on 304s while `headers` carries the long `Cache-Control` of 200s.
+    pub fn set_status_headers(&mut self, status: StatusCode, headers: HeaderMap) {
+        if headers.is_empty() {
+            self.status_headers.remove(&status);
+        } else {
+            self.status_headers.insert(status, headers);
+        }
+    }
+
+    /// Adds a middleware, run after the ones added before it.
+    pub fn add_middleware(&mut self, middleware: impl Middleware<T> + 'static) {
+        self.middlewares.0.push(Box::new(middleware));
+    }
+
+    /// Calls `observer` with every response `call` returns, e.g. to count hits by status and
+    /// `ResponseInfo` in a metrics system.
+    pub fn set_observer(
+        &mut self,
+        observer: impl Fn(&request::Parts, &Response<Body<T, Error>>) + Send + Sync + 'static,
+    ) {
+        self.observer = Some(Observer(Box::new(observer)));
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        self.counters.snapshot(self.len())
+    }
+
+    fn count(&self, res: &Response<Body<T, Error>>) {
+        let counters = &self.counters;
+        Counters::incr(&counters.requests);
+        match res.status() {
+            StatusCode::NOT_MODIFIED => Counters::incr(&counters.not_modified),
+            StatusCode::METHOD_NOT_ALLOWED => Counters::incr(&counters.method_not_allowed),
+            _ => {}
+        }
+        let info = res.extensions().get::<ResponseInfo>();
+        if info.is_some_and(|info| info.decoded) {
+            Counters::incr(&counters.codec_tasks);
+            // counted by the task
+            return;
+        }
+        if let (Some(encoding), Some(len)) = (
+            info.and_then(|info| info.encoding),
+            http_body::Body::size_hint(res.body()).exact(),
+        ) {
+            counters
+                .bytes_served(encoding)
+                .fetch_add(len, Ordering::Relaxed);
+        }
+    }
+
+    pub fn is_filled(&self) -> bool {
+        matches!(**self.payload.load(), Payload::Filled { .. })
+    }
+
+    /// Size of the stored payload, 0 while empty.
+    pub fn len(&self) -> usize {
+        match &**self.payload.load() {
+            Payload::Filled { body, .. } => body.remaining(),
+            _ => 0,
+        }
+    }
+
+    /// No payload bytes to serve, whether filled or not.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The `ETag` of the stored payload.
+    pub fn etag(&self) -> Option<HeaderValue> {
+        match &**self.payload.load() {
+            Payload::Filled { etag, .. } => Some(etag.as_header_value().clone()),
+            _ => None,
+        }
+    }
+
+    /// The encoding of the stored payload, set by `set_encoding`.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn filled_at(&self) -> Option<Instant> {
+        match **self.payload.load() {
+            Payload::Filled { filled_at, .. } => Some(filled_at),
+            _ => None,
+        }
+    }
+
+    /// How many times the service has been filled.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    fn hash_etag(&self, body: &T) -> (ETag, Digests) {
+        if !body.has_remaining() {
+            return (self.weaken(ETag::empty()), Digests::default());
+        }
+        let digest = digest::digest(DigestAlgorithm::Sha256, body.clone());
+        let etag = self.weaken(ETag::from_digest(&digest));
+        (etag, Digests::with_sha256(&digest))
+    }
+
+    fn weaken(&self, etag: ETag) -> ETag {
+        if self.weak_etag {
+            etag.into_weak()
+        } else {
+            etag
+        }
+    }
+
+    pub fn fill(&self, body: T) {
+        self.fill_etag(body, HeaderMap::new());
+    }
+
+    /// Fills the contents of the file at `path`, last modified when the file was. Its
+    /// pre-compressed siblings (`app.js.br`, `app.js.gz`) are registered as variants, as with
+    /// `fill_encoding_variant`.
+    #[cfg(feature = "fs")]
+    pub async fn fill_from_file(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()>
+    where
+        T: From<Bytes>,
+    {
+        let path = path.as_ref();
+        let mut variants = Vec::new();
+        for (sibling, encoding) in crate::files::precompressed(path, std::path::Path::is_file) {
+            let body = Bytes::from(tokio::fs::read(sibling).await?);
+            variants.push(self.encoding_variant(encoding, body.into(), None));
+        }
+        let body = Bytes::from(tokio::fs::read(path).await?);
+        let metadata = tokio::fs::metadata(path).await?;
+        let modified = metadata.modified().unwrap_or_else(|_| SystemTime::now());
+        self.fill_dated(body.into(), None, HeaderMap::new(), modified, variants);
+        Ok(())
+    }
+
+    /// Fills `body` along with headers that override `headers` until the next fill, e.g. a
+    /// `Content-Type` that changes with the payload.
+    pub fn fill_with_headers(&self, body: T, headers: HeaderMap) {
+        self.fill_etag(body, headers);
+    }
+
+    fn fill_etag(&self, body: T, headers: HeaderMap) -> ETag {
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        let (etag, digests) = self.new_etag(&body, generation);
+        self.store(Fill {
+            body,
+            etag: etag.clone(),
+            digests,
+            generation,
+            headers,
+            last_modified: SystemTime::now(),
+            encoding_variants: Vec::new(),
+        });
+        etag
+    }
+
+    /// Fills `body` as last modified at `last_modified`, e.g. the time of the file it was
+    /// read from, with `etag` if it was recorded elsewhere, and its pre-compressed variants.
+    #[cfg(any(feature = "fs", feature = "include_dir"))]
+    pub(crate) fn fill_dated(
+        &self,
+        body: T,
+        etag: Option<ETag>,
+        headers: HeaderMap,
+        last_modified: SystemTime,
+        encoding_variants: Vec<EncodingVariant<T>>,
+    ) {
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        let (etag, digests) = match etag {
+            Some(etag) => (etag, Digests::default()),
+            None => self.new_etag(&body, generation),
+        };
+        self.store(Fill {
+            body,
+            etag,
+            digests,
+            generation,
+            headers,
+            last_modified,
+            encoding_variants,
+        });
+    }
+
+    /// Serves the payload `other` was last filled with, its body and variants shared. The
+    /// headers and options stay those of `self`.
+    #[cfg(any(feature = "fs", feature = "include_dir"))]
+    pub(crate) fn fill_shared(&self, other: &Self) {
+        let payload = other.payload.load_full();
+        let etag = match &*payload {
+            Payload::Filled {
+                etag, generation, ..
+            } => {
+                self.generation.fetch_max(*generation, Ordering::Relaxed);
+                Some(etag.clone())
+            }
+            _ => None,
+        };
+        self.payload.store(payload);
+        self.changes.send_replace(etag);
+    }
+
+    /// `body`, pre-compressed with `encoding`, as a variant of the payload.
+    pub(crate) fn encoding_variant(
+        &self,
+        encoding: Encoding,
+        body: T,
+        etag: Option<ETag>,
+    ) -> EncodingVariant<T> {
+        let (etag, digests) = match etag {
+            Some(etag) => (etag, Digests::default()),
+            None => self.hash_etag(&body),
+        };
+        EncodingVariant {
+            encoding,
+            etag,
+            body,
+            digests: Arc::new(digests),
+        }
+    }
+
+    fn new_etag(&self, body: &T, generation: u64) -> (ETag, Digests) {
+        match self.etag_mode {
+            ETagMode::Content => self.hash_etag(body),
+            ETagMode::Generation => (
+                self.weaken(ETag::from_generation(self.instance, generation)),
+                Digests::default(),
+            ),
+        }
+    }
+
+    /// Fills `body` with a validator computed elsewhere, skipping the hash of the payload.
+    pub fn fill_with_etag(&self, body: T, etag: ETag) {
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        self.store(Fill {
+            body,
+            etag,
+            digests: Digests::default(),
+            generation,
+            headers: HeaderMap::new(),
+            last_modified: SystemTime::now(),
+            encoding_variants: Vec::new(),
+        });
+    }
+
+    fn store(&self, fill: Fill<T>) {
+        let Fill {
+            body,
+            etag,
+            digests,
+            generation,
+            mut headers,
+            last_modified,
+            encoding_variants,
+        } = fill;
+        if self.sniff_content_type
+            && self.encoding == Encoding::Identity
+            && !self.headers.contains_key(CONTENT_TYPE)
+            && !headers.contains_key(CONTENT_TYPE)
+        {
+            if let Some(content_type) = sniff(body.chunk()) {
+                headers.insert(CONTENT_TYPE, content_type);
+            }
+        }
+        if self.validate_utf8 && self.encoding == Encoding::Identity {
+            let content_type = headers
+                .get(CONTENT_TYPE)
+                .or_else(|| self.headers.get(CONTENT_TYPE));
+            if content_type.is_some_and(charset::declares_utf8) && !charset::is_utf8(&body) {
+                warn!("payload declared as UTF-8 is not valid UTF-8");
+            }
+        }
+        let decoded_len = (self.encoding != Encoding::Identity)
+            .then(|| {
+                let max = self.max_decoded_size;
+                codec::decoded_len(body.clone(), self.encoding, &self.compression, max)
+            })
+            .flatten();
+        let signature = self.signer.as_ref().map(|signer| {
+            let mut signed = self.headers.clone();
+            overlay(&mut signed, &headers);
+            let digest = digests.get(DigestAlgorithm::Sha256, &body);
+            signer.sign(&signed, digest, SystemTime::now())
+        });
+        self.payload.store(Arc::new(Payload::Filled {
+            headers,
+            etag: etag.clone(),
+            body,
+            generation,
+            dictionary_variants: Vec::new(),
+            encoding_variants,
+            digests: Arc::new(digests),
+            decoded: Arc::default(),
+            decoded_len,
+            last_modified: Some(truncate_to_secs(last_modified)),
+            filled_at: Instant::now(),
+            signature,
+        }));
+        self.changes.send_replace(Some(etag));
+    }
+
+    /// Retires the payload; requests are answered as if nothing was ever filled.
+    pub fn clear(&self) {
+        self.take();
+    }
+
+    /// Like `clear`, returning the retired payload.
+    pub fn take(&self) -> Option<T> {
+        let payload = self.payload.swap(Arc::new(Payload::Empty));
+        self.changes.send_replace(None);
+        match &*payload {
+            Payload::Filled { body, .. } => Some(body.clone()),
+            _ => None,
+        }
+    }
+
+    /// Replaces the payload with a pointer to `location`, e.g. after moving an asset to a
+    /// CDN: GET and HEAD are answered with `status` and `Location`, along with the
+    /// `Cache-Control` of `headers`, until the next fill. Only 301, 302, 303, 307 and 308 are
+    /// taken.
+    pub fn redirect(&self, status: StatusCode, location: HeaderValue) {
+        if !matches!(status.as_u16(), 301 | 302 | 303 | 307 | 308) {
+            warn!(%status, "redirect is ignored for a status that is not one");
+            return;
+        }
+        info!(%status, location = ?location, "redirecting");
+        self.payload
+            .store(Arc::new(Payload::Redirect { status, location }));
+        self.changes.send_replace(None);
+    }
+
+    /// Watches the ETag of the current payload, updated by every fill and `clear`.
+    pub fn subscribe(&self) -> watch::Receiver<Option<ETag>> {
+        self.changes.subscribe()
+    }
+
+    /// Overrides the `Last-Modified` time of the current payload, which `fill` sets to the
+    /// time of filling.
+    pub fn set_last_modified(&self, last_modified: Option<SystemTime>) {
+        let last_modified = last_modified.map(truncate_to_secs);
+        self.payload.rcu(|payload| {
+            let mut payload = Payload::clone(payload);
+            if let Payload::Filled {
+                last_modified: ref mut current,
+                ..
+            } = payload
+            {
+                *current = last_modified;
+            }
+            payload
+        });
+    }
+
+    /// Sends `Use-As-Dictionary: match="<match_pattern>"`, so that clients keep the payload
+    /// as a compression dictionary for the URLs it matches. Fails, leaving the header as it
+    /// was, if `match_pattern` can't be sent in a header.
+    pub fn set_use_as_dictionary(&mut self, match_pattern: &str) -> Result<(), InvalidHeaderValue> {
+        let value = dictionary::use_as_dictionary(match_pattern)?;
+        self.headers.insert(USE_AS_DICTIONARY, value);
+        Ok(())
+    }
+
+    /// Sends `Link: <href>; rel=preload; as=<destination>` with every response, e.g. for the
+    /// CSS and JS an HTML payload references. Fonts are preloaded with `crossorigin`, as
+    /// browsers fetch them in CORS mode.
+    ///
+    /// No 103 Early Hints are sent, as hyper has no way to write interim responses, but CDNs
+    /// and proxies that support them build theirs from these headers.
+    ///
+    /// Fails, adding nothing, if `href` or `destination` can't be sent in a header.
+    pub fn add_preload(&mut self, href: &str, destination: &str) -> Result<(), InvalidHeaderValue> {
+        let mut link = format!("<{href}>; rel=preload; as={destination}");
+        if destination == "font" {
+            link.push_str("; crossorigin");
+        }
+        self.headers.append(LINK, HeaderValue::try_from(link)?);
+        Ok(())
+    }
+
+    /// Registers `body`, pre-compressed with `encoding`, as a variant of the current payload.
+    /// Clients that accept `encoding` get it as it is, unless they accept the stored encoding
+    /// too, in place of a re-encoded payload. Variants are preferred in the order they are
+    /// registered, and dropped by the next `fill`.
+    pub fn fill_encoding_variant(&self, encoding: Encoding, body: T) {
+        if encoding == Encoding::Identity {
+            warn!("identity variant is ignored");
+            return;
+        }
+        if !self.is_filled() {
+            warn!("encoding variant is ignored on empty payload");
+            return;
+        }
+        let variant = self.encoding_variant(encoding, body, None);
+        self.payload.rcu(|payload| {
+            let mut payload = Payload::clone(payload);
+            if let Payload::Filled {
+                ref mut encoding_variants,
+                ..
+            } = payload
+            {
+                encoding_variants.retain(|v| v.encoding != variant.encoding);
+                encoding_variants.push(variant.clone());
+            }
+            payload
+        });
+    }
+
+    /// Registers `body`, compressed with `encoding` against `dictionary`, as a variant of the
+    /// current payload. It is dropped by the next `fill`.
+    pub fn fill_dictionary_variant(
+        &self,
+        dictionary: impl Buf,
+        encoding: DictionaryEncoding,
+        body: T,
+    ) {
+        let variant = DictionaryVariant {
+            dictionary: DictionaryHash::from_buf(dictionary),
+            encoding,
+            etag: self.hash_etag(&body).0,
+            body,
+        };
+        if !self.is_filled() {
+            warn!("dictionary variant is ignored on empty payload");
+            return;
+        }
+        self.payload.rcu(|payload| {
+            let mut payload = Payload::clone(payload);
+            if let Payload::Filled {
+                ref mut dictionary_variants,
+                ..
+            } = payload
+            {
+                dictionary_variants.retain(|v| {
+                    v.dictionary != variant.dictionary || v.encoding != variant.encoding
+                });
+                dictionary_variants.push(variant.clone());
+            }
+            payload
+        });
+    }
+
+    pub async fn call<B>(&self, req: Request<B>) -> Response<Body<T, Error>> {
+        let (parts, _) = req.into_parts();
+        self.call_parts(&parts).await
+    }
+
+    /// Answers a request from its head alone; the body of a GET or HEAD is never read.
+    ///
+    /// Each call runs in a `call` span recording the method, status, content-coding and
+    /// request id, with OpenTelemetry attribute names under the `otel` feature. Per-request
+    /// events are at debug level.
+    pub async fn call_parts(&self, req: &request::Parts) -> Response<Body<T, Error>> {
+        for middleware in &self.middlewares.0 {
+            if let ControlFlow::Break(res) = middleware.before(req) {
+                return res;
+            }
+        }
+        let request_id = self
+            .request_id_header
+            .as_ref()
+            .map(|name| request_id(req.headers.get(name)));
+        let span = span::call(req, request_id.as_ref());
+        let res = self.respond(req).instrument(span.clone()).await;
+        // HEAD bodies are empty, so their `Content-Length` counts, as for the GET
+        let content_length = res
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok()?.parse().ok());
+        let size = http_body::Body::size_hint(res.body())
+            .lower()
+            .max(content_length.unwrap_or_default());
+        let mut res = match self.max_response_size {
+            Some(max) if size > max => {
+                error!(%size, %max, "response size limit exceeded");
+                internal_server_error()
+            }
+            _ => res,
+        };
+        if let Some(cors) = &self.cors {
+            if !cors::is_preflight(&req.method, &req.headers) {
+                cors.apply(req.headers.get(ORIGIN), res.headers_mut());
+            }
+        }
+        res.headers_mut().entry(DATE).or_insert_with(date::now);
+        if let (Some(name), Some(request_id)) = (&self.request_id_header, request_id) {
+            res.headers_mut().insert(name, request_id);
+        }
+        let decoded = matches!(res.body(), Body::Stream { task: Some(_), .. });
+        let info = res.extensions_mut().get_or_insert_default::<ResponseInfo>();
+        info.decoded = decoded;
+        self.count(&res);
+        if let Some(headers) = self.status_headers.get(&res.status()) {
+            overlay(res.headers_mut(), headers);
+        }
+        for middleware in &self.middlewares.0 {
+            middleware.after(&mut res);
+        }
+        if let Some(signature) = res.extensions_mut().remove::<Signature>() {
+            if !signature.covers(res.status(), res.headers()) {
+                warn!("signed headers changed after signing, signature dropped");
+                Signature::remove(res.headers_mut());
+            }
+        }
+        if let Some(info) = res.extensions().get::<ResponseInfo>() {
+            span::record(&span, res.status(), info);
+        }
+        if let Some(Observer(observer)) = &self.observer {
+            observer(req, &res);
+        }
+        res
+    }
+
+    /// Lets `call_writable` fill the service from `PUT` requests, and clear it on `DELETE`,
+    /// when they are authorized with `Authorization: Bearer <token>`. Disabled by default.
+    pub fn set_write_token(&mut self, token: Option<String>) {
+        self.write_token = token;
+    }
+
+    /// The largest request body a `PUT` may fill, 16 MiB by default. Larger ones are
+    /// answered with 413.
+    pub fn set_max_write_size(&mut self, max_write_size: usize) {
+        self.max_write_size = max_write_size;
+    }
+
+    /// Like `call`, also serving the writes enabled by `set_write_token`: `PUT` replaces the
+    /// payload with the request body and answers 204 with the new `ETag`; `DELETE` clears it
+    /// and answers 204. The `Content-Type` of a `PUT` is served with the payload, in place
+    /// of the one in `headers`.
+    pub async fn call_writable<B>(&self, req: Request<B>) -> Response<Body<T, Error>>
+    where
+        B: http_body::Body,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        T: From<Bytes>,
+    {
+        if self.write_token.is_none() || !matches!(*req.method(), Method::PUT | Method::DELETE) {
+            return self.call(req).await;
+        }
+        if !self.is_authorized(req.headers()) {
+            return unauthorized();
+        }
+        if req.method() == Method::DELETE {
+            self.clear();
+            info!("cleared by DELETE");
+            return Response::builder()
+                .status(StatusCode::NO_CONTENT)
+                .body(Body::Empty)
+                .unwrap();
+        }
+        let mut headers = HeaderMap::new();
+        if let Some(content_type) = req.headers().get(CONTENT_TYPE) {
+            headers.insert(CONTENT_TYPE, content_type.clone());
+        }
+        let body = http_body_util::Limited::new(req.into_body(), self.max_write_size);
+        let body = match http_body_util::BodyExt::collect(body).await {
+            Ok(body) => body.to_bytes(),
+            Err(err) if err.is::<http_body_util::LengthLimitError>() => {
+                warn!(max = %self.max_write_size, "request body too large");
+                return payload_too_large();
+            }
+            Err(err) => {
+                warn!(%err, "fail to read request body");
+                return bad_request();
+            }
+        };
+        let len = body.len();
+        let etag = self.fill_etag(body.into(), headers);
+        info!(%len, "filled by PUT");
+        Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .header(ETAG, etag.0)
+            .body(Body::Empty)
+            .unwrap()
+    }
+
+    fn is_authorized(&self, headers: &HeaderMap) -> bool {
+        let Some(token) = &self.write_token else {
+            return false;
+        };
+        let Some(credentials) = headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.as_bytes().strip_prefix(b"Bearer "))
+        else {
+            return false;
         };
-        *self.payload.write().unwrap() = Payload::Filled { etag, body };
+        aws_lc_rs::constant_time::verify_slices_are_equal(credentials, token.as_bytes()).is_ok()
     }
 
-    pub async fn call<B>(&self, req: Request<B>) -> Response<Body<T>> {
-        let head = match *req.method() {
+    /// The methods this service answers.
+    pub(crate) fn methods(&self) -> Vec<Method> {
+        let mut methods = vec![Method::GET, Method::HEAD, Method::OPTIONS];
+        if self.write_token.is_some() {
+            methods.extend([Method::PUT, Method::DELETE]);
+        }
+        methods
+    }
+
+    /// `methods`, for `Allow`.
+    fn allow(&self) -> HeaderValue {
+        let methods = self.methods();
+        let allow: Vec<_> = methods.iter().map(Method::as_str).collect();
+        HeaderValue::try_from(allow.join(", ")).unwrap()
+    }
+
+    async fn respond(&self, req: &request::Parts) -> Response<Body<T, Error>> {
+        let head = match req.method {
             Method::HEAD => true,
             Method::GET => false,
+            Method::OPTIONS => {
+                return match &self.cors {
+                    Some(cors) if cors::is_preflight(&req.method, &req.headers) => {
+                        cors.preflight(&req.headers, &self.methods())
+                    }
+                    _ => options(self.allow()),
+                };
+            }
             _ => {
-                return method_not_allowed();
+                return method_not_allowed(self.allow());
             }
         };
+        let negotiate_start = Instant::now();
+        let mut selected = match self.select(req, head) {
+            ControlFlow::Continue(selected) => selected,
+            ControlFlow::Break(res) => return res,
+        };
+        let coding = match self.select_coding(req, head, &selected) {
+            ControlFlow::Continue(coding) => coding,
+            ControlFlow::Break(res) => return res,
+        };
+        let negotiate_time = negotiate_start.elapsed();
+
+        let content_digest = self.representation_headers(req, &mut selected, coding);
+        let info = match self.evaluate_conditions(req, &mut selected, coding, negotiate_time) {
+            ControlFlow::Continue(info) => info,
+            ControlFlow::Break(res) => return res,
+        };
+
+        let mut res = Response::builder()
+            .status(http::StatusCode::OK)
+            .extension(info);
+        *res.headers_mut().unwrap() = std::mem::take(&mut selected.headers);
+        let res = match self.partial(req, res, &selected, coding, head) {
+            ControlFlow::Continue(res) => res,
+            ControlFlow::Break(res) => return res,
+        };
+        self.full(res, selected, coding, content_digest, head).await
+    }
 
-        let (etag, body) = {
-            let buf = self.payload.read().unwrap();
+    /// The representation of the stored payload a request gets, before any coding.
+    fn select(
+        &self,
+        req: &request::Parts,
+        head: bool,
+    ) -> ControlFlow<Response<Body<T, Error>>, Selected<T>> {
+        let buf = self.payload.load();
 
-            let Payload::Filled { ref etag, ref body } = *buf else {
-                return no_content();
+        if let Payload::Redirect {
+            status,
+            ref location,
+        } = **buf
+        {
+            return ControlFlow::Break(self.redirection(status, location));
+        }
+        let Payload::Filled {
+            ref etag,
+            ref body,
+            generation,
+            ref dictionary_variants,
+            ref encoding_variants,
+            ref digests,
+            ref decoded,
+            decoded_len,
+            last_modified,
+            headers: ref fill_headers,
+            ref signature,
+            ..
+        } = **buf
+        else {
+            return ControlFlow::Break(match conditional::evaluate_missing(&req.headers) {
+                Outcome::PreconditionFailed => precondition_failed(),
+                _ => self.empty(head),
+            });
+        };
+
+        let mut headers = self.headers.clone();
+        overlay(&mut headers, fill_headers);
+
+        let variant = select_dictionary_variant(dictionary_variants, &req.headers);
+        let precompressed = match variant {
+            Some(_) => None,
+            None => self.select_encoding_variant(encoding_variants, &req.headers),
+        };
+        let (etag, body, digests) = match (variant, precompressed) {
+            (Some(v), _) => (&v.etag, &v.body, None),
+            (None, Some(v)) => (&v.etag, &v.body, Some(&v.digests)),
+            (None, None) => (etag, body, Some(digests)),
+        };
+        ControlFlow::Continue(Selected {
+            headers,
+            etag: etag.clone(),
+            body: body.clone(),
+            generation,
+            dictionary_encoding: variant.map(|v| v.encoding),
+            has_dictionary_variants: !dictionary_variants.is_empty(),
+            precompressed: precompressed.map(|v| v.encoding),
+            variant_encodings: encoding_variants.iter().map(|v| v.encoding).collect(),
+            digests: digests.cloned(),
+            decoded: decoded.clone(),
+            decoded_len,
+            last_modified,
+            signature: signature
+                .clone()
+                .filter(|_| variant.is_none() && precompressed.is_none()),
+        })
+    }
+
+    /// How the selected representation is coded for the request, or the 406 answering
+    /// when no coding is acceptable.
+    fn select_coding(
+        &self,
+        req: &request::Parts,
+        head: bool,
+        selected: &Selected<T>,
+    ) -> ControlFlow<Response<Body<T, Error>>, Coding> {
+        let Selected {
+            headers,
+            body,
+            dictionary_encoding,
+            precompressed,
+            variant_encodings,
+            ..
+        } = selected;
+        let coding =
+            if dictionary_encoding.is_some() || precompressed.is_some() || !body.has_remaining() {
+                Coding::Stored
+            } else {
+                self.negotiate(headers, req.headers.get(ACCEPT_ENCODING))
+            };
+        if coding != Coding::Stored && forbids_transform(&req.headers) {
+            debug!("stored encoding is not acceptable and transforms are forbidden");
+            return ControlFlow::Break(not_acceptable());
+        }
+        if self.strict_negotiation && dictionary_encoding.is_none() && body.has_remaining() {
+            let served = match coding {
+                Coding::Stored => precompressed.unwrap_or(self.encoding),
+                Coding::Transcode(target) => target,
+                Coding::Decode => Encoding::Identity,
+            };
+            let content_type = headers.get(CONTENT_TYPE);
+            let media_type = match (req.headers.get(ACCEPT), content_type) {
+                (Some(accept), Some(content_type)) => {
+                    accept::accepts_media_type(accept, content_type)
+                }
+                _ => true,
             };
+            let coding = match req.headers.get(ACCEPT_ENCODING) {
+                Some(accept_encoding) => accept::accepts_encoding(accept_encoding, served),
+                None => true,
+            };
+            if !media_type || !coding {
+                debug!(%served, "no representation is acceptable");
+                let encodings = self.available_encodings(headers, variant_encodings);
+                return ControlFlow::Break(unacceptable(content_type, &encodings, head));
+            }
+        }
+        ControlFlow::Continue(coding)
+    }
+
+    /// Whether `Range` requests are served for the selected representation.
+    fn rangeable(&self, selected: &Selected<T>, coding: Coding) -> bool {
+        self.accept_ranges
+            && coding == Coding::Stored
+            && self.encoding == Encoding::Identity
+            && selected.dictionary_encoding.is_none()
+            && selected.precompressed.is_none()
+    }
 
-            (etag.clone(), body.clone())
+    /// Adds the headers 200s and 304s share, and returns the `Content-Digest` of a full
+    /// response.
+    fn representation_headers(
+        &self,
+        req: &request::Parts,
+        selected: &mut Selected<T>,
+        coding: Coding,
+    ) -> Option<HeaderValue> {
+        let suffix = match coding {
+            Coding::Transcode(encoding) => Some(encoding),
+            Coding::Decode => Some(Encoding::Identity),
+            Coding::Stored => None,
         };
+        if let Some(suffix) = suffix.filter(|_| self.etag_suffix) {
+            selected.etag = selected.etag.with_suffix(suffix.as_str());
+        }
+        let rangeable = self.rangeable(selected, coding);
 
-        if let Some(if_none_match) = req.headers().get(IF_NONE_MATCH) {
-            if etag.matches(if_none_match.as_bytes()) {
-                return not_modified();
+        let headers = &mut selected.headers;
+        headers.insert(
+            ACCEPT_RANGES,
+            HeaderValue::from_static(if rangeable { "bytes" } else { "none" }),
+        );
+        headers.insert(ETAG, selected.etag.0.clone());
+        if let Some(name) = &self.generation_header {
+            headers.insert(name, selected.generation.into());
+        }
+        if let Some(expires) = self.expires() {
+            headers.insert(EXPIRES, expires);
+        }
+        if let Some(last_modified) = selected.last_modified {
+            headers.insert(
+                LAST_MODIFIED,
+                httpdate::fmt_http_date(last_modified).try_into().unwrap(),
+            );
+        }
+        if selected.has_dictionary_variants {
+            headers.append(
+                VARY,
+                HeaderValue::from_static("accept-encoding, available-dictionary"),
+            );
+        } else if self.encoding != Encoding::Identity
+            || !selected.variant_encodings.is_empty()
+            || self.vary_accept_encoding
+        {
+            headers.append(VARY, HeaderValue::from_static("accept-encoding"));
+        }
+        if let Some(encoding) = selected.precompressed {
+            headers.insert(CONTENT_ENCODING, encoding.into());
+        }
+        let digested = coding == Coding::Stored && !self.repr_digest.is_empty();
+        let digests = selected.digests.as_ref().filter(|_| digested);
+        let body = &selected.body;
+        let digest_header = |want: HeaderName| {
+            let algorithms = digest::negotiate(&self.repr_digest, req.headers.get(want));
+            digests?.header(&algorithms, body)
+        };
+        if let Some(repr_digest) = digest_header(WANT_REPR_DIGEST) {
+            headers.insert(REPR_DIGEST, repr_digest);
+        }
+        let content_digest = digest_header(WANT_CONTENT_DIGEST);
+        if let Some(hook) = self.header_hook {
+            hook(req, headers);
+        }
+        content_digest
+    }
+
+    /// What the response reports in `ResponseInfo`, or the 304 or 412 answering when the
+    /// preconditions of the request say so.
+    fn evaluate_conditions(
+        &self,
+        req: &request::Parts,
+        selected: &mut Selected<T>,
+        coding: Coding,
+        negotiate_time: Duration,
+    ) -> ControlFlow<Response<Body<T, Error>>, ResponseInfo> {
+        let mut info = ResponseInfo {
+            etag: Some(selected.etag.clone()),
+            encoding: match coding {
+                Coding::Stored if selected.dictionary_encoding.is_some() => None,
+                Coding::Stored if !selected.body.has_remaining() => Some(Encoding::Identity),
+                Coding::Stored => Some(selected.precompressed.unwrap_or(self.encoding)),
+                Coding::Transcode(target) => Some(target),
+                Coding::Decode => Some(Encoding::Identity),
+            },
+            ..ResponseInfo::default()
+        };
+
+        let etag_start = Instant::now();
+        let outcome = conditional::evaluate(&req.headers, &selected.etag, selected.last_modified);
+        if self.server_timing {
+            let metrics = [
+                ("negotiate", negotiate_time),
+                ("etag", etag_start.elapsed()),
+            ];
+            for (name, duration) in metrics {
+                let metric = server_timing::metric(name, duration, None);
+                selected.headers.append(SERVER_TIMING, metric);
+            }
+        }
+        match outcome {
+            Outcome::Proceed => ControlFlow::Continue(info),
+            Outcome::NotModified => {
+                info.cache_hit = true;
+                let mut res = not_modified(std::mem::take(&mut selected.headers));
+                res.extensions_mut().insert(info);
+                ControlFlow::Break(res)
             }
+            Outcome::PreconditionFailed => ControlFlow::Break(precondition_failed()),
         }
+    }
 
-        let mut res = Response::builder().status(http::StatusCode::OK);
+    /// The 206 or 416 answering a `Range` request, unless the full body is served.
+    fn partial(
+        &self,
+        req: &request::Parts,
+        mut res: response::Builder,
+        selected: &Selected<T>,
+        coding: Coding,
+        head: bool,
+    ) -> ControlFlow<Response<Body<T, Error>>, response::Builder> {
+        let Some(range) = req.headers.get(RANGE) else {
+            return ControlFlow::Continue(res);
+        };
+        let Selected {
+            etag,
+            body,
+            last_modified,
+            ..
+        } = selected;
+        let if_range = req.headers.get(IF_RANGE);
+        if !self.rangeable(selected, coding)
+            || if_range.is_some_and(|v| !etag.matches_if_range(v.as_bytes(), *last_modified))
+        {
+            return ControlFlow::Continue(res);
+        }
 
-        for (k, v) in &self.headers {
-            res = res.header(k.clone(), v.clone());
+        let len = body.remaining();
+        let ranges = match range::parse(range.as_bytes(), len) {
+            Some(ByteRanges::Satisfiable(ranges)) => {
+                let ranges = range::coalesce(ranges);
+                if ranges.len() > self.max_ranges {
+                    debug!(ranges = ranges.len(), "too many ranges, serving full body");
+                    None
+                } else {
+                    Some(ByteRanges::Satisfiable(ranges))
+                }
+            }
+            ranges => ranges,
+        };
+        let partial = match ranges {
+            Some(ByteRanges::Satisfiable(ranges)) if ranges.len() == 1 => {
+                let range = ranges[0].clone();
+                let content_range = format!("bytes {}-{}/{len}", range.start, range.end - 1);
+                let mut body = body.clone();
+                body.advance(range.start);
+                debug!(%content_range, "serving partial body");
+                res = res.header(CONTENT_RANGE, content_range);
+                Body::Slice {
+                    inner: Some(body.take(range.len())),
+                }
+            }
+            Some(ByteRanges::Satisfiable(ranges)) => {
+                let boundary = format!("{:016x}", random_u64());
+                let chunks = range::multipart(
+                    body,
+                    &ranges,
+                    res.headers_ref().unwrap().get(CONTENT_TYPE),
+                    &boundary,
+                );
+                res.headers_mut().unwrap().insert(
+                    CONTENT_TYPE,
+                    format!("multipart/byteranges; boundary={boundary}")
+                        .try_into()
+                        .unwrap(),
+                );
+                debug!(ranges = ranges.len(), "serving multipart body");
+                Body::Chunks { chunks }
+            }
+            Some(ByteRanges::Unsatisfiable) => {
+                return ControlFlow::Break(range_not_satisfiable(len));
+            }
+            None => return ControlFlow::Continue(res),
+        };
+        let res = res
+            .status(http::StatusCode::PARTIAL_CONTENT)
+            .body(partial)
+            .unwrap();
+        ControlFlow::Break(if head {
+            without_body(res)
+        } else {
+            with_content_length(res)
+        })
+    }
+
+    /// The 200 with the whole representation, coded as negotiated.
+    async fn full(
+        &self,
+        mut res: response::Builder,
+        selected: Selected<T>,
+        coding: Coding,
+        content_digest: Option<HeaderValue>,
+        head: bool,
+    ) -> Response<Body<T, Error>> {
+        let Selected {
+            body,
+            dictionary_encoding,
+            precompressed,
+            decoded,
+            decoded_len,
+            signature,
+            ..
+        } = selected;
+
+        if let Some(encoding) = dictionary_encoding {
+            res.headers_mut()
+                .unwrap()
+                .insert(CONTENT_ENCODING, encoding.into());
+            let bytes = body.remaining();
+            let res = with_content_length(res.body(Body::Buf { inner: Some(body) }).unwrap());
+            if head {
+                return without_body(res);
+            }
+            debug!(%encoding, %bytes, "serving dictionary-compressed body");
+            return res;
+        }
+
+        if let Some(content_digest) = content_digest {
+            // the content is the whole representation
+            res = res.header(CONTENT_DIGEST, content_digest);
+        }
+        if let Some(signature) = signature.filter(|_| coding == Coding::Stored) {
+            signature.apply(res.headers_mut().unwrap());
+            // checked once every header is final
+            res = res.extension(signature);
         }
-        res = res.header(ETAG, etag.0);
 
         if head {
+            // the length of the representation a GET would get, when known without coding it
+            let len = match coding {
+                Coding::Stored => Some(body.remaining() as u64),
+                Coding::Decode => decoded
+                    .get()
+                    .map(|decoded| decoded.len() as u64)
+                    .or(decoded_len),
+                Coding::Transcode(_) => None,
+            };
+            if let Some(len) = len {
+                res = res.header(CONTENT_LENGTH, len);
+            }
             return res.body(Body::Empty).unwrap();
         }
 
         if body.has_remaining() {
             let bytes = body.remaining();
-            let encoding = self.encoding;
+            let encoding = precompressed.unwrap_or(self.encoding);
 
-            let body = if let Some(accept_encoding) = req.headers().get(ACCEPT_ENCODING) {
-                if encoding == Encoding::Identity || encoding.is_contained_in(accept_encoding) {
-                    info!(%encoding, %bytes, "serving body");
-                    Body::Buf { inner: Some(body) }
-                } else {
+            let body = match coding {
+                Coding::Stored => {
+                    debug!(%encoding, %bytes, "serving body");
+                    match self.frame_size {
+                        Some(frame_size) => Body::Frames {
+                            inner: body,
+                            frame_size,
+                        },
+                        None => Body::Buf { inner: Some(body) },
+                    }
+                }
+                Coding::Transcode(target) => {
+                    res.headers_mut()
+                        .unwrap()
+                        .insert(CONTENT_ENCODING, target.into());
+                    let permit = match self.codec_permit().await {
+                        Ok(permit) => permit,
+                        Err(retry_after) => return service_unavailable(retry_after),
+                    };
+                    debug!(%encoding, %target, "transcoder task is spawned");
+                    let (rx, task) = codec::spawn_transcoder(
+                        body,
+                        encoding,
+                        target,
+                        &self.compression,
+                        self.limits(),
+                        permit,
+                        self.counters.bytes_served(target).clone(),
+                    );
+                    Body::from_task(rx, None, task)
+                }
+                Coding::Decode => {
+                    let decode_start = Instant::now();
                     res.headers_mut().unwrap().remove(CONTENT_ENCODING);
-                    let spawn_decoder = match encoding {
-                        Encoding::Br => spawn_br_decoder,
-                        Encoding::Gzip => spawn_gzip_decoder,
-                        Encoding::Deflate => spawn_deflate_decoder,
-                        Encoding::Identity => unreachable!(),
+                    let (body, desc) = if let Some(decoded) = decoded.get() {
+                        debug!(%encoding, bytes = %decoded.len(), "serving decoded body");
+                        (Body::from(decoded.clone()), "cached")
+                    } else {
+                        let permit = match self.codec_permit().await {
+                            Ok(permit) => permit,
+                            Err(retry_after) => return service_unavailable(retry_after),
+                        };
+                        debug!(%encoding, "decoder task is spawned");
+                        let (rx, task) = codec::spawn_decoder(
+                            body,
+                            encoding,
+                            &self.compression,
+                            self.limits(),
+                            decoded,
+                            permit,
+                            self.counters.bytes_served(Encoding::Identity).clone(),
+                        );
+                        (Body::from_task(rx, decoded_len, task), "spawned")
                     };
-                    warn!(%encoding, "decoder task is spawned");
-                    Body::from(spawn_decoder(body))
+                    if self.server_timing {
+                        let metric =
+                            server_timing::metric("decode", decode_start.elapsed(), Some(desc));
+                        res.headers_mut().unwrap().append(SERVER_TIMING, metric);
+                    }
+                    body
                 }
-            } else {
-                info!(%encoding, %bytes, "serving body");
-                Body::Buf { inner: Some(body) }
             };
 
-            res.body(body).unwrap()
+            with_content_length(res.body(body).unwrap())
         } else {
             res.headers_mut().unwrap().remove(CONTENT_ENCODING);
-            res.body(Body::Empty).unwrap()
+            with_content_length(res.body(Body::Empty).unwrap())
+        }
+    }
+}
+
+/// What `Service::set_max_codec_tasks` does with a request once the limit is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Saturation {
+    /// Waits for a running task to end.
+    Queue,
+    /// Answers 503 with `Retry-After`.
+    Shed { retry_after: Duration },
+}
+
+/// What `Service::call` made of a request, found in the extensions of every response it
+/// returns.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ResponseInfo {
+    /// The validator of the selected representation, even when answering 304.
+    pub etag: Option<ETag>,
+    /// The content-coding of the selected representation. `None` when nothing was selected
+    /// or it is dictionary-compressed.
+    pub encoding: Option<Encoding>,
+    /// The client's cached copy is still valid, i.e. the response is 304.
+    pub cache_hit: bool,
+    /// The body is produced by a spawned decoder or transcoder task.
+    pub decoded: bool,
+}
+
+/// How the stored payload is turned into the representation a client gets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Coding {
+    Stored,
+    Transcode(Encoding),
+    Decode,
+}
+
+impl<T> Service<T> {
+    /// A pre-compressed variant the client accepts, unless it accepts the stored encoding.
+    fn select_encoding_variant<'a>(
+        &self,
+        variants: &'a [EncodingVariant<T>],
+        headers: &HeaderMap,
+    ) -> Option<&'a EncodingVariant<T>> {
+        let accept_encoding = headers.get(ACCEPT_ENCODING)?;
+        if self.encoding != Encoding::Identity
+            && accept::accepts_encoding(accept_encoding, self.encoding)
+        {
+            return None;
+        }
+        variants
+            .iter()
+            .find(|variant| accept::accepts_encoding(accept_encoding, variant.encoding))
+    }
+
+    /// The content-codings `negotiate` can serve the payload in, besides `variants`.
+    fn available_encodings(&self, headers: &HeaderMap, variants: &[Encoding]) -> Vec<Encoding> {
+        let mut encodings = vec![self.encoding];
+        encodings.extend_from_slice(variants);
+        if self.encoding != Encoding::Identity && codec::is_supported(self.encoding) {
+            if self.compress_policy.should_compress(headers) {
+                encodings.extend(codec::transcode_targets());
+            }
+            encodings.push(Encoding::Identity);
+        }
+        let mut available = Vec::new();
+        for encoding in encodings {
+            if !available.contains(&encoding) {
+                available.push(encoding);
+            }
+        }
+        available
+    }
+
+    fn negotiate(&self, headers: &HeaderMap, accept_encoding: Option<&HeaderValue>) -> Coding {
+        let encoding = self.encoding;
+        let Some(accept_encoding) = accept_encoding else {
+            return Coding::Stored;
+        };
+        if encoding == Encoding::Identity || accept::accepts_encoding(accept_encoding, encoding) {
+            return Coding::Stored;
+        }
+        if !codec::is_supported(encoding) {
+            warn!(%encoding, "decoder is not compiled in, serving stored encoding");
+            return Coding::Stored;
+        }
+        if self.compress_policy.should_compress(headers) {
+            if let Some(target) = codec::transcode_targets()
+                .find(|&target| accept::accepts_encoding(accept_encoding, target))
+            {
+                return Coding::Transcode(target);
+            }
+        }
+        Coding::Decode
+    }
+}
+
+/// A request `Cache-Control: no-transform`, which rules out decoding or transcoding.
+fn forbids_transform(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|directive| directive.trim().eq_ignore_ascii_case("no-transform"))
+}
+
+/// Replaces the headers of `headers` named in `over`.
+fn overlay(headers: &mut HeaderMap, over: &HeaderMap) {
+    for name in over.keys() {
+        headers.remove(name);
+        for value in over.get_all(name) {
+            headers.append(name, value.clone());
+        }
+    }
+}
+
+/// The request id to send back: the one of the request, unless it is missing, empty, too
+/// long or not visible ASCII.
+fn request_id(requested: Option<&HeaderValue>) -> HeaderValue {
+    match requested {
+        Some(id) if (1..=200).contains(&id.len()) && id.to_str().is_ok() => id.clone(),
+        _ => HeaderValue::try_from(format!("{:016x}{:016x}", random_u64(), random_u64())).unwrap(),
+    }
+}
+
+fn random_u64() -> u64 {
+    let mut bytes = [0; 8];
+    aws_lc_rs::rand::fill(&mut bytes).expect("fail to generate random bytes");
+    u64::from_ne_bytes(bytes)
+}
+
+/// HTTP dates have a resolution of one second.
+fn truncate_to_secs(time: SystemTime) -> SystemTime {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs());
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+fn select_dictionary_variant<'a, T>(
+    variants: &'a [DictionaryVariant<T>],
+    headers: &HeaderMap,
+) -> Option<&'a DictionaryVariant<T>> {
+    if variants.is_empty() {
+        return None;
+    }
+    let available_dictionary = headers.get(AVAILABLE_DICTIONARY)?;
+    let accept_encoding = headers.get(ACCEPT_ENCODING)?;
+    variants.iter().find(|variant| {
+        variant.dictionary.matches(available_dictionary.as_bytes())
+            && accept::accepts_coding(accept_encoding, |coding| {
+                coding.eq_ignore_ascii_case(variant.encoding.as_str())
+            })
+    })
+}
+
+/// Headers a 304 response carries over from the 200 it stands for (RFC 9110 §15.4.5).
+const NOT_MODIFIED_HEADERS: [HeaderName; 8] = [
+    CACHE_CONTROL,
+    CDN_CACHE_CONTROL,
+    SURROGATE_CONTROL,
+    CONTENT_LOCATION,
+    ETAG,
+    EXPIRES,
+    VARY,
+    SERVER_TIMING,
+];
+
+fn not_modified<T: Buf>(headers: HeaderMap) -> Response<Body<T, Error>> {
+    let mut res = Response::builder().status(http::StatusCode::NOT_MODIFIED);
+    for name in NOT_MODIFIED_HEADERS {
+        for value in headers.get_all(&name) {
+            res = res.header(&name, value);
         }
     }
+    res.body(Body::Empty).unwrap()
 }
 
-fn no_content<T: Buf>() -> Response<Body<T>> {
+fn not_acceptable<T: Buf>() -> Response<Body<T, Error>> {
     Response::builder()
-        .status(http::StatusCode::NO_CONTENT)
+        .status(http::StatusCode::NOT_ACCEPTABLE)
+        .header(VARY, "accept-encoding")
         .body(Body::Empty)
         .unwrap()
 }
 
-fn not_modified<T: Buf>() -> Response<Body<T>> {
+/// A 406 listing the available representations as JSON.
+fn unacceptable<T: Buf>(
+    content_type: Option<&HeaderValue>,
+    encodings: &[Encoding],
+    head: bool,
+) -> Response<Body<T, Error>> {
+    let quote = |value: &str| {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        format!("\"{value}\"")
+    };
+    let content_type = content_type
+        .and_then(|content_type| content_type.to_str().ok())
+        .map_or_else(|| "null".to_owned(), quote);
+    let encodings: Vec<_> = encodings
+        .iter()
+        .map(|encoding| quote(encoding.as_str()))
+        .collect();
+    let json = format!(
+        "{{\"content-type\":{content_type},\"content-encoding\":[{}]}}",
+        encodings.join(",")
+    );
+    let res = Response::builder()
+        .status(http::StatusCode::NOT_ACCEPTABLE)
+        .header(CONTENT_TYPE, "application/json")
+        .header(VARY, "accept, accept-encoding")
+        .body(Body::from(Bytes::from(json)))
+        .unwrap();
+    if head {
+        without_body(res)
+    } else {
+        with_content_length(res)
+    }
+}
+
+fn precondition_failed<T: Buf>() -> Response<Body<T, Error>> {
     Response::builder()
-        .status(http::StatusCode::NOT_MODIFIED)
+        .status(http::StatusCode::PRECONDITION_FAILED)
         .body(Body::Empty)
         .unwrap()
 }
 
-fn method_not_allowed<T: Buf>() -> Response<Body<T>> {
+/// Sets `Content-Length` from a body of known size, rather than leaving it to the connection.
+fn with_content_length<T: Buf>(mut res: Response<Body<T, Error>>) -> Response<Body<T, Error>> {
+    if let Some(len) = http_body::Body::size_hint(res.body()).exact() {
+        res.headers_mut().insert(CONTENT_LENGTH, len.into());
+    }
+    res
+}
+
+/// Turns a response into the one for HEAD, keeping the length of the dropped body.
+fn without_body<T: Buf>(res: Response<Body<T, Error>>) -> Response<Body<T, Error>> {
+    let mut res = with_content_length(res);
+    *res.body_mut() = Body::Empty;
+    res
+}
+
+fn range_not_satisfiable<T: Buf>(len: usize) -> Response<Body<T, Error>> {
     Response::builder()
-        .status(http::StatusCode::METHOD_NOT_ALLOWED)
-        .body(Body::from_static(b"Method not allowed"))
+        .status(http::StatusCode::RANGE_NOT_SATISFIABLE)
+        .header(CONTENT_RANGE, format!("bytes */{len}"))
+        .body(Body::Empty)
         .unwrap()
 }
 
-fn spawn_br_decoder(body: impl Buf + Send + 'static) -> mpsc::Receiver<Bytes> {
-    spawn_decoder(brotli_decompressor::Decompressor::new(body.reader(), 512))
+fn bad_request<T: Buf>() -> Response<Body<T, Error>> {
+    Response::builder()
+        .status(http::StatusCode::BAD_REQUEST)
+        .body(Body::Empty)
+        .unwrap()
 }
 
-fn spawn_gzip_decoder(body: impl Buf + Send + 'static) -> mpsc::Receiver<Bytes> {
-    spawn_decoder(flate2::read::GzDecoder::new(body.reader()))
+fn payload_too_large<T: Buf>() -> Response<Body<T, Error>> {
+    Response::builder()
+        .status(http::StatusCode::PAYLOAD_TOO_LARGE)
+        .body(Body::Empty)
+        .unwrap()
 }
 
-fn spawn_deflate_decoder(body: impl Buf + Send + 'static) -> mpsc::Receiver<Bytes> {
-    spawn_decoder(flate2::read::DeflateDecoder::new(body.reader()))
+fn unauthorized<T: Buf>() -> Response<Body<T, Error>> {
+    Response::builder()
+        .status(http::StatusCode::UNAUTHORIZED)
+        .header(http::header::WWW_AUTHENTICATE, "Bearer")
+        .body(Body::Empty)
+        .unwrap()
 }
 
-fn spawn_decoder(mut read_decoder: impl std::io::Read + Send + 'static) -> mpsc::Receiver<Bytes> {
-    let (tx, rx) = mpsc::channel(1);
+fn service_unavailable<T: Buf>(retry_after: Duration) -> Response<Body<T, Error>> {
+    Response::builder()
+        .status(http::StatusCode::SERVICE_UNAVAILABLE)
+        .header(http::header::RETRY_AFTER, retry_after.as_secs())
+        .body(Body::Empty)
+        .unwrap()
+}
 
-    tokio::task::spawn_blocking(move || loop {
-        let mut buf = BytesMut::zeroed(512);
-        let n = read_decoder.read(buf.as_mut()).expect("fail to read");
-        if n == 0 {
-            break;
-        }
-        tx.blocking_send(buf.split_to(n).freeze())
-            .expect("fail to blocking_send");
-    });
+fn internal_server_error<T: Buf>() -> Response<Body<T, Error>> {
+    Response::builder()
+        .status(http::StatusCode::INTERNAL_SERVER_ERROR)
+        .body(Body::Empty)
+        .unwrap()
+}
+
+fn options<T: Buf>(allow: HeaderValue) -> Response<Body<T, Error>> {
+    Response::builder()
+        .status(http::StatusCode::NO_CONTENT)
+        .header(http::header::ALLOW, allow)
+        .body(Body::Empty)
+        .unwrap()
+}
 
-    rx
+fn method_not_allowed<T: Buf>(allow: HeaderValue) -> Response<Body<T, Error>> {
+    Response::builder()
+        .status(http::StatusCode::METHOD_NOT_ALLOWED)
+        .header(http::header::ALLOW, allow)
+        .body(Body::from_static(b"Method not allowed"))
+        .unwrap()
 }
@@ -0,0 +1,43 @@
+use crate::Service;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// A `Service` behind an `Arc`, cheap to clone into every connection. Clones share the
+/// configuration and the payload, so a `fill` through one is served by all of them.
+///
+/// It derefs to the `Service`, and implements the `tower` and hyper service traits when
+/// the `tower` and `server` features are enabled.
+#[derive(Debug)]
+pub struct SharedService<T>(Arc<Service<T>>);
+
+impl<T> SharedService<T> {
+    pub fn new(service: Service<T>) -> Self {
+        Self(Arc::new(service))
+    }
+}
+
+impl<T> Clone for SharedService<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Deref for SharedService<T> {
+    type Target = Service<T>;
+
+    fn deref(&self) -> &Service<T> {
+        &self.0
+    }
+}
+
+impl<T> From<Service<T>> for SharedService<T> {
+    fn from(service: Service<T>) -> Self {
+        Self::new(service)
+    }
+}
+
+impl<T> From<Arc<Service<T>>> for SharedService<T> {
+    fn from(service: Arc<Service<T>>) -> Self {
+        Self(service)
+    }
+}
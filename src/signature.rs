@@ -0,0 +1,264 @@
+use crate::base64;
+use aws_lc_rs::hmac;
+use aws_lc_rs::signature::{Ed25519KeyPair, KeyPair};
+use http::header::CONTENT_TYPE;
+use http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+const SIGNATURE_INPUT: HeaderName = HeaderName::from_static("signature-input");
+const SIGNATURE: HeaderName = HeaderName::from_static("signature");
+const REPR_DIGEST: HeaderName = HeaderName::from_static("repr-digest");
+
+/// The label of the signature in `Signature-Input` and `Signature`.
+const LABEL: &str = "sig1";
+
+#[derive(Clone)]
+enum Key {
+    Ed25519(Arc<Ed25519KeyPair>),
+    HmacSha256(Arc<hmac::Key>),
+}
+
+/// Signs full responses with HTTP Message Signatures (RFC 9421), for `Service::set_signer`.
+///
+/// The signature covers `@status`, the `sha-256` member of `Repr-Digest` and the headers set
+/// by `headers`, `Content-Type` by default, and is made when the service is filled.
+#[derive(Clone)]
+pub struct Signer {
+    key_id: String,
+    key: Key,
+    headers: Vec<HeaderName>,
+}
+
+impl std::fmt::Debug for Signer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Signer")
+            .field("key_id", &self.key_id)
+            .field("alg", &self.alg())
+            .field("headers", &self.headers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Signer {
+    /// Signs with an Ed25519 private key in PKCS#8 v1 or v2 DER, as `ed25519`.
+    pub fn ed25519(key_id: &str, pkcs8: &[u8]) -> Result<Self, InvalidSigningKey> {
+        let key =
+            Ed25519KeyPair::from_pkcs8_maybe_unchecked(pkcs8).map_err(|_| InvalidSigningKey)?;
+        Ok(Self::new(key_id, Key::Ed25519(Arc::new(key))))
+    }
+
+    /// Signs with a shared secret, as `hmac-sha256`.
+    pub fn hmac_sha256(key_id: &str, secret: &[u8]) -> Self {
+        Self::new(
+            key_id,
+            Key::HmacSha256(Arc::new(hmac::Key::new(hmac::HMAC_SHA256, secret))),
+        )
+    }
+
+    fn new(key_id: &str, key: Key) -> Self {
+        Self {
+            key_id: key_id.to_owned(),
+            key,
+            headers: vec![CONTENT_TYPE],
+        }
+    }
+
+    /// The response headers to cover besides `Repr-Digest`, replacing the default
+    /// `Content-Type`. Those missing from a payload are left out of its signature.
+    pub fn headers(mut self, headers: impl IntoIterator<Item = HeaderName>) -> Self {
+        self.headers = headers.into_iter().collect();
+        self
+    }
+
+    /// The public key of an Ed25519 signer, for verifiers.
+    pub fn public_key(&self) -> Option<&[u8]> {
+        match &self.key {
+            Key::Ed25519(key) => Some(key.public_key().as_ref()),
+            Key::HmacSha256(_) => None,
+        }
+    }
+
+    fn alg(&self) -> &'static str {
+        match self.key {
+            Key::Ed25519(_) => "ed25519",
+            Key::HmacSha256(_) => "hmac-sha256",
+        }
+    }
+
+    /// The signature of a 200 response with `headers` and the SHA-256 `digest` of its
+    /// representation, in base64.
+    pub(crate) fn sign(&self, headers: &HeaderMap, digest: &str, created: SystemTime) -> Signature {
+        let created = created
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |created| created.as_secs());
+
+        let covered: Vec<_> = self
+            .headers
+            .iter()
+            .filter_map(|name| Some((name.clone(), field_value(headers, name)?)))
+            .collect();
+        let mut components = vec![("\"@status\"".to_owned(), "200".to_owned())];
+        for (name, value) in &covered {
+            components.push((format!("\"{name}\""), value.clone()));
+        }
+        components.push((
+            "\"repr-digest\";key=\"sha-256\"".to_owned(),
+            format!(":{digest}:"),
+        ));
+
+        let names: Vec<_> = components.iter().map(|(name, _)| name.as_str()).collect();
+        let params = format!(
+            "({});created={created};keyid=\"{}\";alg=\"{}\"",
+            names.join(" "),
+            self.key_id.replace('\\', "\\\\").replace('"', "\\\""),
+            self.alg(),
+        );
+        let base = signature_base(&components, &params);
+        let signature = match &self.key {
+            Key::Ed25519(key) => key.sign(base.as_bytes()).as_ref().to_vec(),
+            Key::HmacSha256(key) => hmac::sign(key, base.as_bytes()).as_ref().to_vec(),
+        };
+        Signature {
+            input: HeaderValue::try_from(format!("{LABEL}={params}")).unwrap(),
+            signature: HeaderValue::try_from(format!("{LABEL}=:{}:", base64::encode(&signature)))
+                .unwrap(),
+            digest: format!(":{digest}:"),
+            covered,
+        }
+    }
+}
+
+/// A signature made by `Signer::sign`, along with what it covers, to check responses against.
+#[derive(Debug, Clone)]
+pub(crate) struct Signature {
+    input: HeaderValue,
+    signature: HeaderValue,
+    /// The `sha-256` member value of `Repr-Digest`, as `:<base64>:`.
+    digest: String,
+    covered: Vec<(HeaderName, String)>,
+}
+
+impl Signature {
+    /// Adds the signature to `headers`, and its digest to `Repr-Digest` unless present.
+    pub(crate) fn apply(&self, headers: &mut HeaderMap) {
+        if repr_digest_sha256(headers).is_none() {
+            let member = format!("sha-256={}", self.digest);
+            let value = match field_value(headers, &REPR_DIGEST) {
+                Some(value) => format!("{value}, {member}"),
+                None => member,
+            };
+            headers.insert(REPR_DIGEST, HeaderValue::try_from(value).unwrap());
+        }
+        headers.insert(SIGNATURE_INPUT, self.input.clone());
+        headers.insert(SIGNATURE, self.signature.clone());
+    }
+
+    /// Whether a response with `status` and `headers` still has what was signed.
+    pub(crate) fn covers(&self, status: StatusCode, headers: &HeaderMap) -> bool {
+        status == StatusCode::OK
+            && repr_digest_sha256(headers).as_deref() == Some(self.digest.as_str())
+            && self
+                .covered
+                .iter()
+                .all(|(name, value)| field_value(headers, name).as_ref() == Some(value))
+    }
+
+    /// Takes the signature out of `headers`.
+    pub(crate) fn remove(headers: &mut HeaderMap) {
+        headers.remove(SIGNATURE_INPUT);
+        headers.remove(SIGNATURE);
+    }
+}
+
+/// The value of a field in a signature base: the values of all its lines, trimmed and
+/// joined with `, `.
+fn field_value(headers: &HeaderMap, name: &HeaderName) -> Option<String> {
+    let values: Vec<_> = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .map(str::trim)
+        .collect();
+    (!values.is_empty()).then(|| values.join(", "))
+}
+
+/// The `sha-256` member of the `Repr-Digest` in `headers`.
+fn repr_digest_sha256(headers: &HeaderMap) -> Option<String> {
+    let repr_digest = field_value(headers, &REPR_DIGEST)?;
+    repr_digest.split(',').find_map(|member| {
+        let (key, value) = member.trim().split_once('=')?;
+        (key == "sha-256").then(|| value.trim().to_owned())
+    })
+}
+
+/// The signature base of RFC 9421 §2.5, which the signature is made over.
+fn signature_base(components: &[(String, String)], params: &str) -> String {
+    let mut base = String::new();
+    for (name, value) in components {
+        base.push_str(&format!("{name}: {value}\n"));
+    }
+    base.push_str(&format!("\"@signature-params\": {params}"));
+    base
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidSigningKey;
+
+impl std::fmt::Display for InvalidSigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("invalid signing key")
+    }
+}
+
+impl std::error::Error for InvalidSigningKey {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test() {
+        let signer = Signer::hmac_sha256("test-shared-secret", b"secret");
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let digest = "X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=";
+        let created = SystemTime::UNIX_EPOCH + Duration::from_secs(1618884473);
+
+        let signature = signer.sign(&headers, digest, created);
+        let params = "(\"@status\" \"content-type\" \"repr-digest\";key=\"sha-256\");\
+                      created=1618884473;keyid=\"test-shared-secret\";alg=\"hmac-sha256\"";
+        assert_eq!(signature.input, format!("sig1={params}").as_str());
+
+        let base = "\"@status\": 200\n\
+                    \"content-type\": application/json\n\
+                    \"repr-digest\";key=\"sha-256\": :X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:\n\
+                    \"@signature-params\": "
+            .to_owned()
+            + params;
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"secret");
+        let expected = base64::encode(hmac::sign(&key, base.as_bytes()).as_ref());
+        assert_eq!(signature.signature, format!("sig1=:{expected}:").as_str());
+
+        // merged into the digests already sent
+        headers.insert(REPR_DIGEST, HeaderValue::from_static("sha-512=:abc=:"));
+        signature.apply(&mut headers);
+        assert_eq!(
+            headers.get(REPR_DIGEST).unwrap(),
+            "sha-512=:abc=:, sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:"
+        );
+        assert!(signature.covers(StatusCode::OK, &headers));
+        assert!(!signature.covers(StatusCode::PARTIAL_CONTENT, &headers));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        assert!(!signature.covers(StatusCode::OK, &headers));
+
+        // missing headers are left out
+        let signature = signer.sign(&HeaderMap::new(), digest, created);
+        assert!(signature
+            .input
+            .to_str()
+            .unwrap()
+            .starts_with("sig1=(\"@status\" \"repr-digest\";key=\"sha-256\");"));
+    }
+}
@@ -0,0 +1,78 @@
+use http::HeaderValue;
+
+/// Signatures at the start of a payload, in the order they are tried.
+const SIGNATURES: [(&[u8], &str); 12] = [
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"\0asm", "application/wasm"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"wOFF", "font/woff"),
+    (b"wOF2", "font/woff2"),
+    (b"OggS", "audio/ogg"),
+    (b"\x1a\x45\xdf\xa3", "video/webm"),
+];
+
+/// Guesses the `Content-Type` of a payload from its first bytes. `None` unless one of a
+/// few well-known formats is recognized.
+pub(crate) fn sniff(head: &[u8]) -> Option<HeaderValue> {
+    let content_type = match head {
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "image/webp",
+        _ => match SIGNATURES.iter().find(|(magic, _)| head.starts_with(magic)) {
+            Some((_, content_type)) => content_type,
+            None => text(head)?,
+        },
+    };
+    Some(HeaderValue::from_static(content_type))
+}
+
+/// Markup and JSON, after any byte order mark and leading whitespace.
+fn text(head: &[u8]) -> Option<&'static str> {
+    let head = head.strip_prefix(b"\xef\xbb\xbf").unwrap_or(head);
+    let start = head.iter().position(|b| !b.is_ascii_whitespace())?;
+    let head = &head[start..];
+    let starts_with = |prefix: &[u8]| {
+        head.len() >= prefix.len() && head[..prefix.len()].eq_ignore_ascii_case(prefix)
+    };
+    if starts_with(b"<!doctype html") || starts_with(b"<html") {
+        Some("text/html; charset=utf-8")
+    } else if starts_with(b"<svg") {
+        Some("image/svg+xml")
+    } else if starts_with(b"<?xml") {
+        Some("application/xml")
+    } else if starts_with(b"{") || starts_with(b"[") {
+        Some("application/json")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let sniff = |head: &[u8]| sniff(head).map(|v| v.to_str().unwrap().to_owned());
+
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0").unwrap(), "image/png");
+        assert_eq!(sniff(b"RIFF\0\0\0\0WEBPVP8 ").unwrap(), "image/webp");
+        assert_eq!(sniff(b"RIFF\0\0\0\0WAVE"), None);
+        assert_eq!(sniff(b"\0asm\x01\0\0\0").unwrap(), "application/wasm");
+        assert_eq!(sniff(b"\x1f\x8b\x08").unwrap(), "application/gzip");
+        assert_eq!(
+            sniff(b"\xef\xbb\xbf\n  <!DOCTYPE html><p>").unwrap(),
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(sniff(b" {\"a\": 1}").unwrap(), "application/json");
+        assert_eq!(sniff(b"[1, 2]").unwrap(), "application/json");
+
+        assert_eq!(sniff(b""), None);
+        assert_eq!(sniff(b"   "), None);
+        assert_eq!(sniff(b"payload"), None);
+        assert_eq!(sniff(b"<p>fragment</p>"), None);
+    }
+}
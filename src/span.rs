@@ -0,0 +1,49 @@
+use crate::ResponseInfo;
+use http::{request, HeaderValue, StatusCode};
+use tracing::field::Empty;
+use tracing::Span;
+
+#[cfg(not(feature = "otel"))]
+pub(crate) fn call(req: &request::Parts, request_id: Option<&HeaderValue>) -> Span {
+    tracing::info_span!(
+        "call",
+        method = %req.method,
+        request_id = request_id.and_then(|id| id.to_str().ok()),
+        status = Empty,
+        encoding = Empty,
+    )
+}
+
+#[cfg(not(feature = "otel"))]
+pub(crate) fn record(span: &Span, status: StatusCode, info: &ResponseInfo) {
+    span.record("status", status.as_u16());
+    if let Some(encoding) = info.encoding {
+        span.record("encoding", encoding.as_str());
+    }
+}
+
+/// A server span named after OpenTelemetry semantic conventions, as read by
+/// `tracing-opentelemetry`.
+#[cfg(feature = "otel")]
+pub(crate) fn call(req: &request::Parts, request_id: Option<&HeaderValue>) -> Span {
+    tracing::info_span!(
+        "call",
+        otel.name = %req.method,
+        otel.kind = "server",
+        http.request.method = %req.method,
+        url.path = req.uri.path(),
+        geta.request_id = request_id.and_then(|id| id.to_str().ok()),
+        http.response.status_code = Empty,
+        geta.encoding = Empty,
+        geta.cache_hit = Empty,
+    )
+}
+
+#[cfg(feature = "otel")]
+pub(crate) fn record(span: &Span, status: StatusCode, info: &ResponseInfo) {
+    span.record("http.response.status_code", status.as_u16());
+    if let Some(encoding) = info.encoding {
+        span.record("geta.encoding", encoding.as_str());
+    }
+    span.record("geta.cache_hit", info.cache_hit);
+}
@@ -0,0 +1,228 @@
+use crate::files::{self, MANIFEST};
+use crate::listing::{self, Entry};
+use crate::{Body, ContentTypes, Error, Listing, Router, Service};
+use bytes::Bytes;
+use http::header::CONTENT_TYPE;
+use http::{HeaderMap, Request, Response};
+#[cfg(feature = "watch")]
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The files of a directory, loaded into memory and served by path. An index file is also
+/// served at the path of its directory, e.g. `/docs/` for `/docs/index.html`.
+///
+/// Pre-compressed siblings of a file (`app.js.br`, `app.js.gz`) are served as they are to
+/// clients that accept their encoding, and the file itself to the others.
+#[derive(Debug)]
+pub struct StaticDir {
+    pub(crate) root: PathBuf,
+    pub(crate) router: Router<Bytes>,
+    /// Directory routes by the route of the index file they serve, to re-fill both from one
+    /// read.
+    #[cfg(feature = "watch")]
+    pub(crate) indexes: HashMap<String, String>,
+}
+
+/// Options of a `StaticDir`, started with `StaticDir::builder()` and applied on `open`.
+#[derive(Debug, Clone)]
+pub struct StaticDirBuilder {
+    content_types: ContentTypes,
+    index_files: Vec<String>,
+    spa_fallback: bool,
+    listing: Option<Listing>,
+}
+
+impl Default for StaticDirBuilder {
+    fn default() -> Self {
+        Self {
+            content_types: ContentTypes::new(),
+            index_files: files::INDEX_FILES.map(str::to_owned).to_vec(),
+            spa_fallback: false,
+            listing: None,
+        }
+    }
+}
+
+impl StaticDirBuilder {
+    /// Picks the `Content-Type` of each file.
+    pub fn content_types(mut self, content_types: ContentTypes) -> Self {
+        self.content_types = content_types;
+        self
+    }
+
+    /// File names served at the path of their directory, the first one present winning.
+    /// `index.html` by default; empty turns index files off.
+    pub fn index_files<S: Into<String>>(mut self, names: impl IntoIterator<Item = S>) -> Self {
+        self.index_files = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Serves the index file of `root` for unknown paths without a file extension, see
+    /// `Router::with_fallback`.
+    pub fn spa_fallback(mut self, spa_fallback: bool) -> Self {
+        self.spa_fallback = spa_fallback;
+        self
+    }
+
+    /// Serves a listing of its entries, with their sizes and ETags, at the path of each
+    /// directory without an index file. The listings are generated on `open` only.
+    pub fn listing(mut self, listing: Option<Listing>) -> Self {
+        self.listing = listing;
+        self
+    }
+
+    /// Walks `root` and loads every file under it.
+    pub fn open(self, root: impl AsRef<Path>) -> io::Result<StaticDir> {
+        let root = root.as_ref().canonicalize()?;
+        let mut paths = Vec::new();
+        let mut dirs = vec![root.clone()];
+        walk(&root, &mut paths, &mut dirs)?;
+        // the ETags of a manifest would go stale as soon as a file is edited
+        paths.retain(|path| *path != root.join(MANIFEST));
+
+        let mut router = Router::new();
+        if self.spa_fallback {
+            router = router.with_fallback("/");
+        }
+        #[cfg(feature = "watch")]
+        let mut indexes = HashMap::new();
+        for path in &paths {
+            if files::is_precompressed(path, Path::is_file) {
+                continue;
+            }
+            let route = files::route(&root, path);
+            let service = load(path, &self.content_types)?;
+            if files::is_index(path, &self.index_files, Path::is_file) {
+                let dir = files::dir_route(&route).to_owned();
+                #[cfg(feature = "watch")]
+                indexes.insert(route.clone(), dir.clone());
+                router.insert(dir.clone(), files::index_service(&service, &dir));
+            }
+            router.insert(route, service);
+        }
+        if let Some(listing) = self.listing {
+            for dir in &dirs {
+                let route = files::route(&root, dir) + "/";
+                if router.get(&route).is_none() {
+                    let service = list(listing, dir, &route, &paths, &dirs, &router)?;
+                    router.insert(route, service);
+                }
+            }
+        }
+        Ok(StaticDir {
+            root,
+            router,
+            #[cfg(feature = "watch")]
+            indexes,
+        })
+    }
+}
+
+impl StaticDir {
+    /// Opens `root` with the default options.
+    pub fn open(root: impl AsRef<Path>) -> io::Result<Self> {
+        Self::builder().open(root)
+    }
+
+    pub fn builder() -> StaticDirBuilder {
+        StaticDirBuilder::default()
+    }
+
+    /// The directory, canonicalized.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn router(&self) -> &Router<Bytes> {
+        &self.router
+    }
+
+    pub async fn call<B>(&self, req: Request<B>) -> Response<Body<Bytes, Error>> {
+        self.router.call(req).await
+    }
+}
+
+pub(crate) fn walk(
+    dir: &Path,
+    paths: &mut Vec<PathBuf>,
+    dirs: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            dirs.push(entry.path());
+            walk(&entry.path(), paths, dirs)?;
+        } else if file_type.is_file() {
+            paths.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+fn load(path: &Path, content_types: &ContentTypes) -> io::Result<Service<Bytes>> {
+    let mut service = Service::new();
+    if let Some(content_type) = content_types.guess(path) {
+        service.headers.insert(CONTENT_TYPE, content_type);
+    }
+
+    fill(&service, path, HeaderMap::new())?;
+    Ok(service)
+}
+
+/// Fills `service` with the file at `path`, and its pre-compressed siblings as variants.
+pub(crate) fn fill(service: &Service<Bytes>, path: &Path, headers: HeaderMap) -> io::Result<()> {
+    let read = |path: &Path| io::Result::Ok(Bytes::from(std::fs::read(path)?));
+    let mut variants = Vec::new();
+    for (sibling, encoding) in files::precompressed(path, Path::is_file) {
+        variants.push(service.encoding_variant(encoding, read(&sibling)?, None));
+    }
+    service.fill_dated(read(path)?, None, headers, modified(path)?, variants);
+    Ok(())
+}
+
+/// The modification time of `path`, or now on platforms that don't record it.
+pub(crate) fn modified(path: &Path) -> io::Result<SystemTime> {
+    let metadata = std::fs::metadata(path)?;
+    Ok(metadata.modified().unwrap_or_else(|_| SystemTime::now()))
+}
+
+/// The listing of `dir`, mounted at `route`.
+fn list(
+    listing: Listing,
+    dir: &Path,
+    route: &str,
+    paths: &[PathBuf],
+    dirs: &[PathBuf],
+    router: &Router<Bytes>,
+) -> io::Result<Service<Bytes>> {
+    let name = |path: &Path| path.file_name().unwrap().to_string_lossy().into_owned();
+    let mut entries = Vec::new();
+    for path in paths.iter().filter(|path| path.parent() == Some(dir)) {
+        if files::is_precompressed(path, Path::is_file) {
+            continue;
+        }
+        let name = name(path);
+        entries.push(Entry {
+            size: Some(std::fs::metadata(path)?.len()),
+            etag: router
+                .get(&format!("{route}{name}"))
+                .and_then(Service::etag),
+            name,
+        });
+    }
+    for path in dirs.iter().filter(|path| path.parent() == Some(dir)) {
+        entries.push(Entry {
+            name: name(path) + "/",
+            size: None,
+            etag: None,
+        });
+    }
+
+    let mut service = Service::new();
+    service.headers.insert(CONTENT_TYPE, listing.content_type());
+    service.fill(listing::render(listing, route, entries));
+    Ok(service)
+}
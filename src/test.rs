@@ -1,7 +1,8 @@
+use crate::dictionary::DictionaryHash;
 use crate::*;
 use bytes::Bytes;
 use http::header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE, ETAG, IF_NONE_MATCH};
-use http::{HeaderValue, Request, StatusCode};
+use http::{HeaderName, HeaderValue, Request, Response, StatusCode};
 use http_body_util::BodyExt;
 
 fn test_body() -> Bytes {
@@ -65,6 +66,7 @@ async fn get() {
     }
 }
 
+#[cfg(feature = "br")]
 #[tokio::test]
 async fn br() {
     let orig_body = test_body();
@@ -152,7 +154,10 @@ async fn br() {
         let mut res = bufd.call(req).await;
 
         assert_eq!(res.status(), StatusCode::OK);
-        assert_eq!(res.headers().get(ETAG).unwrap(), orig_etag.0);
+        assert_eq!(
+            res.headers().get(ETAG).unwrap(),
+            orig_etag.with_suffix("identity").0
+        );
         assert!(res.headers().get(CONTENT_ENCODING).is_none());
         assert_eq!(
             res.body_mut().collect().await.unwrap().to_bytes(),
@@ -161,6 +166,7 @@ async fn br() {
     }
 }
 
+#[cfg(feature = "gzip")]
 #[tokio::test]
 async fn gzip() {
     let orig_body = test_body();
@@ -248,7 +254,10 @@ async fn gzip() {
         let mut res = bufd.call(req).await;
 
         assert_eq!(res.status(), StatusCode::OK);
-        assert_eq!(res.headers().get(ETAG).unwrap(), orig_etag.0);
+        assert_eq!(
+            res.headers().get(ETAG).unwrap(),
+            orig_etag.with_suffix("identity").0
+        );
         assert!(res.headers().get(CONTENT_ENCODING).is_none());
         assert_eq!(
             res.body_mut().collect().await.unwrap().to_bytes(),
@@ -257,6 +266,7 @@ async fn gzip() {
     }
 }
 
+#[cfg(feature = "deflate")]
 #[tokio::test]
 async fn deflate() {
     let orig_body = test_body();
@@ -344,7 +354,208 @@ async fn deflate() {
         let mut res = bufd.call(req).await;
 
         assert_eq!(res.status(), StatusCode::OK);
-        assert_eq!(res.headers().get(ETAG).unwrap(), orig_etag.0);
+        assert_eq!(
+            res.headers().get(ETAG).unwrap(),
+            orig_etag.with_suffix("identity").0
+        );
+        assert!(res.headers().get(CONTENT_ENCODING).is_none());
+        assert_eq!(
+            res.body_mut().collect().await.unwrap().to_bytes(),
+            orig_body
+        );
+    }
+}
+
+#[cfg(feature = "br")]
+#[tokio::test]
+async fn compression_config() {
+    let orig_body = test_body();
+
+    let orig_body_br = {
+        let mut encoder = brotli::CompressorWriter::new(vec![], 4096, 9, 22);
+        std::io::copy(&mut &orig_body[..], &mut encoder).unwrap();
+        Bytes::from(encoder.into_inner())
+    };
+
+    let mut bufd = Service::new();
+    bufd.set_encoding(Encoding::Br);
+    bufd.set_compression(CompressionConfig {
+        brotli_buffer_size: 16,
+        ..Default::default()
+    });
+    bufd.fill(orig_body_br);
+
+    let req = Request::get("/")
+        .header(ACCEPT_ENCODING, "identity")
+        .body(())
+        .unwrap();
+
+    let mut res = bufd.call(req).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().get(CONTENT_ENCODING).is_none());
+    assert_eq!(
+        res.body_mut().collect().await.unwrap().to_bytes(),
+        orig_body
+    );
+}
+
+#[tokio::test]
+async fn dictionary() {
+    let dictionary = Bytes::from_static(b"previous version of the payload");
+    let dictionary_hash = DictionaryHash::from_buf(&dictionary[..]);
+    let orig_body = test_body();
+    // the content is opaque to the service, so any bytes stand in for a real dcb stream
+    let orig_body_dcb = Bytes::from_static(b"dictionary-compressed payload");
+
+    let mut bufd = Service::new();
+    bufd.set_use_as_dictionary("/payload").unwrap();
+    bufd.fill(orig_body.clone());
+    bufd.fill_dictionary_variant(
+        &dictionary[..],
+        DictionaryEncoding::Dcb,
+        orig_body_dcb.clone(),
+    );
+
+    // GET request (matching dictionary)
+    {
+        let req = Request::get("/")
+            .header(ACCEPT_ENCODING, "gzip, br, dcb")
+            .header("available-dictionary", dictionary_hash.0.clone())
+            .body(())
+            .unwrap();
+
+        let mut res = bufd.call(req).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get(CONTENT_ENCODING).unwrap(), "dcb");
+        assert_eq!(
+            res.headers().get(ETAG).unwrap(),
+            ETag::from_buf(&orig_body_dcb[..]).0
+        );
+        assert_eq!(
+            res.headers().get("vary").unwrap(),
+            "accept-encoding, available-dictionary"
+        );
+        assert_eq!(
+            res.headers().get("use-as-dictionary").unwrap(),
+            r#"match="/payload""#
+        );
+        assert_eq!(
+            res.body_mut().collect().await.unwrap().to_bytes(),
+            orig_body_dcb
+        );
+    }
+
+    // GET request (unknown dictionary)
+    {
+        let req = Request::get("/")
+            .header(ACCEPT_ENCODING, "dcb")
+            .header("available-dictionary", ":AAAA:")
+            .body(())
+            .unwrap();
+
+        let mut res = bufd.call(req).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get(CONTENT_ENCODING).is_none());
+        assert_eq!(
+            res.body_mut().collect().await.unwrap().to_bytes(),
+            orig_body
+        );
+    }
+
+    // refill drops the variant
+    {
+        bufd.fill(orig_body.clone());
+
+        let req = Request::get("/")
+            .header(ACCEPT_ENCODING, "dcb")
+            .header("available-dictionary", dictionary_hash.0.clone())
+            .body(())
+            .unwrap();
+
+        let res = bufd.call(req).await;
+
+        assert!(res.headers().get(CONTENT_ENCODING).is_none());
+        assert!(res.headers().get("vary").is_none());
+    }
+}
+
+#[cfg(all(feature = "br", feature = "gzip", feature = "deflate"))]
+#[tokio::test]
+async fn transcode() {
+    let orig_body = test_body();
+
+    let orig_body_br = {
+        let mut encoder = brotli::CompressorWriter::new(vec![], 4096, 9, 22);
+        std::io::copy(&mut &orig_body[..], &mut encoder).unwrap();
+        Bytes::from(encoder.into_inner())
+    };
+
+    let orig_etag = ETag::from_buf(&orig_body_br[..]);
+
+    let mut bufd = Service::new();
+    bufd.set_encoding(Encoding::Br);
+    bufd.fill(orig_body_br.clone());
+
+    // GET request (accept-encoding: gzip)
+    {
+        let req = Request::get("/")
+            .header(ACCEPT_ENCODING, "gzip")
+            .body(())
+            .unwrap();
+
+        let mut res = bufd.call(req).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get(ETAG).unwrap(),
+            orig_etag.with_suffix("gzip").0
+        );
+        assert_eq!(res.headers().get(CONTENT_ENCODING).unwrap(), "gzip");
+        assert_eq!(res.headers().get("vary").unwrap(), "accept-encoding");
+
+        let body = res.body_mut().collect().await.unwrap().to_bytes();
+        let mut decoded = vec![];
+        std::io::copy(&mut flate2::read::GzDecoder::new(&body[..]), &mut decoded).unwrap();
+        assert_eq!(decoded, orig_body);
+    }
+
+    // GET request (accept-encoding: deflate, identity)
+    {
+        let req = Request::get("/")
+            .header(ACCEPT_ENCODING, "deflate, identity")
+            .body(())
+            .unwrap();
+
+        let mut res = bufd.call(req).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get(CONTENT_ENCODING).unwrap(), "deflate");
+
+        let body = res.body_mut().collect().await.unwrap().to_bytes();
+        let mut decoded = vec![];
+        std::io::copy(
+            &mut flate2::read::DeflateDecoder::new(&body[..]),
+            &mut decoded,
+        )
+        .unwrap();
+        assert_eq!(decoded, orig_body);
+    }
+
+    // GET request (accept-encoding: gzip, policy forbids compression)
+    {
+        bufd.set_compress_policy(CompressPolicy::custom(|_| false));
+
+        let req = Request::get("/")
+            .header(ACCEPT_ENCODING, "gzip")
+            .body(())
+            .unwrap();
+
+        let mut res = bufd.call(req).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
         assert!(res.headers().get(CONTENT_ENCODING).is_none());
         assert_eq!(
             res.body_mut().collect().await.unwrap().to_bytes(),
@@ -352,3 +563,3162 @@ async fn deflate() {
         );
     }
 }
+
+#[cfg(feature = "gzip")]
+#[tokio::test]
+async fn decoded_cache() {
+    let orig_body = test_body();
+
+    let orig_body_gzip = {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::best());
+        std::io::copy(&mut &orig_body[..], &mut encoder).unwrap();
+        Bytes::from(encoder.finish().unwrap())
+    };
+
+    let mut bufd: Service<Bytes> = Service::new();
+    bufd.set_encoding(Encoding::Gzip);
+    bufd.fill(orig_body_gzip.clone());
+
+    let identity = || {
+        Request::get("/")
+            .header(ACCEPT_ENCODING, "identity")
+            .body(())
+            .unwrap()
+    };
+
+    // first request streams from the decoder task
+    {
+        let mut res = bufd.call(identity()).await;
+        assert!(matches!(res.body(), Body::Stream { .. }));
+        // recorded at fill
+        assert_eq!(
+            res.headers().get(http::header::CONTENT_LENGTH).unwrap(),
+            &orig_body.len().to_string()
+        );
+        assert_eq!(
+            http_body::Body::size_hint(res.body()).exact(),
+            Some(orig_body.len() as u64)
+        );
+        assert_eq!(
+            res.body_mut().collect().await.unwrap().to_bytes(),
+            orig_body
+        );
+    }
+
+    // subsequent requests are served from memory
+    {
+        let mut res = bufd.call(identity()).await;
+        assert!(matches!(res.body(), Body::Bytes { .. }));
+        assert_eq!(
+            res.body_mut().collect().await.unwrap().to_bytes(),
+            orig_body
+        );
+    }
+
+    // fill invalidates the cache
+    {
+        bufd.fill(orig_body_gzip);
+        let res = bufd.call(identity()).await;
+        assert!(matches!(res.body(), Body::Stream { .. }));
+    }
+}
+
+#[cfg(feature = "gzip")]
+#[tokio::test]
+async fn multi_member_gzip() {
+    use http::header::CONTENT_LENGTH;
+
+    let member = |data: &[u8]| {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::best());
+        std::io::copy(&mut &data[..], &mut encoder).unwrap();
+        encoder.finish().unwrap()
+    };
+    let first = vec![b'a'; 10_000];
+    let mut gzip = member(&first);
+    gzip.extend(member(b"second"));
+    let mut orig_body = first;
+    orig_body.extend_from_slice(b"second");
+
+    let mut bufd = Service::new();
+    bufd.set_encoding(Encoding::Gzip);
+    bufd.fill(Bytes::from(gzip));
+    let identity =
+        |req: http::request::Builder| req.header(ACCEPT_ENCODING, "identity").body(()).unwrap();
+
+    // the trailer says 6, the length of the last member, but all of them are counted
+    let res = bufd.call(identity(Request::head("/"))).await;
+    assert_eq!(res.headers().get(CONTENT_LENGTH).unwrap(), "10006");
+    let res = bufd.call(identity(Request::get("/"))).await;
+    assert!(matches!(res.body(), Body::Stream { .. }));
+    assert_eq!(res.headers().get(CONTENT_LENGTH).unwrap(), "10006");
+    assert_eq!(
+        res.into_body().collect().await.unwrap().to_bytes(),
+        orig_body
+    );
+
+    let res = bufd.call(identity(Request::head("/"))).await;
+    assert_eq!(
+        res.headers().get(CONTENT_LENGTH).unwrap(),
+        &orig_body.len().to_string()
+    );
+    let res = bufd.call(identity(Request::get("/"))).await;
+    assert_eq!(res.headers().get(CONTENT_LENGTH).unwrap(), "10006");
+    assert_eq!(
+        res.into_body().collect().await.unwrap().to_bytes(),
+        orig_body
+    );
+}
+
+#[cfg(not(feature = "br"))]
+#[tokio::test]
+async fn decoder_compiled_out() {
+    let orig_body = Bytes::from_static(b"not really brotli");
+
+    let mut bufd = Service::new();
+    bufd.set_encoding(Encoding::Br);
+    bufd.fill(orig_body.clone());
+
+    let req = Request::get("/")
+        .header(ACCEPT_ENCODING, "identity")
+        .body(())
+        .unwrap();
+
+    let mut res = bufd.call(req).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get(CONTENT_ENCODING).unwrap(), "br");
+    assert_eq!(
+        res.body_mut().collect().await.unwrap().to_bytes(),
+        orig_body
+    );
+}
+
+#[cfg(feature = "gzip")]
+#[tokio::test]
+async fn max_decoded_size() {
+    let orig_body = Bytes::from(vec![b'a'; 1 << 20]);
+
+    let orig_body_gzip = {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::best());
+        std::io::copy(&mut &orig_body[..], &mut encoder).unwrap();
+        Bytes::from(encoder.finish().unwrap())
+    };
+
+    let mut bufd = Service::new();
+    bufd.set_encoding(Encoding::Gzip);
+    bufd.set_max_decoded_size(Some(4096));
+    bufd.fill(orig_body_gzip);
+
+    let identity = || {
+        Request::get("/")
+            .header(ACCEPT_ENCODING, "identity")
+            .body(())
+            .unwrap()
+    };
+
+    let mut res = bufd.call(identity()).await;
+    let err = res.body_mut().collect().await.unwrap_err();
+    assert!(matches!(err, Error::DecodedSizeExceeded));
+    assert_eq!(bufd.metrics().decoded_size_exceeded, 1);
+
+    // a failed stream is not cached
+    let mut res = bufd.call(identity()).await;
+    assert!(matches!(res.body(), Body::Stream { .. }));
+    res.body_mut().collect().await.unwrap_err();
+    assert_eq!(bufd.metrics().decoded_size_exceeded, 2);
+}
+
+#[tokio::test]
+async fn last_modified() {
+    use http::header::{IF_MODIFIED_SINCE, LAST_MODIFIED};
+    use std::time::{Duration, SystemTime};
+
+    let last_modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+    let bufd = Service::new();
+    bufd.fill(test_body());
+    assert!(bufd
+        .call(Request::get("/").body(()).unwrap())
+        .await
+        .headers()
+        .contains_key(LAST_MODIFIED));
+
+    bufd.set_last_modified(Some(last_modified));
+
+    // GET request
+    {
+        let req = Request::get("/").body(()).unwrap();
+
+        let res = bufd.call(req).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get(LAST_MODIFIED).unwrap(),
+            "Tue, 14 Nov 2023 22:13:20 GMT"
+        );
+    }
+
+    // GET If-Modified-Since (not modified)
+    {
+        let req = Request::get("/")
+            .header(IF_MODIFIED_SINCE, "Tue, 14 Nov 2023 22:13:20 GMT")
+            .body(())
+            .unwrap();
+
+        let res = bufd.call(req).await;
+
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    // GET If-Modified-Since (modified)
+    {
+        let req = Request::get("/")
+            .header(IF_MODIFIED_SINCE, "Tue, 14 Nov 2023 22:13:19 GMT")
+            .body(())
+            .unwrap();
+
+        let res = bufd.call(req).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    // If-None-Match takes precedence over If-Modified-Since
+    {
+        let req = Request::get("/")
+            .header(IF_NONE_MATCH, r#""other""#)
+            .header(IF_MODIFIED_SINCE, "Tue, 14 Nov 2023 22:13:20 GMT")
+            .body(())
+            .unwrap();
+
+        let res = bufd.call(req).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn precondition() {
+    use http::header::{IF_MATCH, IF_UNMODIFIED_SINCE};
+    use std::time::{Duration, SystemTime};
+
+    let orig_body = test_body();
+    let orig_etag = ETag::from_buf(&orig_body[..]);
+
+    let bufd = Service::new();
+
+    // If-Match on an empty service
+    {
+        let req = Request::get("/").header(IF_MATCH, "*").body(()).unwrap();
+        let res = bufd.call(req).await;
+        assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+    }
+
+    bufd.fill(orig_body);
+    bufd.set_last_modified(Some(
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+    ));
+
+    let status = |name, value: &'static str| {
+        let req = Request::get("/").header(name, value).body(()).unwrap();
+        async { bufd.call(req).await.status() }
+    };
+
+    assert_eq!(status(IF_MATCH, "*").await, StatusCode::OK);
+    assert_eq!(
+        status(IF_MATCH, r#""other""#).await,
+        StatusCode::PRECONDITION_FAILED
+    );
+    assert_eq!(
+        status(IF_UNMODIFIED_SINCE, "Tue, 14 Nov 2023 22:13:20 GMT").await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        status(IF_UNMODIFIED_SINCE, "Tue, 14 Nov 2023 22:13:19 GMT").await,
+        StatusCode::PRECONDITION_FAILED
+    );
+
+    // matching If-Match
+    {
+        let req = Request::get("/")
+            .header(IF_MATCH, orig_etag.0.clone())
+            .header(IF_UNMODIFIED_SINCE, "Tue, 14 Nov 2023 22:13:19 GMT")
+            .body(())
+            .unwrap();
+        let res = bufd.call(req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn weak_etag() {
+    use http::header::IF_MATCH;
+
+    let orig_body = test_body();
+    let orig_etag = ETag::from_buf(&orig_body[..]);
+    let weak_etag = orig_etag.clone().into_weak();
+
+    let mut bufd = Service::new();
+    bufd.set_weak_etag(true);
+    bufd.fill(orig_body);
+
+    // GET request
+    {
+        let req = Request::get("/").body(()).unwrap();
+        let res = bufd.call(req).await;
+        assert_eq!(
+            res.headers().get(ETAG).unwrap(),
+            weak_etag.as_header_value()
+        );
+    }
+
+    // GET If-None-Match (weak comparison)
+    {
+        let req = Request::get("/")
+            .header(IF_NONE_MATCH, orig_etag.as_header_value())
+            .body(())
+            .unwrap();
+        let res = bufd.call(req).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    // GET If-Match (strong comparison never matches a weak validator)
+    {
+        let req = Request::get("/")
+            .header(IF_MATCH, weak_etag.as_header_value())
+            .body(())
+            .unwrap();
+        let res = bufd.call(req).await;
+        assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+    }
+}
+
+#[tokio::test]
+async fn if_none_match_any() {
+    let bufd = Service::new();
+    bufd.fill(test_body());
+
+    for req in [Request::get("/"), Request::head("/")] {
+        let req = req.header(IF_NONE_MATCH, "*").body(()).unwrap();
+        let res = bufd.call(req).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    }
+}
+
+#[tokio::test]
+async fn fill_with_etag() {
+    let etag: ETag = r#""build-1234""#.parse().unwrap();
+
+    let bufd = Service::new();
+    bufd.fill_with_etag(test_body(), etag.clone());
+
+    let req = Request::get("/").body(()).unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.headers().get(ETAG).unwrap(), etag.as_header_value());
+
+    let req = Request::get("/")
+        .header(IF_NONE_MATCH, r#""build-1234""#)
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+}
+
+#[tokio::test]
+async fn generation_etag() {
+    let mut bufd = Service::new();
+    bufd.set_etag_mode(ETagMode::Generation);
+
+    async fn etag(bufd: &Service<Bytes>) -> HeaderValue {
+        let req = Request::get("/").body(()).unwrap();
+        bufd.call(req).await.headers().get(ETAG).unwrap().clone()
+    }
+
+    bufd.fill(test_body());
+    let first = etag(&bufd).await;
+    assert!(first.to_str().unwrap().ends_with(r#"-1""#));
+
+    bufd.fill(test_body());
+    let second = etag(&bufd).await;
+    assert!(second.to_str().unwrap().ends_with(r#"-2""#));
+    assert_ne!(first, second);
+
+    // a fresh instance does not reuse validators
+    let mut other = Service::new();
+    other.set_etag_mode(ETagMode::Generation);
+    other.fill(test_body());
+    assert_ne!(etag(&other).await, first);
+}
+
+#[tokio::test]
+async fn expires() {
+    use http::header::{CACHE_CONTROL, EXPIRES};
+    use std::time::{Duration, SystemTime};
+
+    let mut bufd = Service::new();
+    bufd.set_cache_control(
+        CacheControl::new()
+            .public()
+            .max_age(Duration::from_secs(3600)),
+    );
+    bufd.fill(test_body());
+
+    // Expires is opt-in
+    {
+        let req = Request::get("/").body(()).unwrap();
+        let res = bufd.call(req).await;
+        assert_eq!(
+            res.headers().get(CACHE_CONTROL).unwrap(),
+            "public, max-age=3600"
+        );
+        assert!(res.headers().get(EXPIRES).is_none());
+    }
+
+    bufd.set_expires(true);
+
+    // Expires follows max-age
+    {
+        let req = Request::get("/").body(()).unwrap();
+        let res = bufd.call(req).await;
+        let expires = res.headers().get(EXPIRES).unwrap().to_str().unwrap();
+        let expires = httpdate::parse_http_date(expires).unwrap();
+        let max_age = expires.duration_since(SystemTime::now()).unwrap();
+        assert!(max_age > Duration::from_secs(3590) && max_age <= Duration::from_secs(3600));
+    }
+
+    // uncacheable responses are already expired
+    {
+        bufd.set_cache_control(CacheControl::new().no_store());
+        let req = Request::get("/").body(()).unwrap();
+        let res = bufd.call(req).await;
+        assert_eq!(
+            res.headers().get(EXPIRES).unwrap(),
+            "Thu, 01 Jan 1970 00:00:00 GMT"
+        );
+    }
+}
+
+#[tokio::test]
+async fn not_modified_headers() {
+    use http::header::{CACHE_CONTROL, CONTENT_LOCATION, EXPIRES, LAST_MODIFIED, VARY};
+    use std::time::Duration;
+
+    let mut bufd = Service::new();
+    bufd.headers
+        .insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+    bufd.headers
+        .insert(CONTENT_LOCATION, HeaderValue::from_static("/index.txt"));
+    bufd.set_encoding(Encoding::Gzip);
+    bufd.set_cache_control(CacheControl::new().max_age(Duration::from_secs(60)));
+    bufd.set_expires(true);
+    bufd.fill(test_body());
+
+    let req = Request::get("/").body(()).unwrap();
+    let ok = bufd.call(req).await;
+    let etag = ok.headers().get(ETAG).unwrap().clone();
+
+    let req = Request::get("/")
+        .header(IF_NONE_MATCH, etag.clone())
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(res.headers().get(ETAG).unwrap(), etag);
+    assert_eq!(res.headers().get(CACHE_CONTROL).unwrap(), "max-age=60");
+    assert_eq!(res.headers().get(CONTENT_LOCATION).unwrap(), "/index.txt");
+    assert_eq!(res.headers().get(VARY).unwrap(), "accept-encoding");
+    assert!(res.headers().get(EXPIRES).is_some());
+    assert!(res.headers().get(CONTENT_TYPE).is_none());
+    assert!(res.headers().get(LAST_MODIFIED).is_none());
+}
+
+#[tokio::test]
+async fn cdn_cache_control() {
+    use http::header::CACHE_CONTROL;
+    use std::time::Duration;
+
+    let mut bufd = Service::new();
+    bufd.set_cache_control(CacheControl::new().max_age(Duration::from_secs(60)));
+    bufd.set_cdn_cache_control(
+        CacheControl::new()
+            .max_age(Duration::from_secs(86400))
+            .stale_if_error(Duration::from_secs(3600)),
+    );
+    bufd.fill(test_body());
+
+    let req = Request::get("/").body(()).unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.headers().get(CACHE_CONTROL).unwrap(), "max-age=60");
+    for name in ["cdn-cache-control", "surrogate-control"] {
+        assert_eq!(
+            res.headers().get(name).unwrap(),
+            "max-age=86400, stale-if-error=3600"
+        );
+    }
+
+    let etag = res.headers().get(ETAG).unwrap().clone();
+    let req = Request::get("/")
+        .header(IF_NONE_MATCH, etag)
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert!(res.headers().get("cdn-cache-control").is_some());
+}
+
+#[cfg(feature = "gzip")]
+#[tokio::test]
+async fn etag_suffix() {
+    let orig_body_gzip = {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::best());
+        std::io::copy(&mut &test_body()[..], &mut encoder).unwrap();
+        Bytes::from(encoder.finish().unwrap())
+    };
+    let orig_etag = ETag::from_buf(&orig_body_gzip[..]);
+
+    let mut bufd = Service::new();
+    bufd.set_encoding(Encoding::Gzip);
+    bufd.fill(orig_body_gzip);
+
+    async fn call(bufd: &Service<Bytes>, if_none_match: &HeaderValue) -> StatusCode {
+        let req = Request::get("/")
+            .header(ACCEPT_ENCODING, "identity")
+            .header(IF_NONE_MATCH, if_none_match)
+            .body(())
+            .unwrap();
+        bufd.call(req).await.status()
+    }
+
+    // the decoded representation only revalidates against its own validator
+    let decoded_etag = orig_etag.with_suffix("identity");
+    assert_eq!(
+        call(&bufd, decoded_etag.as_header_value()).await,
+        StatusCode::NOT_MODIFIED
+    );
+    assert_eq!(
+        call(&bufd, orig_etag.as_header_value()).await,
+        StatusCode::OK
+    );
+
+    // shared validator
+    bufd.set_etag_suffix(false);
+    assert_eq!(
+        call(&bufd, orig_etag.as_header_value()).await,
+        StatusCode::NOT_MODIFIED
+    );
+}
+
+#[tokio::test]
+async fn immutable_asset() {
+    use http::header::{CACHE_CONTROL, VARY};
+
+    let bufd = Service::immutable_asset();
+    bufd.fill(test_body());
+
+    let req = Request::get("/").body(()).unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(
+        res.headers().get(CACHE_CONTROL).unwrap(),
+        "public, max-age=31536000, immutable"
+    );
+    assert_eq!(res.headers().get(VARY).unwrap(), "accept-encoding");
+    let etag = res.headers().get(ETAG).unwrap().clone();
+    assert!(!etag.as_bytes().starts_with(b"W/"));
+
+    let req = Request::get("/")
+        .header(IF_NONE_MATCH, etag)
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(
+        res.headers().get(CACHE_CONTROL).unwrap(),
+        "public, max-age=31536000, immutable"
+    );
+}
+
+#[tokio::test]
+async fn range() {
+    use http::header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, IF_RANGE, RANGE};
+
+    let orig_body = test_body();
+    let len = orig_body.len();
+    let bufd = Service::new();
+    bufd.fill(orig_body.clone());
+    let etag = ETag::from_buf(&orig_body[..]);
+
+    async fn get(
+        bufd: &Service<Bytes>,
+        headers: &[(HeaderName, &str)],
+    ) -> Response<Body<Bytes, Error>> {
+        let mut req = Request::get("/");
+        for (name, value) in headers {
+            req = req.header(name, *value);
+        }
+        bufd.call(req.body(()).unwrap()).await
+    }
+
+    // single range
+    {
+        let mut res = get(&bufd, &[(RANGE, "bytes=10-19")]).await;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            res.headers().get(CONTENT_RANGE).unwrap().to_str().unwrap(),
+            format!("bytes 10-19/{len}")
+        );
+        assert_eq!(
+            res.body_mut().collect().await.unwrap().to_bytes(),
+            orig_body.slice(10..20)
+        );
+    }
+
+    // HEAD probes a range without a body
+    {
+        let req = Request::head("/")
+            .header(RANGE, "bytes=10-19")
+            .body(())
+            .unwrap();
+        let mut res = bufd.call(req).await;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            res.headers().get(CONTENT_RANGE).unwrap().to_str().unwrap(),
+            format!("bytes 10-19/{len}")
+        );
+        assert_eq!(res.headers().get(CONTENT_LENGTH).unwrap(), "10");
+        assert!(res
+            .body_mut()
+            .collect()
+            .await
+            .unwrap()
+            .to_bytes()
+            .is_empty());
+    }
+
+    // suffix range
+    {
+        let mut res = get(&bufd, &[(RANGE, "bytes=-5")]).await;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            res.body_mut().collect().await.unwrap().to_bytes(),
+            orig_body.slice(len - 5..)
+        );
+    }
+
+    // unsatisfiable
+    {
+        let range = format!("bytes={len}-");
+        let res = get(&bufd, &[(RANGE, &range)]).await;
+        assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(
+            res.headers().get(CONTENT_RANGE).unwrap().to_str().unwrap(),
+            format!("bytes */{len}")
+        );
+    }
+
+    // an empty payload can't satisfy any range
+    {
+        let bufd = Service::new();
+        bufd.fill(Bytes::new());
+        for range in ["bytes=0-", "bytes=-1"] {
+            let res = get(&bufd, &[(RANGE, range)]).await;
+            assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+            assert_eq!(res.headers().get(CONTENT_RANGE).unwrap(), "bytes */0");
+        }
+    }
+
+    // multiple ranges
+    {
+        let mut res = get(&bufd, &[(RANGE, "bytes=0-1,5-6")]).await;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        let content_type = res.headers().get(CONTENT_TYPE).unwrap().to_str().unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap()
+            .to_string();
+        let body = res.body_mut().collect().await.unwrap().to_bytes();
+        let expected = [
+            format!("--{boundary}\r\nContent-Range: bytes 0-1/{len}\r\n\r\n").as_bytes(),
+            &orig_body[0..2],
+            format!("\r\n--{boundary}\r\nContent-Range: bytes 5-6/{len}\r\n\r\n").as_bytes(),
+            &orig_body[5..7],
+            format!("\r\n--{boundary}--\r\n").as_bytes(),
+        ]
+        .concat();
+        assert_eq!(body, expected);
+    }
+
+    // overlapping ranges are merged
+    {
+        let mut res = get(&bufd, &[(RANGE, "bytes=5-9,0-4,3-6")]).await;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            res.headers().get(CONTENT_RANGE).unwrap().to_str().unwrap(),
+            format!("bytes 0-9/{len}")
+        );
+        assert_eq!(
+            res.body_mut().collect().await.unwrap().to_bytes(),
+            orig_body.slice(0..10)
+        );
+    }
+
+    // too many ranges
+    {
+        let mut bufd = Service::new();
+        bufd.set_max_ranges(2);
+        bufd.fill(orig_body.clone());
+        let res = get(&bufd, &[(RANGE, "bytes=0-0,2-2,4-4")]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = get(&bufd, &[(RANGE, "bytes=0-0,1-1,4-4")]).await;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    }
+
+    // If-Range
+    {
+        let tag = etag.as_header_value().to_str().unwrap();
+        let res = get(&bufd, &[(RANGE, "bytes=0-0"), (IF_RANGE, tag)]).await;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+
+        let mut res = get(&bufd, &[(RANGE, "bytes=0-0"), (IF_RANGE, r#""stale""#)]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.body_mut().collect().await.unwrap().to_bytes(),
+            orig_body
+        );
+    }
+
+    // encoded payloads are served whole
+    {
+        let mut bufd = Service::new();
+        bufd.set_encoding(Encoding::Br);
+        bufd.fill(orig_body.clone());
+        let res = get(&bufd, &[(RANGE, "bytes=0-0")]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get(ACCEPT_RANGES).unwrap(), "none");
+    }
+
+    // Accept-Ranges
+    {
+        let res = bufd.call(Request::head("/").body(()).unwrap()).await;
+        assert_eq!(res.headers().get(ACCEPT_RANGES).unwrap(), "bytes");
+
+        let mut bufd = Service::new();
+        bufd.set_accept_ranges(false);
+        bufd.fill(orig_body.clone());
+        let res = get(&bufd, &[(RANGE, "bytes=0-0")]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get(ACCEPT_RANGES).unwrap(), "none");
+    }
+}
+
+#[tokio::test]
+async fn frame_size() {
+    use bytes::Buf;
+
+    let orig_body = test_body();
+    let mut bufd = Service::new();
+    bufd.set_frame_size(Some(1000));
+    bufd.fill(orig_body.clone());
+
+    let mut res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(
+        http_body::Body::size_hint(res.body()).exact(),
+        Some(orig_body.len() as u64)
+    );
+    let mut frames = 0;
+    let mut body = Vec::new();
+    while let Some(frame) = res.body_mut().frame().await {
+        let data = frame.unwrap().into_data().unwrap();
+        assert!(data.remaining() <= 1000);
+        body.extend_from_slice(data.chunk());
+        frames += 1;
+    }
+    assert_eq!(frames, orig_body.len().div_ceil(1000));
+    assert_eq!(body, orig_body);
+}
+
+#[cfg(feature = "fs")]
+#[tokio::test]
+async fn from_file() {
+    let orig_body = test_body();
+    let path = std::env::temp_dir().join(format!("geta-from-file-{}", std::process::id()));
+    std::fs::write(&path, &orig_body).unwrap();
+
+    let body: Body<Bytes, std::io::Error> = Body::from_file(&path).await.unwrap();
+    assert_eq!(
+        http_body::Body::size_hint(&body).exact(),
+        Some(orig_body.len() as u64)
+    );
+    assert_eq!(body.collect().await.unwrap().to_bytes(), orig_body);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(Body::<Bytes, std::io::Error>::from_file(&path)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn from_stream() {
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    struct Iter(std::vec::IntoIter<Result<Bytes, &'static str>>);
+
+    impl futures_core::Stream for Iter {
+        type Item = Result<Bytes, &'static str>;
+
+        fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            Poll::Ready(self.0.next())
+        }
+    }
+
+    let chunks = vec![Ok(Bytes::from_static(b"ge")), Ok(Bytes::from_static(b"ta"))];
+    let body: Body<Bytes, _> = Body::from_stream(Iter(chunks.into_iter()));
+    assert_eq!(body.collect().await.unwrap().to_bytes(), "geta");
+
+    let chunks = vec![Ok(Bytes::from_static(b"ge")), Err("broken")];
+    let body: Body<Bytes, _> = Body::from_stream(Iter(chunks.into_iter()));
+    assert_eq!(body.collect().await.unwrap_err(), "broken");
+
+    // still `Sync`, so it can be boxed
+    let chunks = vec![Ok(Bytes::from_static(b"geta"))];
+    let body = Body::<Bytes, _>::from_stream(Iter(chunks.into_iter())).boxed();
+    assert_eq!(body.collect().await.unwrap().to_bytes(), "geta");
+}
+
+#[tokio::test]
+async fn inspect() {
+    use std::sync::{Arc, Mutex};
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let record = {
+        let events = events.clone();
+        move |event| events.lock().unwrap().push(event)
+    };
+
+    let mut bufd = Service::new();
+    bufd.set_frame_size(Some(4));
+    bufd.fill(Bytes::from_static(b"0123456789"));
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    let body = res.into_body().inspect(record.clone());
+    assert_eq!(body.collect().await.unwrap().to_bytes(), "0123456789");
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            BodyEvent::Frame(4),
+            BodyEvent::Frame(4),
+            BodyEvent::Frame(2),
+            BodyEvent::End {
+                total: 10,
+                complete: true
+            }
+        ]
+    );
+
+    // dropped before the end
+    events.lock().unwrap().clear();
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    let mut body = res.into_body().inspect(record);
+    body.frame().await.unwrap().unwrap();
+    drop(body);
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            BodyEvent::Frame(4),
+            BodyEvent::End {
+                total: 4,
+                complete: false
+            }
+        ]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn throttled() {
+    use bytes::Buf;
+    use std::time::Duration;
+
+    let orig_body = Bytes::from(vec![b'a'; 10_000]);
+    let bufd = Service::new();
+    bufd.fill(orig_body.clone());
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+
+    let start = tokio::time::Instant::now();
+    let mut body = res.into_body().throttled(1000);
+    let mut received = 0;
+    while let Some(frame) = body.frame().await {
+        let data = frame.unwrap().into_data().unwrap();
+        assert!(data.remaining() <= 100);
+        received += data.remaining();
+    }
+    assert_eq!(received, orig_body.len());
+
+    // the first burst is free, the rest arrives at 1000 bytes per second
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(9900), "{elapsed:?}");
+    assert!(elapsed < Duration::from_millis(10100), "{elapsed:?}");
+}
+
+#[tokio::test]
+async fn max_response_size() {
+    let orig_body = Bytes::from(vec![b'a'; 1 << 16]);
+
+    let mut bufd = Service::new();
+    bufd.set_max_response_size(Some(1 << 16));
+    bufd.fill(orig_body.clone());
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    bufd.set_max_response_size(Some(1000));
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let res = bufd.call(Request::head("/").body(()).unwrap()).await;
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    // a smaller range still fits
+    let req = Request::get("/")
+        .header(http::header::RANGE, "bytes=0-999")
+        .body(())
+        .unwrap();
+    assert_eq!(bufd.call(req).await.status(), StatusCode::PARTIAL_CONTENT);
+}
+
+#[cfg(all(feature = "br", feature = "gzip"))]
+#[tokio::test]
+async fn max_response_size_stream() {
+    // incompressible, so that it stays over the limit once transcoded
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let orig_body: Bytes = std::iter::repeat_with(|| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state as u8
+    })
+    .take(1 << 12)
+    .collect();
+    let orig_body_br = {
+        let mut encoder = brotli::CompressorWriter::new(vec![], 4096, 9, 22);
+        std::io::copy(&mut &orig_body[..], &mut encoder).unwrap();
+        Bytes::from(encoder.into_inner())
+    };
+
+    let mut bufd = Service::new();
+    bufd.set_encoding(Encoding::Br);
+    bufd.set_max_response_size(Some(1000));
+    bufd.fill(orig_body_br);
+
+    // the decoded size is recorded at fill
+    let req = Request::get("/")
+        .header(ACCEPT_ENCODING, "identity")
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let req = Request::head("/")
+        .header(ACCEPT_ENCODING, "identity")
+        .body(())
+        .unwrap();
+    assert_eq!(
+        bufd.call(req).await.status(),
+        StatusCode::INTERNAL_SERVER_ERROR
+    );
+
+    // the transcoded size is only known as it flows
+    let req = Request::get("/")
+        .header(ACCEPT_ENCODING, "gzip")
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let err = res.into_body().collect().await.unwrap_err();
+    assert!(matches!(err, Error::ResponseSizeExceeded));
+}
+
+#[tokio::test]
+async fn boxed() {
+    let orig_body = test_body();
+    let bufd = Service::new();
+    bufd.fill(orig_body.clone());
+
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    let body: http_body_util::combinators::BoxBody<Bytes, Error> = res.into_body().boxed();
+    assert_eq!(body.collect().await.unwrap().to_bytes(), orig_body);
+
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    let body = res.into_body().boxed_unsync();
+    assert_eq!(body.collect().await.unwrap().to_bytes(), orig_body);
+}
+
+#[tokio::test]
+async fn into_data_stream() {
+    use futures_core::Stream;
+    use std::pin::Pin;
+
+    let orig_body = test_body();
+    let bufd = Service::new();
+    bufd.fill(orig_body.clone());
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+
+    let mut stream = res.into_body().into_data_stream();
+    let next = std::future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx));
+    // the stored payload itself, not a copy
+    match next.await {
+        Some(Ok(BodyChunk::Buf(buf))) => assert_eq!(buf.as_ptr(), orig_body.as_ptr()),
+        other => panic!("unexpected chunk: {other:?}"),
+    }
+    let next = std::future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx));
+    assert!(next.await.is_none());
+}
+
+#[tokio::test]
+async fn abort_on_drop() {
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<Bytes, Error>>(1);
+    let task = tokio::spawn(async move {
+        let _tx = tx;
+        std::future::pending::<()>().await
+    });
+    let body: Body<Bytes, Error> = Body::from_task(rx, None, task.abort_handle());
+    drop(body);
+    assert!(task.await.unwrap_err().is_cancelled());
+}
+
+#[cfg(feature = "tower")]
+#[tokio::test]
+async fn tower() {
+    use tower_service::Service as TowerService;
+
+    let orig_body = test_body();
+    let bufd = Service::new();
+    bufd.fill(orig_body.clone());
+
+    let req = Request::get("/").body(()).unwrap();
+    let res = TowerService::call(&mut &bufd, req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let mut shared = SharedService::new(bufd);
+    std::future::poll_fn(|cx| TowerService::<Request<()>>::poll_ready(&mut shared, cx))
+        .await
+        .unwrap();
+    let future = TowerService::call(&mut shared, Request::get("/").body(()).unwrap());
+    let res = tokio::spawn(future).await.unwrap().unwrap();
+    assert_eq!(
+        res.into_body().collect().await.unwrap().to_bytes(),
+        orig_body
+    );
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn serve() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let bufd = Service::new();
+    bufd.fill(Bytes::from_static(b"hello"));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(serve_listener(listener, bufd));
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: identity\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut res = String::new();
+    stream.read_to_string(&mut res).await.unwrap();
+    assert!(res.starts_with("HTTP/1.1 200 OK\r\n"), "{res}");
+    assert!(res.ends_with("\r\n\r\nhello"), "{res}");
+}
+
+#[cfg(feature = "axum")]
+#[tokio::test]
+async fn axum() {
+    use ::axum::handler::Handler;
+
+    let orig_body = test_body();
+    let shared = SharedService::new(Service::new());
+    shared.fill(orig_body.clone());
+
+    let req = Request::get("/").body(::axum::body::Body::empty()).unwrap();
+    let res = Handler::<(), ()>::call(shared, req, ()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.into_body().collect().await.unwrap().to_bytes(),
+        orig_body
+    );
+}
+
+#[cfg(feature = "fs")]
+#[tokio::test]
+async fn fill_from_file() {
+    use http::header::{IF_MODIFIED_SINCE, LAST_MODIFIED};
+    use std::time::{Duration, SystemTime};
+
+    let path = std::env::temp_dir().join(format!("geta-fill-from-file-{}", std::process::id()));
+    let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(784111777);
+    std::fs::write(&path, "body").unwrap();
+    std::fs::File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_modified(mtime)
+        .unwrap();
+
+    let service = Service::<Bytes>::new();
+    service.fill_from_file(&path).await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let res = service.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(
+        res.headers().get(LAST_MODIFIED).unwrap(),
+        "Sun, 06 Nov 1994 08:49:37 GMT"
+    );
+    assert_eq!(res.into_body().collect().await.unwrap().to_bytes(), "body");
+
+    let res = service
+        .call(
+            Request::get("/")
+                .header(IF_MODIFIED_SINCE, "Sun, 06 Nov 1994 08:49:37 GMT")
+                .body(())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+    assert!(service.fill_from_file(&path).await.is_err());
+}
+
+#[cfg(feature = "mmap")]
+#[tokio::test]
+async fn mmap() {
+    use http::header::{CONTENT_RANGE, RANGE};
+
+    let orig_body = test_body();
+    let path = std::env::temp_dir().join(format!("geta-mmap-{}", std::process::id()));
+    std::fs::write(&path, &orig_body).unwrap();
+    // SAFETY: the file is not written to again
+    let body = unsafe { MmapBuf::open(&path) }.unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let service = Service::new();
+    service.fill(body);
+    assert_eq!(
+        service.etag().unwrap(),
+        ETag::from_buf(&orig_body[..]).as_header_value()
+    );
+
+    let res = service.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(
+        res.into_body().collect().await.unwrap().to_bytes(),
+        orig_body
+    );
+
+    let res = service
+        .call(
+            Request::get("/")
+                .header(RANGE, "bytes=10-19")
+                .body(())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        res.headers().get(CONTENT_RANGE).unwrap(),
+        format!("bytes 10-19/{}", orig_body.len()).as_str()
+    );
+    assert_eq!(
+        res.into_body().collect().await.unwrap().to_bytes(),
+        orig_body[10..20]
+    );
+}
+
+#[tokio::test]
+async fn router() {
+    let mut router = Router::new();
+    router.insert("/app.js", Service::new());
+    router.insert("/app.css", Service::new());
+    router
+        .get("/app.js")
+        .unwrap()
+        .fill(Bytes::from_static(b"js"));
+    router
+        .get("/app.css")
+        .unwrap()
+        .fill(Bytes::from_static(b"css"));
+
+    for (path, body) in [("/app.js", "js"), ("/app.css", "css")] {
+        let res = router.call(Request::get(path).body(()).unwrap()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.into_body().collect().await.unwrap().to_bytes(), body);
+    }
+
+    let res = router
+        .call(Request::get("/index.html").body(()).unwrap())
+        .await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    // the query is not part of the key
+    let res = router
+        .call(Request::get("/app.js?v=2").body(()).unwrap())
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn router_cors() {
+    use http::header::{
+        ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_REQUEST_METHOD,
+        ORIGIN,
+    };
+
+    let mut router = Router::new().with_cors(Cors::new().allow_origin(AllowOrigin::Exact(
+        HeaderValue::from_static("https://a.example"),
+    )));
+    let service = Service::new();
+    service.fill(test_body());
+    router.insert("/a", service);
+
+    for path in ["/a", "/missing"] {
+        let req = Request::get(path)
+            .header(ORIGIN, "https://a.example")
+            .body(())
+            .unwrap();
+        let res = router.call(req).await;
+        assert_eq!(
+            res.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "https://a.example"
+        );
+    }
+
+    let req = Request::options("/a")
+        .header(ORIGIN, "https://a.example")
+        .header(ACCESS_CONTROL_REQUEST_METHOD, "HEAD")
+        .body(())
+        .unwrap();
+    let res = router.call(req).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert_eq!(
+        res.headers().get(ACCESS_CONTROL_ALLOW_METHODS).unwrap(),
+        "HEAD"
+    );
+}
+
+#[tokio::test]
+async fn router_prefix() {
+    use http::header::CONTENT_LOCATION;
+
+    let mut router = Router::new().with_prefix("/static/");
+    let mut service = Service::new();
+    service
+        .headers
+        .insert(CONTENT_LOCATION, HeaderValue::from_static("/app.js"));
+    service.fill(Bytes::from_static(b"js"));
+    router.insert("/app.js", service);
+    let index = Service::new();
+    index.fill(Bytes::from_static(b"index"));
+    router.insert("/", index);
+
+    let res = router
+        .call(Request::get("/static/app.js").body(()).unwrap())
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.headers().get(CONTENT_LOCATION).unwrap(),
+        "/static/app.js"
+    );
+
+    let res = router.call(Request::get("/static").body(()).unwrap()).await;
+    assert_eq!(res.into_body().collect().await.unwrap().to_bytes(), "index");
+
+    for path in ["/app.js", "/staticapp.js"] {
+        let res = router.call(Request::get(path).body(()).unwrap()).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
+
+#[tokio::test]
+async fn router_fingerprint() {
+    use http::header::CACHE_CONTROL;
+
+    let mut router = Router::new();
+    let js = Service::immutable_asset();
+    js.fill(Bytes::from_static(b"js"));
+    let etag = ETag::from_buf(&b"js"[..]);
+    let hash = &etag.as_header_value().to_str().unwrap()[1..9];
+    let path = router.insert_fingerprinted("/app.js", js).unwrap();
+    assert_eq!(path, format!("/app.{hash}.js"));
+    assert_eq!(router.fingerprinted("/app.js"), Some(path.as_str()));
+    assert!(router
+        .insert_fingerprinted("/empty.js", Service::new())
+        .is_none());
+
+    let res = router.call(Request::get(&path).body(()).unwrap()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().contains_key(CACHE_CONTROL));
+    let res = router.call(Request::get("/app.js").body(()).unwrap()).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    assert_eq!(
+        router.fingerprint_manifest(),
+        format!(r#"{{"/app.js":"{path}"}}"#)
+    );
+}
+
+#[tokio::test]
+async fn router_fallback() {
+    let mut router = Router::new()
+        .with_prefix("/app")
+        .with_fallback("/index.html");
+    let index = Service::new();
+    index.fill(Bytes::from_static(b"index"));
+    router.insert("/index.html", index);
+    let js = Service::new();
+    js.fill(Bytes::from_static(b"js"));
+    router.insert("/app.js", js);
+
+    for path in ["/app/settings", "/app/users/1/", "/app"] {
+        let res = router.call(Request::get(path).body(()).unwrap()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.into_body().collect().await.unwrap().to_bytes(), "index");
+    }
+    let res = router
+        .call(Request::get("/app/app.js").body(()).unwrap())
+        .await;
+    assert_eq!(res.into_body().collect().await.unwrap().to_bytes(), "js");
+
+    for path in ["/app/missing.js", "/other"] {
+        let res = router.call(Request::get(path).body(()).unwrap()).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
+
+#[tokio::test]
+async fn encoding_variant() {
+    use http::header::{RANGE, VARY};
+
+    let bufd = Service::new();
+    bufd.fill_encoding_variant(Encoding::Gzip, Bytes::from_static(b"gz"));
+    bufd.fill(Bytes::from_static(b"payload"));
+    bufd.fill_encoding_variant(Encoding::Br, Bytes::from_static(b"br"));
+    bufd.fill_encoding_variant(Encoding::Gzip, Bytes::from_static(b"gz"));
+    bufd.fill_encoding_variant(Encoding::Identity, Bytes::from_static(b"id"));
+
+    let get = |accept_encoding: &'static str| {
+        let mut req = Request::get("/").header(RANGE, "bytes=0-0");
+        if !accept_encoding.is_empty() {
+            req = req.header(ACCEPT_ENCODING, accept_encoding);
+        }
+        bufd.call(req.body(()).unwrap())
+    };
+
+    let res = get("gzip, br").await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get(CONTENT_ENCODING).unwrap(), "br");
+    assert_eq!(res.headers().get(VARY).unwrap(), "accept-encoding");
+    let br_etag = res.headers().get(ETAG).unwrap().clone();
+    assert_eq!(res.into_body().collect().await.unwrap().to_bytes(), "br");
+
+    let res = get("gzip").await;
+    assert_eq!(res.headers().get(CONTENT_ENCODING).unwrap(), "gzip");
+    assert_ne!(res.headers().get(ETAG).unwrap(), br_etag);
+    assert_eq!(res.into_body().collect().await.unwrap().to_bytes(), "gz");
+
+    // others get the payload, ranges and all
+    let res = get("").await;
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert!(!res.headers().contains_key(CONTENT_ENCODING));
+    assert_eq!(res.headers().get(VARY).unwrap(), "accept-encoding");
+    assert_eq!(res.into_body().collect().await.unwrap().to_bytes(), "p");
+
+    // dropped by the next fill
+    bufd.fill(Bytes::from_static(b"payload"));
+    let res = get("br").await;
+    assert!(!res.headers().contains_key(VARY));
+    assert_eq!(res.into_body().collect().await.unwrap().to_bytes(), "p");
+}
+
+#[cfg(feature = "fs")]
+#[tokio::test]
+async fn static_dir() {
+    use http::header::{LAST_MODIFIED, VARY};
+
+    let root = std::env::temp_dir().join(format!("geta-static-dir-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("sub")).unwrap();
+    std::fs::write(root.join("index.html"), "<p>index</p>").unwrap();
+    std::fs::write(root.join("sub/app.js"), "js").unwrap();
+    std::fs::write(root.join("sub/app.js.gz"), "not really gzip").unwrap();
+
+    let dir = StaticDir::open(&root).unwrap();
+    std::fs::remove_dir_all(&root).unwrap();
+    let mut paths: Vec<_> = dir.router().paths().collect();
+    paths.sort();
+    assert_eq!(paths, ["/", "/index.html", "/sub/app.js"]);
+
+    for path in ["/", "/index.html"] {
+        let res = dir.call(Request::get(path).body(()).unwrap()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get(CONTENT_TYPE).unwrap(),
+            "text/html; charset=utf-8"
+        );
+        assert!(res.headers().contains_key(ETAG));
+        assert!(res.headers().contains_key(LAST_MODIFIED));
+        assert_eq!(
+            res.into_body().collect().await.unwrap().to_bytes(),
+            "<p>index</p>"
+        );
+    }
+
+    // the pre-compressed sibling is served as it is to those who accept it
+    let res = dir
+        .call(
+            Request::get("/sub/app.js")
+                .header(ACCEPT_ENCODING, "gzip")
+                .body(())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(res.headers().get(CONTENT_ENCODING).unwrap(), "gzip");
+    assert_eq!(res.headers().get(VARY).unwrap(), "accept-encoding");
+    assert_eq!(
+        res.into_body().collect().await.unwrap().to_bytes(),
+        "not really gzip"
+    );
+    let res = dir
+        .call(Request::get("/sub/app.js").body(()).unwrap())
+        .await;
+    assert!(!res.headers().contains_key(CONTENT_ENCODING));
+    assert_eq!(res.into_body().collect().await.unwrap().to_bytes(), "js");
+
+    let res = dir
+        .call(Request::get("/sub/app.js.gz").body(()).unwrap())
+        .await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[cfg(feature = "fs")]
+#[tokio::test]
+async fn static_dir_index() {
+    use http::header::CONTENT_LOCATION;
+
+    let root = std::env::temp_dir().join(format!("geta-static-dir-index-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("sub")).unwrap();
+    std::fs::write(root.join("index.html"), "root").unwrap();
+    std::fs::write(root.join("sub/index.html"), "html").unwrap();
+    std::fs::write(root.join("sub/index.htm"), "htm").unwrap();
+
+    let dir = StaticDir::builder()
+        .index_files(["index.htm", "index.html"])
+        .open(&root)
+        .unwrap();
+    std::fs::remove_dir_all(&root).unwrap();
+
+    for (path, body) in [("/", "root"), ("/sub/", "htm")] {
+        let res = dir.call(Request::get(path).body(()).unwrap()).await;
+        assert_eq!(res.headers().get(CONTENT_LOCATION).unwrap(), path);
+        assert_eq!(res.into_body().collect().await.unwrap().to_bytes(), body);
+    }
+    let res = dir
+        .call(Request::get("/sub/index.html").body(()).unwrap())
+        .await;
+    assert!(!res.headers().contains_key(CONTENT_LOCATION));
+    assert_eq!(res.into_body().collect().await.unwrap().to_bytes(), "html");
+
+    // both routes serve the one read of the index file
+    let dir = &dir;
+    let body = |path: &'static str| async move {
+        let res = dir.call(Request::get(path).body(()).unwrap()).await;
+        res.into_body().collect().await.unwrap().to_bytes()
+    };
+    let (index, file) = (body("/sub/").await, body("/sub/index.htm").await);
+    assert_eq!(index.as_ptr(), file.as_ptr());
+}
+
+#[cfg(feature = "fs")]
+#[tokio::test]
+async fn static_dir_listing() {
+    let root = std::env::temp_dir().join(format!("geta-static-dir-listing-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("docs")).unwrap();
+    std::fs::create_dir_all(root.join("data/raw")).unwrap();
+    std::fs::write(root.join("docs/index.html"), "docs").unwrap();
+    std::fs::write(root.join("data/a.json"), "{}").unwrap();
+    std::fs::write(root.join("data/raw/a#b.txt"), "ab").unwrap();
+
+    let dir = StaticDir::builder()
+        .listing(Some(Listing::Json))
+        .open(&root)
+        .unwrap();
+    let html = StaticDir::builder()
+        .listing(Some(Listing::Html))
+        .open(&root)
+        .unwrap();
+    let unlisted = StaticDir::open(&root).unwrap();
+    std::fs::remove_dir_all(&root).unwrap();
+
+    let etag = ETag::from_buf(&b"{}"[..]);
+    let res = dir.call(Request::get("/data/").body(()).unwrap()).await;
+    assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), "application/json");
+    assert_eq!(
+        res.into_body().collect().await.unwrap().to_bytes(),
+        format!(
+            r#"[{{"name":"a.json","size":2,"etag":{:?}}},{{"name":"raw/"}}]"#,
+            etag.as_header_value().to_str().unwrap()
+        ) + "\n"
+    );
+
+    let res = dir.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(
+        res.into_body().collect().await.unwrap().to_bytes(),
+        r#"[{"name":"data/"},{"name":"docs/"}]"#.to_owned() + "\n"
+    );
+
+    // directories with an index file are not listed
+    let res = dir.call(Request::get("/docs/").body(()).unwrap()).await;
+    assert_eq!(res.into_body().collect().await.unwrap().to_bytes(), "docs");
+
+    // links are percent-encoded, and lead to the file
+    let res = html
+        .call(Request::get("/data/raw/").body(()).unwrap())
+        .await;
+    let body = res.into_body().collect().await.unwrap().to_bytes();
+    let body = std::str::from_utf8(&body).unwrap();
+    assert!(body.contains("<a href=\"a%23b.txt\">a#b.txt</a>"));
+    let res = html
+        .call(Request::get("/data/raw/a%23b.txt").body(()).unwrap())
+        .await;
+    assert_eq!(res.into_body().collect().await.unwrap().to_bytes(), "ab");
+
+    // off by default
+    let res = unlisted
+        .call(Request::get("/data/").body(()).unwrap())
+        .await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[cfg(all(feature = "fs", feature = "gzip"))]
+#[tokio::test]
+async fn precompress() {
+    let root = std::env::temp_dir().join(format!("geta-precompress-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("app.js"), "console.log(1);\n".repeat(100)).unwrap();
+    std::fs::write(root.join("logo.png"), [0; 100]).unwrap();
+    std::fs::write(root.join("logo.png.gz"), "stale").unwrap();
+
+    let config = CompressionConfig::default();
+    crate::precompress(&root, &config).unwrap();
+    let gzip = std::fs::read(root.join("app.js.gz")).unwrap();
+    let manifest = std::fs::read_to_string(root.join("geta-manifest.txt")).unwrap();
+    assert!(!root.join("logo.png.gz").exists());
+
+    // deterministic
+    crate::precompress(&root, &config).unwrap();
+    assert_eq!(std::fs::read(root.join("app.js.gz")).unwrap(), gzip);
+    assert_eq!(
+        std::fs::read_to_string(root.join("geta-manifest.txt")).unwrap(),
+        manifest
+    );
+    let gzip_etag = ETag::from_buf(&gzip[..]);
+    assert!(manifest.contains(&format!(
+        "{} {} app.js.gz\n",
+        gzip.len(),
+        gzip_etag.as_header_value().to_str().unwrap()
+    )));
+
+    // not trusted for files on disk, which may have been edited since
+    let fake = format!("\"{}\"", "0".repeat(64));
+    let manifest: String = manifest
+        .lines()
+        .map(|line| {
+            let (len, rest) = line.split_once(' ').unwrap();
+            let (_, path) = rest.split_once(' ').unwrap();
+            format!("{len} {fake} {path}\n")
+        })
+        .collect();
+    std::fs::write(root.join("geta-manifest.txt"), manifest).unwrap();
+    let dir = StaticDir::open(&root).unwrap();
+    std::fs::remove_dir_all(&root).unwrap();
+
+    assert!(dir.router().get("/geta-manifest.txt").is_none());
+    let res = dir
+        .call(
+            Request::get("/app.js")
+                .header(ACCEPT_ENCODING, "gzip")
+                .body(())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(res.headers().get(CONTENT_ENCODING).unwrap(), "gzip");
+    assert_eq!(
+        res.headers().get(ETAG).unwrap(),
+        gzip_etag.as_header_value()
+    );
+}
+
+#[cfg(feature = "watch")]
+#[tokio::test]
+async fn watch() {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let root = std::env::temp_dir().join(format!("geta-watch-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("index.html"), "v1").unwrap();
+    std::fs::write(root.join("file.txt"), "v1").unwrap();
+    std::fs::write(root.join("file.txt.gz"), "not really gzip").unwrap();
+
+    let dir = Arc::new(StaticDir::open(&root).unwrap());
+    let _dir_watch = dir.watch().unwrap();
+    let file = SharedService::new(Service::new());
+    let _file_watch = file.watch_file(root.join("file.txt")).unwrap();
+    assert_eq!(file.len(), 2);
+
+    let mut index_changes = dir.router().get("/").unwrap().subscribe();
+    let mut file_changes = file.subscribe();
+    std::fs::write(root.join("index.html"), "v2").unwrap();
+    std::fs::write(root.join("file.txt"), "v2").unwrap();
+    // a write can be seen half done, so wait for the final contents
+    let v2 = ETag::from_buf(&b"v2"[..]);
+    for changes in [&mut index_changes, &mut file_changes] {
+        let filled = changes.wait_for(|etag| etag.as_ref() == Some(&v2));
+        tokio::time::timeout(Duration::from_secs(5), filled)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    for path in ["/", "/index.html"] {
+        let res = dir.call(Request::get(path).body(()).unwrap()).await;
+        assert_eq!(res.into_body().collect().await.unwrap().to_bytes(), "v2");
+    }
+    let res = file.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(
+        res.headers().get(CONTENT_TYPE).unwrap(),
+        "text/plain; charset=utf-8"
+    );
+    assert_eq!(res.into_body().collect().await.unwrap().to_bytes(), "v2");
+
+    // the pre-compressed sibling survives the refill
+    let req = Request::get("/")
+        .header(ACCEPT_ENCODING, "gzip")
+        .body(())
+        .unwrap();
+    let res = file.call(req).await;
+    assert_eq!(res.headers().get(CONTENT_ENCODING).unwrap(), "gzip");
+    assert_eq!(
+        res.into_body().collect().await.unwrap().to_bytes(),
+        "not really gzip"
+    );
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[cfg(feature = "include_dir")]
+#[tokio::test]
+async fn include_dir() {
+    use http::header::CONTENT_LOCATION;
+    use include_dir::{Dir, DirEntry, File};
+
+    static ASSETS: Dir = Dir::new(
+        "",
+        &[
+            DirEntry::File(File::new("index.html", b"<p>index</p>")),
+            DirEntry::File(File::new(
+                "geta-manifest.txt",
+                b"2 \"recorded\" sub/app.js\n",
+            )),
+            DirEntry::Dir(Dir::new(
+                "sub",
+                &[
+                    DirEntry::File(File::new("sub/app.js", b"js")),
+                    DirEntry::File(File::new("sub/app.js.gz", b"not really gzip")),
+                ],
+            )),
+        ],
+    );
+
+    let router = Router::from_include_dir(&ASSETS);
+    let mut paths: Vec<_> = router.paths().collect();
+    paths.sort();
+    assert_eq!(paths, ["/", "/index.html", "/sub/app.js"]);
+
+    let res = router.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(
+        res.headers().get(CONTENT_TYPE).unwrap(),
+        "text/html; charset=utf-8"
+    );
+    let etag = res.headers().get(ETAG).unwrap().clone();
+    assert_eq!(etag, ETag::from_buf(&b"<p>index</p>"[..]).as_header_value());
+
+    let res = router
+        .call(
+            Request::get("/index.html")
+                .header(IF_NONE_MATCH, etag)
+                .body(())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+    let res = router
+        .call(
+            Request::get("/sub/app.js")
+                .header(ACCEPT_ENCODING, "gzip")
+                .body(())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(res.headers().get(CONTENT_ENCODING).unwrap(), "gzip");
+    assert_eq!(
+        res.into_body().collect().await.unwrap().to_bytes(),
+        "not really gzip"
+    );
+
+    // the recorded ETags are taken as they are
+    let res = router
+        .call(
+            Request::get("/sub/app.js")
+                .header(ACCEPT_ENCODING, "identity")
+                .body(())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(res.headers().get(ETAG).unwrap(), "\"recorded\"");
+
+    // configured index files; the directory shares the file's payload
+    let router =
+        Router::from_include_dir_with(&ASSETS, &ContentTypes::new(), &["app.js", "index.html"]);
+    let mut paths: Vec<_> = router.paths().collect();
+    paths.sort();
+    assert_eq!(paths, ["/", "/index.html", "/sub/", "/sub/app.js"]);
+    let res = router.call(Request::get("/sub/").body(()).unwrap()).await;
+    assert_eq!(res.headers().get(CONTENT_LOCATION).unwrap(), "/sub/");
+    assert_eq!(res.headers().get(ETAG).unwrap(), "\"recorded\"");
+}
+
+#[tokio::test]
+async fn builder() {
+    use http::header::{CACHE_CONTROL, CONTENT_LANGUAGE};
+
+    let shared = Service::builder()
+        .content_type(HeaderValue::from_static("text/css"))
+        .header(CONTENT_LANGUAGE, HeaderValue::from_static("en"))
+        .cache_control(CacheControl::new().no_cache())
+        .configure(|service| service.set_accept_ranges(false))
+        .body(Bytes::from_static(b"body {}"))
+        .build();
+
+    let res = shared.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let headers = res.headers();
+    assert_eq!(headers.get(CONTENT_TYPE).unwrap(), "text/css");
+    assert_eq!(headers.get(CONTENT_LANGUAGE).unwrap(), "en");
+    assert_eq!(headers.get(CACHE_CONTROL).unwrap(), "no-cache");
+    assert_eq!(headers.get("accept-ranges").unwrap(), "none");
+    assert_eq!(
+        res.into_body().collect().await.unwrap().to_bytes(),
+        "body {}"
+    );
+}
+
+#[tokio::test]
+async fn clear() {
+    let bufd = Service::new();
+    assert_eq!(bufd.take(), None);
+
+    bufd.fill(Bytes::from_static(b"payload"));
+    assert_eq!(bufd.take(), Some(Bytes::from_static(b"payload")));
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    bufd.fill(Bytes::from_static(b"payload"));
+    bufd.clear();
+    assert_eq!(bufd.take(), None);
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn subscribe() {
+    let bufd = Service::new();
+    let mut changes = bufd.subscribe();
+    assert_eq!(*changes.borrow(), None);
+
+    bufd.fill(Bytes::from_static(b"payload"));
+    changes.changed().await.unwrap();
+    assert_eq!(
+        *changes.borrow_and_update(),
+        Some(ETag::from_buf(&b"payload"[..]))
+    );
+
+    let etag: ETag = "\"custom\"".parse().unwrap();
+    bufd.fill_with_etag(Bytes::from_static(b"other"), etag.clone());
+    changes.changed().await.unwrap();
+    assert_eq!(*changes.borrow_and_update(), Some(etag));
+
+    bufd.clear();
+    changes.changed().await.unwrap();
+    assert_eq!(*changes.borrow_and_update(), None);
+}
+
+#[tokio::test]
+async fn generation_header() {
+    let mut bufd = Service::new();
+    bufd.set_generation_header(Some(HeaderName::from_static("x-geta-generation")));
+    assert_eq!(bufd.generation(), 0);
+
+    bufd.fill(Bytes::from_static(b"first"));
+    bufd.fill(Bytes::from_static(b"second"));
+    assert_eq!(bufd.generation(), 2);
+
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.headers().get("x-geta-generation").unwrap(), "2");
+}
+
+#[tokio::test]
+async fn content_length() {
+    use http::header::CONTENT_LENGTH;
+
+    let bufd = Service::new();
+    bufd.fill(Bytes::from_static(b"payload"));
+    bufd.fill_encoding_variant(Encoding::Br, Bytes::from_static(b"br"));
+
+    for req in [Request::get("/"), Request::head("/")] {
+        let res = bufd.call(req.body(()).unwrap()).await;
+        assert_eq!(res.headers().get(CONTENT_LENGTH).unwrap(), "7");
+    }
+    // of the selected representation
+    for req in [Request::get("/"), Request::head("/")] {
+        let req = req.header(ACCEPT_ENCODING, "br").body(()).unwrap();
+        let res = bufd.call(req).await;
+        assert_eq!(res.headers().get(CONTENT_LENGTH).unwrap(), "2");
+    }
+    let req = Request::head("/")
+        .header(http::header::RANGE, "bytes=1-3")
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(res.headers().get(CONTENT_LENGTH).unwrap(), "3");
+}
+
+#[tokio::test]
+async fn sniff_content_type() {
+    let mut bufd = Service::new();
+    bufd.fill(Bytes::from_static(b"<!doctype html><p>shell</p>"));
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(
+        res.headers().get(CONTENT_TYPE).unwrap(),
+        "text/html; charset=utf-8"
+    );
+
+    // follows the payload
+    bufd.fill(Bytes::from_static(b"{\"a\": 1}"));
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), "application/json");
+    bufd.fill(Bytes::from_static(b"payload"));
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert!(!res.headers().contains_key(CONTENT_TYPE));
+
+    // configured or opted out
+    bufd.headers
+        .insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+    bufd.fill(Bytes::from_static(b"{}"));
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), "text/plain");
+    bufd.headers.remove(CONTENT_TYPE);
+    bufd.set_sniff_content_type(false);
+    bufd.fill(Bytes::from_static(b"{}"));
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert!(!res.headers().contains_key(CONTENT_TYPE));
+}
+
+#[tokio::test]
+async fn utf8_charset() {
+    let bufd = Service::builder()
+        .content_type(HeaderValue::from_static("text/plain; charset=iso-8859-1"))
+        .configure(|s| {
+            s.set_utf8_charset();
+            s.set_validate_utf8(true);
+        })
+        .body(Bytes::from_static(b"\xff"))
+        .build();
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(
+        res.headers().get(CONTENT_TYPE).unwrap(),
+        "text/plain; charset=utf-8"
+    );
+    // only warned about
+    assert_eq!(
+        res.into_body().collect().await.unwrap().to_bytes(),
+        &b"\xff"[..]
+    );
+}
+
+#[tokio::test]
+async fn security_headers() {
+    use http::header::{CONTENT_SECURITY_POLICY, REFERRER_POLICY, X_CONTENT_TYPE_OPTIONS};
+
+    let bufd = Service::builder()
+        .security_headers(Some(HeaderValue::from_static("default-src 'self'")))
+        .body(test_body())
+        .build();
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(
+        res.headers().get(X_CONTENT_TYPE_OPTIONS).unwrap(),
+        "nosniff"
+    );
+    assert_eq!(
+        res.headers().get(REFERRER_POLICY).unwrap(),
+        "strict-origin-when-cross-origin"
+    );
+    assert_eq!(
+        res.headers().get("cross-origin-resource-policy").unwrap(),
+        "same-origin"
+    );
+    assert_eq!(
+        res.headers().get(CONTENT_SECURITY_POLICY).unwrap(),
+        "default-src 'self'"
+    );
+
+    let mut bufd = Service::new();
+    bufd.set_security_headers(None);
+    bufd.fill(test_body());
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert!(res.headers().contains_key(X_CONTENT_TYPE_OPTIONS));
+    assert!(!res.headers().contains_key(CONTENT_SECURITY_POLICY));
+}
+
+#[tokio::test]
+async fn preload() {
+    use http::header::LINK;
+
+    let mut bufd = Service::new();
+    bufd.add_preload("/app.css", "style").unwrap();
+    bufd.add_preload("/font.woff2", "font").unwrap();
+    assert!(bufd.add_preload("/app\n.js", "script").is_err());
+    bufd.fill(Bytes::from_static(b"<!doctype html>"));
+
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    let links: Vec<_> = res.headers().get_all(LINK).iter().collect();
+    assert_eq!(
+        links,
+        [
+            "</app.css>; rel=preload; as=style",
+            "</font.woff2>; rel=preload; as=font; crossorigin"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn repr_digest() {
+    use http::header::RANGE;
+
+    let sha256 = "sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:";
+    let mut bufd = Service::new();
+    bufd.fill(Bytes::from_static(b"{\"hello\": \"world\"}"));
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert!(!res.headers().contains_key("repr-digest"));
+
+    bufd.set_repr_digest(&[DigestAlgorithm::Sha256]);
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.headers().get("repr-digest").unwrap(), sha256);
+    assert_eq!(res.headers().get("content-digest").unwrap(), sha256);
+
+    // of the whole representation only
+    let req = Request::get("/")
+        .header(RANGE, "bytes=0-1")
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(res.headers().get("repr-digest").unwrap(), sha256);
+    assert!(!res.headers().contains_key("content-digest"));
+
+    // any algorithm, for the selected variant
+    bufd.set_repr_digest(&DigestAlgorithm::ALL);
+    bufd.set_etag_mode(ETagMode::Generation);
+    bufd.fill(Bytes::from_static(b"{\"hello\": \"world\"}"));
+    bufd.fill_encoding_variant(Encoding::Br, Bytes::from_static(b"br"));
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(
+        res.headers().get("repr-digest").unwrap(),
+        "sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:, \
+         sha-512=:WZDPaVn/7XgHaAy8pmojAkGWoRx2UFChF41A2svX+TaPm+AbwAgBWnrIiYllu7BNNyealdVLvRwEmTHWXvJwew==:"
+    );
+    let req = Request::get("/")
+        .header(ACCEPT_ENCODING, "br")
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    let digest = res.headers().get("repr-digest").unwrap().to_str().unwrap();
+    assert!(digest.starts_with("sha-256=:"));
+    assert!(!digest.starts_with(sha256.trim_end_matches(':')));
+
+    // as the client wants
+    bufd.set_repr_digest(&[DigestAlgorithm::Sha256]);
+    let req = Request::get("/")
+        .header("want-repr-digest", "sha-512=3, sha-256=1")
+        .header("want-content-digest", "sha-256=0")
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    let digest = res.headers().get("repr-digest").unwrap().to_str().unwrap();
+    assert!(digest.starts_with("sha-512=:WZDPaVn/"));
+    assert!(digest.ends_with(sha256));
+    assert!(!res.headers().contains_key("content-digest"));
+}
+
+#[tokio::test]
+async fn date() {
+    use http::header::DATE;
+    use std::time::SystemTime;
+
+    let bufd = Service::new();
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    let date = res.headers().get(DATE).unwrap().to_str().unwrap();
+    let date = httpdate::parse_http_date(date).unwrap();
+    let age = SystemTime::now().duration_since(date).unwrap();
+    assert!(age.as_secs() <= 1);
+
+    bufd.fill(test_body());
+    let res = bufd.call(Request::post("/").body(()).unwrap()).await;
+    assert!(res.headers().contains_key(DATE));
+    let res = Router::<Bytes>::new()
+        .call(Request::get("/").body(()).unwrap())
+        .await;
+    assert!(res.headers().contains_key(DATE));
+}
+
+#[cfg(feature = "gzip")]
+#[tokio::test]
+async fn no_transform() {
+    use http::header::CACHE_CONTROL;
+
+    let gzip = {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::best());
+        std::io::copy(&mut &test_body()[..], &mut encoder).unwrap();
+        Bytes::from(encoder.finish().unwrap())
+    };
+    let mut bufd = Service::new();
+    bufd.set_encoding(Encoding::Gzip);
+    bufd.fill(gzip.clone());
+
+    let get = |accept_encoding: &'static str| {
+        Request::get("/")
+            .header(ACCEPT_ENCODING, accept_encoding)
+            .header(CACHE_CONTROL, "max-age=0, No-Transform")
+            .body(())
+            .unwrap()
+    };
+    let res = bufd.call(get("gzip")).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.into_body().collect().await.unwrap().to_bytes(), gzip);
+
+    let res = bufd.call(get("identity")).await;
+    assert_eq!(res.status(), StatusCode::NOT_ACCEPTABLE);
+}
+
+#[tokio::test]
+async fn redirect() {
+    use http::header::{CACHE_CONTROL, CONTENT_LENGTH, LOCATION};
+    use std::time::Duration;
+
+    let mut bufd = Service::new();
+    bufd.set_cache_control(CacheControl::new().max_age(Duration::from_secs(60)));
+    bufd.fill(test_body());
+    bufd.redirect(
+        StatusCode::MOVED_PERMANENTLY,
+        HeaderValue::from_static("https://cdn.example/app.js"),
+    );
+    assert!(!bufd.is_filled());
+    assert_eq!(bufd.etag(), None);
+
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(
+        res.headers().get(LOCATION).unwrap(),
+        "https://cdn.example/app.js"
+    );
+    assert_eq!(res.headers().get(CACHE_CONTROL).unwrap(), "max-age=60");
+    assert_eq!(res.headers().get(CONTENT_LENGTH).unwrap(), "0");
+    assert!(!res.headers().contains_key(ETAG));
+    assert!(res
+        .into_body()
+        .collect()
+        .await
+        .unwrap()
+        .to_bytes()
+        .is_empty());
+
+    let res = bufd.call(Request::head("/").body(()).unwrap()).await;
+    assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+    assert!(res.headers().contains_key(LOCATION));
+
+    let res = bufd.call(Request::post("/").body(()).unwrap()).await;
+    assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    // not a redirection
+    bufd.redirect(StatusCode::NOT_MODIFIED, HeaderValue::from_static("/other"));
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+
+    bufd.fill(test_body());
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn request_id() {
+    let x_request_id = HeaderName::from_static("x-request-id");
+    let mut bufd = Service::new();
+    bufd.fill(test_body());
+
+    // disabled by default
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert!(!res.headers().contains_key(&x_request_id));
+
+    bufd.set_request_id_header(Some(x_request_id.clone()));
+    let req = Request::get("/")
+        .header(&x_request_id, "abc-123")
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.headers().get(&x_request_id).unwrap(), "abc-123");
+
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    let generated = res.headers().get(&x_request_id).unwrap().clone();
+    assert_eq!(generated.len(), 32);
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_ne!(res.headers().get(&x_request_id).unwrap(), generated);
+
+    // replaced when not usable
+    let req = Request::get("/")
+        .header(&x_request_id, "a".repeat(201))
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.headers().get(&x_request_id).unwrap().len(), 32);
+
+    // on every response
+    let req = Request::post("/")
+        .header(&x_request_id, "abc-123")
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(res.headers().get(&x_request_id).unwrap(), "abc-123");
+}
+
+#[cfg(feature = "gzip")]
+#[tokio::test]
+async fn server_timing() {
+    let server_timing = |res: &Response<_>| {
+        res.headers()
+            .get_all("server-timing")
+            .iter()
+            .map(|v| {
+                let v = v.to_str().unwrap();
+                let (name, rest) = v.split_once(";dur=").unwrap();
+                let desc = rest.split_once(';').map(|(_, desc)| desc.to_owned());
+                (name.to_owned(), desc)
+            })
+            .collect::<Vec<_>>()
+    };
+    let gzip = {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::best());
+        std::io::copy(&mut &test_body()[..], &mut encoder).unwrap();
+        Bytes::from(encoder.finish().unwrap())
+    };
+    let mut bufd = Service::new();
+    bufd.set_encoding(Encoding::Gzip);
+    bufd.fill(gzip);
+
+    // disabled by default
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert!(server_timing(&res).is_empty());
+
+    bufd.set_server_timing(true);
+    let res = bufd
+        .call(
+            Request::get("/")
+                .header(ACCEPT_ENCODING, "gzip")
+                .body(())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(
+        server_timing(&res),
+        [("negotiate".to_owned(), None), ("etag".to_owned(), None)]
+    );
+    let etag = res.headers().get(ETAG).unwrap().clone();
+
+    let decode = |res: &Response<_>| server_timing(res).get(2).cloned();
+    let identity = || {
+        Request::get("/")
+            .header(ACCEPT_ENCODING, "identity")
+            .body(())
+            .unwrap()
+    };
+    let res = bufd.call(identity()).await;
+    assert_eq!(
+        decode(&res),
+        Some(("decode".to_owned(), Some("desc=\"spawned\"".to_owned())))
+    );
+    res.into_body().collect().await.unwrap();
+    let res = bufd.call(identity()).await;
+    assert_eq!(
+        decode(&res),
+        Some(("decode".to_owned(), Some("desc=\"cached\"".to_owned())))
+    );
+
+    let req = Request::get("/")
+        .header(ACCEPT_ENCODING, "gzip")
+        .header(IF_NONE_MATCH, etag)
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(server_timing(&res).len(), 2);
+}
+
+#[tokio::test]
+async fn signer() {
+    use aws_lc_rs::signature::{Ed25519KeyPair, UnparsedPublicKey, ED25519};
+
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&aws_lc_rs::rand::SystemRandom::new()).unwrap();
+    let signer = Signer::ed25519("feed-key", pkcs8.as_ref()).unwrap();
+    let public_key = signer.public_key().unwrap().to_vec();
+    assert_eq!(
+        Signer::ed25519("feed-key", b"not a key").unwrap_err(),
+        InvalidSigningKey
+    );
+
+    let mut bufd = Service::new();
+    bufd.headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/atom+xml"),
+    );
+    bufd.set_signer(Some(signer));
+    bufd.fill(Bytes::from_static(b"{\"hello\": \"world\"}"));
+
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    let header = |name: &str| res.headers().get(name).unwrap().to_str().unwrap();
+    assert_eq!(
+        header("repr-digest"),
+        "sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:"
+    );
+    let params = header("signature-input").strip_prefix("sig1=").unwrap();
+    assert!(params
+        .starts_with("(\"@status\" \"content-type\" \"repr-digest\";key=\"sha-256\");created="));
+    assert!(params.ends_with(";keyid=\"feed-key\";alg=\"ed25519\""));
+
+    let base = format!(
+        "\"@status\": 200\n\"content-type\": application/atom+xml\n\
+         \"repr-digest\";key=\"sha-256\": :X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:\n\
+         \"@signature-params\": {params}"
+    );
+    // Ed25519 signatures are deterministic
+    let key = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+    let signature = key.sign(base.as_bytes());
+    let expected = format!("sig1=:{}:", crate::base64::encode(signature.as_ref()));
+    assert_eq!(header("signature"), expected);
+    UnparsedPublicKey::new(&ED25519, &public_key)
+        .verify(base.as_bytes(), signature.as_ref())
+        .unwrap();
+
+    // only full responses
+    let req = Request::get("/")
+        .header("range", "bytes=0-1")
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert!(!res.headers().contains_key("signature"));
+
+    // merged into the negotiated digests
+    bufd.set_repr_digest(&[DigestAlgorithm::Sha256]);
+    let req = Request::get("/")
+        .header("want-repr-digest", "sha-512=1")
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    let repr_digest = res.headers().get("repr-digest").unwrap().to_str().unwrap();
+    assert!(repr_digest.starts_with("sha-512=:"));
+    assert!(repr_digest.ends_with(", sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:"));
+    assert_eq!(res.headers().get("signature").unwrap(), expected.as_str());
+
+    // dropped once a covered header is rewritten
+    bufd.set_header_hook(Some(|_, headers| {
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+    }));
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), "text/plain");
+    assert!(!res.headers().contains_key("signature"));
+    assert!(!res.headers().contains_key("signature-input"));
+    bufd.set_header_hook(None);
+    let mut headers = http::HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+    bufd.set_status_headers(StatusCode::OK, headers);
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert!(!res.headers().contains_key("signature"));
+
+    // kept when uncovered headers change
+    let mut headers = http::HeaderMap::new();
+    headers.insert("x-frame-options", HeaderValue::from_static("DENY"));
+    bufd.set_status_headers(StatusCode::OK, headers);
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert!(res.headers().contains_key("signature"));
+}
+
+#[tokio::test]
+async fn strict_negotiation() {
+    use http::header::{ACCEPT, VARY};
+
+    let mut bufd = Service::new();
+    bufd.headers
+        .insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+    bufd.fill(test_body());
+    let get = |name: HeaderName, value: &'static str| {
+        Request::get("/").header(name, value).body(()).unwrap()
+    };
+
+    // disabled by default
+    let res = bufd.call(get(ACCEPT, "image/png")).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    bufd.set_strict_negotiation(true);
+    let res = bufd.call(get(ACCEPT, "image/png")).await;
+    assert_eq!(res.status(), StatusCode::NOT_ACCEPTABLE);
+    assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), "application/json");
+    assert_eq!(res.headers().get(VARY).unwrap(), "accept, accept-encoding");
+    assert_eq!(
+        res.into_body().collect().await.unwrap().to_bytes(),
+        r#"{"content-type":"text/html","content-encoding":["identity"]}"#
+    );
+
+    let res = bufd.call(get(ACCEPT, "image/png, text/*;q=0.1")).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = bufd.call(get(ACCEPT_ENCODING, "gzip")).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = bufd.call(get(ACCEPT_ENCODING, "gzip, identity;q=0")).await;
+    assert_eq!(res.status(), StatusCode::NOT_ACCEPTABLE);
+
+    // q=0 refuses a coding
+    bufd.fill_encoding_variant(Encoding::Br, Bytes::from_static(b"not really br"));
+    let res = bufd.call(get(ACCEPT_ENCODING, "br")).await;
+    assert_eq!(res.headers().get(CONTENT_ENCODING).unwrap(), "br");
+    let res = bufd.call(get(ACCEPT_ENCODING, "br;q=0")).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(!res.headers().contains_key(CONTENT_ENCODING));
+    let res = bufd
+        .call(get(ACCEPT_ENCODING, "br;q=0, identity;q=0"))
+        .await;
+    assert_eq!(res.status(), StatusCode::NOT_ACCEPTABLE);
+    assert_eq!(
+        res.into_body().collect().await.unwrap().to_bytes(),
+        r#"{"content-type":"text/html","content-encoding":["identity","br"]}"#
+    );
+
+    let req = Request::head("/")
+        .header(ACCEPT, "image/png")
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.status(), StatusCode::NOT_ACCEPTABLE);
+    assert!(res
+        .into_body()
+        .collect()
+        .await
+        .unwrap()
+        .to_bytes()
+        .is_empty());
+
+    #[cfg(feature = "gzip")]
+    {
+        let gzip = {
+            let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::best());
+            std::io::copy(&mut &test_body()[..], &mut encoder).unwrap();
+            Bytes::from(encoder.finish().unwrap())
+        };
+        bufd.set_encoding(Encoding::Gzip);
+        bufd.fill(gzip);
+
+        let res = bufd.call(get(ACCEPT_ENCODING, "gzip, identity;q=0")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = bufd.call(get(ACCEPT_ENCODING, "*, identity;q=0")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get(CONTENT_ENCODING).unwrap(), "gzip");
+        let res = bufd.call(get(ACCEPT_ENCODING, "compress, *;q=0")).await;
+        assert_eq!(res.status(), StatusCode::NOT_ACCEPTABLE);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(body.contains(r#""content-encoding":["gzip","#));
+        assert!(body.ends_with(r#""identity"]}"#));
+    }
+}
+
+#[tokio::test]
+async fn on_empty() {
+    use http::header::CONTENT_LENGTH;
+
+    let mut bufd = Service::new();
+    bufd.set_on_empty(
+        StatusCode::SERVICE_UNAVAILABLE,
+        Bytes::from_static(b"warming up"),
+    );
+
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        res.into_body().collect().await.unwrap().to_bytes(),
+        "warming up"
+    );
+
+    let res = bufd.call(Request::head("/").body(()).unwrap()).await;
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(res.headers().get(CONTENT_LENGTH).unwrap(), "10");
+    assert!(res
+        .into_body()
+        .collect()
+        .await
+        .unwrap()
+        .to_bytes()
+        .is_empty());
+
+    bufd.fill(Bytes::from_static(b"payload"));
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn options() {
+    use http::header::ALLOW;
+
+    let bufd = Service::new();
+    bufd.fill(test_body());
+
+    let req = Request::options("/").body(()).unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert_eq!(res.headers().get(ALLOW).unwrap(), "GET, HEAD, OPTIONS");
+
+    let req = Request::post("/").body(()).unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(res.headers().get(ALLOW).unwrap(), "GET, HEAD, OPTIONS");
+}
+
+#[tokio::test]
+async fn cors() {
+    use http::header::{
+        ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+        ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS,
+        ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD,
+        ALLOW, ORIGIN, VARY,
+    };
+    use std::time::Duration;
+
+    let mut bufd = Service::new();
+    bufd.fill(test_body());
+    let get = |origin: &'static str| Request::get("/").header(ORIGIN, origin).body(()).unwrap();
+
+    // disabled by default
+    let res = bufd.call(get("https://a.example")).await;
+    assert!(!res.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+
+    bufd.set_cors(Some(Cors::new()));
+    let res = bufd.call(get("https://a.example")).await;
+    assert_eq!(res.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), "*");
+    assert_eq!(
+        res.headers().get(ACCESS_CONTROL_EXPOSE_HEADERS).unwrap(),
+        "etag"
+    );
+    assert!(!res.headers().contains_key(VARY));
+
+    bufd.set_cors(Some(
+        Cors::new()
+            .allow_origin(AllowOrigin::List(vec![
+                HeaderValue::from_static("https://a.example"),
+                HeaderValue::from_static("https://b.example"),
+            ]))
+            .max_age(Duration::from_secs(600))
+            .allow_credentials(),
+    ));
+    let res = bufd.call(get("https://b.example")).await;
+    assert_eq!(
+        res.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+        "https://b.example"
+    );
+    assert_eq!(
+        res.headers().get(ACCESS_CONTROL_ALLOW_CREDENTIALS).unwrap(),
+        "true"
+    );
+    assert_eq!(res.headers().get(VARY).unwrap(), "origin");
+    let res = bufd.call(get("https://c.example")).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(!res.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+    assert_eq!(res.headers().get(VARY).unwrap(), "origin");
+
+    // also on 304s
+    let etag = ETag::from_buf(test_body());
+    let req = Request::get("/")
+        .header(ORIGIN, "https://a.example")
+        .header(IF_NONE_MATCH, etag.as_header_value())
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert!(res.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+
+    let preflight = |origin: &'static str, method: &'static str| {
+        Request::options("/")
+            .header(ORIGIN, origin)
+            .header(ACCESS_CONTROL_REQUEST_METHOD, method)
+            .header(ACCESS_CONTROL_REQUEST_HEADERS, "if-none-match, range")
+            .body(())
+            .unwrap()
+    };
+    let res = bufd.call(preflight("https://a.example", "GET")).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert_eq!(
+        res.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+        "https://a.example"
+    );
+    assert_eq!(
+        res.headers().get(ACCESS_CONTROL_ALLOW_METHODS).unwrap(),
+        "GET"
+    );
+    assert_eq!(
+        res.headers().get(ACCESS_CONTROL_ALLOW_HEADERS).unwrap(),
+        "if-none-match, range"
+    );
+    assert_eq!(res.headers().get(ACCESS_CONTROL_MAX_AGE).unwrap(), "600");
+    assert!(!res.headers().contains_key(ACCESS_CONTROL_EXPOSE_HEADERS));
+    assert!(!res.headers().contains_key(ALLOW));
+
+    // denied without CORS headers
+    for (origin, method) in [("https://c.example", "GET"), ("https://a.example", "PUT")] {
+        let res = bufd.call(preflight(origin, method)).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert!(!res.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+        assert!(!res.headers().contains_key(ACCESS_CONTROL_ALLOW_METHODS));
+    }
+
+    // a plain OPTIONS is not a preflight
+    let res = bufd.call(Request::options("/").body(()).unwrap()).await;
+    assert_eq!(res.headers().get(ALLOW).unwrap(), "GET, HEAD, OPTIONS");
+
+    // credentials from any origin take `Reflect`
+    bufd.set_cors(Some(
+        Cors::new()
+            .allow_origin(AllowOrigin::Reflect)
+            .allow_credentials(),
+    ));
+    let res = bufd.call(get("https://c.example")).await;
+    assert_eq!(
+        res.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+        "https://c.example"
+    );
+    assert_eq!(res.headers().get(VARY).unwrap(), "origin");
+}
+
+#[test]
+#[should_panic(expected = "Cors::allow_credentials")]
+fn cors_any_with_credentials() {
+    Service::<Bytes>::new().set_cors(Some(Cors::new().allow_credentials()));
+}
+
+#[tokio::test]
+async fn put() {
+    use http::header::{ALLOW, AUTHORIZATION};
+    use http_body_util::Full;
+
+    let put = |token: &str, body: &'static [u8]| {
+        Request::put("/")
+            .header(AUTHORIZATION, format!("Bearer {token}"))
+            .body(Full::new(Bytes::from_static(body)))
+            .unwrap()
+    };
+
+    let mut bufd = Service::<Bytes>::new();
+    let res = bufd.call_writable(put("secret", b"payload")).await;
+    assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    bufd.set_write_token(Some("secret".into()));
+    let res = bufd.call_writable(put("wrong", b"payload")).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let req = Request::put("/").body(Full::new(Bytes::new())).unwrap();
+    let res = bufd.call_writable(req).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let res = bufd.call_writable(put("secret", b"payload")).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    let etag = ETag::from_buf(&b"payload"[..]);
+    assert_eq!(res.headers().get(ETAG).unwrap(), etag.as_header_value());
+
+    let req = Request::get("/").body(Full::new(Bytes::new())).unwrap();
+    let res = bufd.call_writable(req).await;
+    assert_eq!(res.headers().get(ETAG).unwrap(), etag.as_header_value());
+    assert_eq!(
+        res.into_body().collect().await.unwrap().to_bytes(),
+        "payload"
+    );
+
+    let res = bufd.call(Request::options("/").body(()).unwrap()).await;
+    assert_eq!(
+        res.headers().get(ALLOW).unwrap(),
+        "GET, HEAD, OPTIONS, PUT, DELETE"
+    );
+
+    // the content type comes along
+    let mut req = put("secret", b"{}");
+    req.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    assert_eq!(
+        bufd.call_writable(req).await.status(),
+        StatusCode::NO_CONTENT
+    );
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), "application/json");
+
+    // bodies over the limit are refused
+    bufd.set_max_write_size(8);
+    let res = bufd
+        .call_writable(put("secret", b"too large payload"))
+        .await;
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.into_body().collect().await.unwrap().to_bytes(), "{}");
+}
+
+#[tokio::test]
+async fn delete() {
+    use http::header::AUTHORIZATION;
+    use http_body_util::Empty;
+
+    let delete = |token: &str| {
+        Request::delete("/")
+            .header(AUTHORIZATION, format!("Bearer {token}"))
+            .body(Empty::<Bytes>::new())
+            .unwrap()
+    };
+
+    let mut bufd = Service::new();
+    bufd.set_write_token(Some("secret".into()));
+    bufd.fill(test_body());
+
+    let res = bufd.call_writable(delete("wrong")).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert!(bufd.take().is_some());
+
+    bufd.fill(test_body());
+    let res = bufd.call_writable(delete("secret")).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert!(bufd.take().is_none());
+}
+
+#[tokio::test]
+async fn panicking_fill() {
+    #[derive(Clone)]
+    struct Payload(Bytes, bool);
+
+    impl bytes::Buf for Payload {
+        fn remaining(&self) -> usize {
+            self.0.remaining()
+        }
+        fn chunk(&self) -> &[u8] {
+            assert!(!self.1, "bad payload");
+            self.0.chunk()
+        }
+        fn advance(&mut self, cnt: usize) {
+            self.0.advance(cnt)
+        }
+    }
+
+    let bufd = Service::new();
+    bufd.fill(Payload(Bytes::from_static(b"good"), false));
+    let fill =
+        std::panic::AssertUnwindSafe(|| bufd.fill(Payload(Bytes::from_static(b"bad"), true)));
+    assert!(std::panic::catch_unwind(fill).is_err());
+
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.into_body().collect().await.unwrap().to_bytes(), "good");
+}
+
+#[tokio::test]
+async fn call_parts() {
+    let bufd = Service::new();
+    bufd.fill(test_body());
+
+    let (parts, _) = Request::head("/")
+        .header(ACCEPT_ENCODING, "identity")
+        .body(())
+        .unwrap()
+        .into_parts();
+    let res = bufd.call_parts(&parts).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res
+        .into_body()
+        .collect()
+        .await
+        .unwrap()
+        .to_bytes()
+        .is_empty());
+}
+
+#[cfg(feature = "gzip")]
+#[tokio::test]
+async fn response_info() {
+    let orig_body = test_body();
+    let mut bufd = Service::new();
+    bufd.set_encoding(Encoding::Gzip);
+    bufd.fill({
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::best());
+        std::io::copy(&mut &orig_body[..], &mut encoder).unwrap();
+        Bytes::from(encoder.finish().unwrap())
+    });
+
+    let req = Request::get("/")
+        .header(ACCEPT_ENCODING, "gzip")
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    let info = res.extensions().get::<ResponseInfo>().unwrap().clone();
+    assert_eq!(info.encoding, Some(Encoding::Gzip));
+    assert!(!info.cache_hit && !info.decoded);
+    let etag = info.etag.unwrap();
+    assert_eq!(res.headers().get(ETAG).unwrap(), etag.as_header_value());
+
+    let req = Request::get("/")
+        .header(IF_NONE_MATCH, etag.as_header_value())
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    let info = res.extensions().get::<ResponseInfo>().unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert!(info.cache_hit);
+
+    let req = Request::get("/")
+        .header(ACCEPT_ENCODING, "identity")
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    let info = res.extensions().get::<ResponseInfo>().unwrap();
+    assert_eq!(info.encoding, Some(Encoding::Identity));
+    assert!(info.decoded);
+
+    let res = bufd.call(Request::post("/").body(()).unwrap()).await;
+    assert_eq!(
+        res.extensions().get::<ResponseInfo>(),
+        Some(&ResponseInfo::default())
+    );
+}
+
+#[tokio::test]
+async fn shared() {
+    let shared = SharedService::new(Service::new());
+    let clone = shared.clone();
+    shared.fill(Bytes::from_static(b"payload"));
+
+    let res = tokio::spawn(async move { clone.call(Request::get("/").body(()).unwrap()).await })
+        .await
+        .unwrap();
+    assert_eq!(
+        res.into_body().collect().await.unwrap().to_bytes(),
+        "payload"
+    );
+}
+
+#[tokio::test]
+async fn header_hook() {
+    use http::header::CONTENT_DISPOSITION;
+
+    let mut bufd = Service::new();
+    bufd.set_header_hook(Some(|req, headers| {
+        if req.uri.query() == Some("download") {
+            headers.insert(
+                CONTENT_DISPOSITION,
+                HeaderValue::from_static("attachment; filename=\"data.bin\""),
+            );
+        }
+    }));
+    bufd.fill(test_body());
+
+    let res = bufd
+        .call(Request::get("/?download").body(()).unwrap())
+        .await;
+    assert_eq!(
+        res.headers().get(CONTENT_DISPOSITION).unwrap(),
+        "attachment; filename=\"data.bin\""
+    );
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert!(res.headers().get(CONTENT_DISPOSITION).is_none());
+}
+
+#[tokio::test]
+async fn observer() {
+    use std::sync::{Arc, Mutex};
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut bufd = Service::new();
+    bufd.set_observer({
+        let seen = seen.clone();
+        move |req, res| {
+            let info = res.extensions().get::<ResponseInfo>().unwrap();
+            seen.lock()
+                .unwrap()
+                .push((req.method.clone(), res.status(), info.cache_hit));
+        }
+    });
+
+    bufd.call(Request::get("/").body(()).unwrap()).await;
+    bufd.fill(test_body());
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    let etag = res.headers().get(ETAG).unwrap().clone();
+    let req = Request::head("/")
+        .header(IF_NONE_MATCH, etag)
+        .body(())
+        .unwrap();
+    bufd.call(req).await;
+    bufd.call(Request::post("/").body(()).unwrap()).await;
+
+    assert_eq!(
+        *seen.lock().unwrap(),
+        [
+            (http::Method::GET, StatusCode::NO_CONTENT, false),
+            (http::Method::GET, StatusCode::OK, false),
+            (http::Method::HEAD, StatusCode::NOT_MODIFIED, true),
+            (http::Method::POST, StatusCode::METHOD_NOT_ALLOWED, false),
+        ]
+    );
+}
+
+#[cfg(feature = "gzip")]
+#[tokio::test]
+async fn max_codec_tasks() {
+    use http::header::RETRY_AFTER;
+    use std::time::Duration;
+
+    let mut bufd = Service::new();
+    bufd.set_encoding(Encoding::Gzip);
+    bufd.set_compress_policy(CompressPolicy::Never);
+    bufd.set_max_codec_tasks(
+        Some(1),
+        Saturation::Shed {
+            retry_after: Duration::from_secs(2),
+        },
+    );
+    bufd.fill({
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::fast());
+        std::io::Write::write_all(&mut encoder, &[b'x'; 1 << 20]).unwrap();
+        Bytes::from(encoder.finish().unwrap())
+    });
+    let identity = || {
+        Request::get("/")
+            .header(ACCEPT_ENCODING, "identity")
+            .body(())
+            .unwrap()
+    };
+
+    let first = bufd.call(identity()).await;
+    assert_eq!(first.status(), StatusCode::OK);
+    let res = bufd.call(identity()).await;
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(res.headers().get(RETRY_AFTER).unwrap(), "2");
+
+    // the slot is freed once the task notices the response is gone
+    drop(first);
+    for _ in 0..100 {
+        let res = bufd.call(identity()).await;
+        if res.status() == StatusCode::OK {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("codec task slot was not released");
+}
+
+#[cfg(feature = "gzip")]
+#[tokio::test]
+async fn corrupt_payload() {
+    let mut bufd = Service::new();
+    bufd.set_encoding(Encoding::Gzip);
+    bufd.set_compress_policy(CompressPolicy::Never);
+    bufd.fill(Bytes::from_static(b"\x1f\x8b\x08\x00 not really gzip"));
+
+    let req = Request::get("/")
+        .header(ACCEPT_ENCODING, "identity")
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let err = res.into_body().collect().await.unwrap_err();
+    assert!(matches!(err, Error::Codec(_)), "{err}");
+}
+
+#[cfg(feature = "gzip")]
+#[tokio::test]
+async fn codec_timeout() {
+    use std::time::Duration;
+
+    let mut bufd = Service::new();
+    bufd.set_encoding(Encoding::Gzip);
+    bufd.set_compress_policy(CompressPolicy::Never);
+    bufd.set_codec_timeout(Some(Duration::from_millis(50)));
+    bufd.fill({
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::fast());
+        std::io::Write::write_all(&mut encoder, &[b'x'; 1 << 20]).unwrap();
+        Bytes::from(encoder.finish().unwrap())
+    });
+
+    let req = Request::get("/")
+        .header(ACCEPT_ENCODING, "identity")
+        .body(())
+        .unwrap();
+    let mut res = bufd.call(req).await;
+    assert!(res.body_mut().frame().await.unwrap().is_ok());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let err = res.into_body().collect().await.unwrap_err();
+    assert!(matches!(err, Error::TimedOut), "{err}");
+}
+
+#[cfg(feature = "gzip")]
+#[tokio::test]
+async fn codec_timeout_unpolled() {
+    use std::time::Duration;
+
+    let mut bufd = Service::new();
+    bufd.set_encoding(Encoding::Gzip);
+    bufd.set_compress_policy(CompressPolicy::Never);
+    bufd.set_codec_timeout(Some(Duration::from_millis(50)));
+    bufd.set_max_codec_tasks(
+        Some(1),
+        Saturation::Shed {
+            retry_after: Duration::from_secs(1),
+        },
+    );
+    bufd.fill({
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::fast());
+        std::io::Write::write_all(&mut encoder, &[b'x'; 1 << 20]).unwrap();
+        Bytes::from(encoder.finish().unwrap())
+    });
+    let identity = || {
+        Request::get("/")
+            .header(ACCEPT_ENCODING, "identity")
+            .body(())
+            .unwrap()
+    };
+
+    // never polled until the deadline has passed
+    let res = bufd.call(identity()).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // the task gave up its permit
+    let other = bufd.call(identity()).await;
+    assert_eq!(other.status(), StatusCode::OK);
+
+    let err = res.into_body().collect().await.unwrap_err();
+    assert!(matches!(err, Error::TimedOut), "{err}");
+}
+
+#[cfg(feature = "gzip")]
+#[tokio::test]
+async fn metrics() {
+    let orig_body = test_body();
+    let orig_body_gzip = {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::best());
+        std::io::copy(&mut &orig_body[..], &mut encoder).unwrap();
+        Bytes::from(encoder.finish().unwrap())
+    };
+
+    let mut bufd = Service::new();
+    bufd.set_encoding(Encoding::Gzip);
+    bufd.set_compress_policy(CompressPolicy::Never);
+    bufd.fill(orig_body_gzip.clone());
+
+    let req = Request::get("/")
+        .header(ACCEPT_ENCODING, "gzip")
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    let etag = res.headers().get(ETAG).unwrap().clone();
+    let req = Request::get("/")
+        .header(ACCEPT_ENCODING, "identity")
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    res.into_body().collect().await.unwrap();
+    let req = Request::get("/")
+        .header(ACCEPT_ENCODING, "gzip")
+        .header(IF_NONE_MATCH, etag)
+        .body(())
+        .unwrap();
+    bufd.call(req).await;
+    bufd.call(Request::post("/").body(()).unwrap()).await;
+
+    let metrics = bufd.metrics();
+    assert_eq!(metrics.requests, 4);
+    assert_eq!(metrics.not_modified, 1);
+    assert_eq!(metrics.method_not_allowed, 1);
+    assert_eq!(metrics.codec_tasks, 1);
+    assert_eq!(metrics.payload_size, orig_body_gzip.len());
+    let bytes_served = |encoding| {
+        metrics
+            .bytes_served
+            .iter()
+            .find(|(e, _)| *e == encoding)
+            .unwrap()
+            .1
+    };
+    assert_eq!(bytes_served(Encoding::Gzip), orig_body_gzip.len() as u64);
+    assert_eq!(bytes_served(Encoding::Identity), orig_body.len() as u64);
+}
+
+#[cfg(feature = "prometheus")]
+#[tokio::test]
+async fn prometheus() {
+    let shared = SharedService::new(Service::new());
+    shared.fill(Bytes::from_static(b"payload"));
+    let metrics = MetricsService::new(shared.clone());
+
+    shared.call(Request::get("/").body(()).unwrap()).await;
+    let res = metrics
+        .call(Request::get("/metrics").body(()).unwrap())
+        .await;
+    assert_eq!(
+        res.headers().get(CONTENT_TYPE).unwrap(),
+        "text/plain; version=0.0.4"
+    );
+    let text = res.into_body().collect().await.unwrap().to_bytes();
+    let text = std::str::from_utf8(&text).unwrap();
+    assert!(text.contains("# TYPE geta_requests_total counter\ngeta_requests_total 1\n"));
+    assert!(text.contains("geta_bytes_served_total{encoding=\"identity\"} 7\n"));
+    assert!(text.contains("geta_payload_bytes 7\n"));
+    assert!(text.contains("geta_decoded_size_exceeded_total 0\n"));
+
+    // refreshed on scrape
+    shared.call(Request::get("/").body(()).unwrap()).await;
+    let res = metrics
+        .call(Request::get("/metrics").body(()).unwrap())
+        .await;
+    let text = res.into_body().collect().await.unwrap().to_bytes();
+    assert!(std::str::from_utf8(&text)
+        .unwrap()
+        .contains("geta_requests_total 2\n"));
+}
+
+#[tokio::test]
+async fn introspection() {
+    let bufd = Service::new();
+    assert!(!bufd.is_filled());
+    assert!(bufd.is_empty());
+    assert_eq!(bufd.etag(), None);
+    assert_eq!(bufd.filled_at(), None);
+    assert_eq!(bufd.encoding(), Encoding::Identity);
+
+    let before = std::time::Instant::now();
+    bufd.fill(Bytes::from_static(b"payload"));
+    assert!(bufd.is_filled());
+    assert_eq!(bufd.len(), 7);
+    assert_eq!(
+        bufd.etag().unwrap(),
+        ETag::from_buf(&b"payload"[..]).as_header_value()
+    );
+    assert!(bufd.filled_at().unwrap() >= before);
+
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.headers().get(ETAG), bufd.etag().as_ref());
+}
+
+#[tokio::test]
+async fn fill_with_headers() {
+    use http::header::CONTENT_LANGUAGE;
+
+    let mut bufd = Service::new();
+    bufd.headers
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    bufd.headers
+        .insert(CONTENT_LANGUAGE, HeaderValue::from_static("en"));
+    bufd.fill(Bytes::from_static(b"{}"));
+
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), "application/json");
+
+    let mut headers = http::HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+    bufd.fill_with_headers(Bytes::from_static(b"<p>"), headers);
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), "text/html");
+    assert_eq!(res.headers().get(CONTENT_LANGUAGE).unwrap(), "en");
+
+    // dropped by the next fill
+    bufd.fill(Bytes::from_static(b"{}"));
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), "application/json");
+}
+
+#[tokio::test]
+async fn status_headers() {
+    use http::header::CACHE_CONTROL;
+
+    let mut bufd = Service::new();
+    bufd.set_cache_control(CacheControl::new().max_age(std::time::Duration::from_secs(3600)));
+    let mut headers = http::HeaderMap::new();
+    headers.insert("x-debug", HeaderValue::from_static("revalidated"));
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    bufd.set_status_headers(StatusCode::NOT_MODIFIED, headers);
+    bufd.fill(test_body());
+
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.headers().get(CACHE_CONTROL).unwrap(), "max-age=3600");
+    assert!(res.headers().get("x-debug").is_none());
+
+    let req = Request::get("/")
+        .header(IF_NONE_MATCH, res.headers().get(ETAG).unwrap())
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(res.headers().get(CACHE_CONTROL).unwrap(), "no-cache");
+    assert_eq!(res.headers().get("x-debug").unwrap(), "revalidated");
+}
+
+#[tokio::test]
+async fn middleware() {
+    use http::header::AUTHORIZATION;
+    use std::ops::ControlFlow;
+
+    struct Auth;
+
+    impl Middleware<Bytes> for Auth {
+        fn before(&self, req: &http::request::Parts) -> ControlFlow<Response<Body<Bytes, Error>>> {
+            if req.headers.contains_key(AUTHORIZATION) {
+                return ControlFlow::Continue(());
+            }
+            let res = Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .body(Body::Empty)
+                .unwrap();
+            ControlFlow::Break(res)
+        }
+
+        fn after(&self, res: &mut Response<Body<Bytes, Error>>) {
+            res.headers_mut()
+                .insert("x-served-by", HeaderValue::from_static("geta"));
+        }
+    }
+
+    let mut bufd = Service::new();
+    bufd.add_middleware(Auth);
+    bufd.fill(test_body());
+
+    let res = bufd.call(Request::get("/").body(()).unwrap()).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let req = Request::get("/")
+        .header(AUTHORIZATION, "Bearer token")
+        .body(())
+        .unwrap();
+    let res = bufd.call(req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get("x-served-by").unwrap(), "geta");
+}
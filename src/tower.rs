@@ -0,0 +1,48 @@
+use crate::{Body, Error, Service, SharedService};
+use bytes::Buf;
+use http::{Request, Response};
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+type ResponseFuture<'a, T> =
+    Pin<Box<dyn Future<Output = Result<Response<Body<T, Error>>, Infallible>> + Send + 'a>>;
+
+impl<'a, T, B> tower_service::Service<Request<B>> for &'a Service<T>
+where
+    T: Buf + Clone + Send + Sync + 'static,
+{
+    type Response = Response<Body<T, Error>>;
+    type Error = Infallible;
+    type Future = ResponseFuture<'a, T>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let service = *self;
+        let (parts, _) = req.into_parts();
+        Box::pin(async move { Ok(service.call_parts(&parts).await) })
+    }
+}
+
+impl<T, B> tower_service::Service<Request<B>> for SharedService<T>
+where
+    T: Buf + Clone + Send + Sync + 'static,
+{
+    type Response = Response<Body<T, Error>>;
+    type Error = Infallible;
+    type Future = ResponseFuture<'static, T>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let service = self.clone();
+        let (parts, _) = req.into_parts();
+        Box::pin(async move { Ok(service.call_parts(&parts).await) })
+    }
+}
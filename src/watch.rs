@@ -0,0 +1,118 @@
+use crate::files::{self, PRECOMPRESSED};
+use crate::static_dir;
+use crate::{ContentTypes, Service, SharedService, StaticDir};
+use bytes::Bytes;
+use http::header::CONTENT_TYPE;
+use http::HeaderMap;
+use notify::{Event, RecursiveMode, Watcher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Keeps re-filling from the filesystem until dropped.
+#[derive(Debug)]
+pub struct Watch {
+    _watcher: notify::RecommendedWatcher,
+}
+
+impl SharedService<Bytes> {
+    /// Fills the service from `path`, then again every time the file or its pre-compressed
+    /// sibling is written or replaced, the siblings served as variants. A file that goes
+    /// missing keeps being served as it was last read.
+    ///
+    /// Unless the service's `headers` have one, the `Content-Type` is picked from the file
+    /// extension.
+    pub fn watch_file(&self, path: impl AsRef<Path>) -> notify::Result<Watch> {
+        let path = path.as_ref().canonicalize()?;
+        let mut headers = HeaderMap::new();
+        if !self.headers.contains_key(CONTENT_TYPE) {
+            if let Some(content_type) = ContentTypes::new().guess(&path) {
+                headers.insert(CONTENT_TYPE, content_type);
+            }
+        }
+        static_dir::fill(self, &path, headers.clone())?;
+
+        let service = self.clone();
+        let file = path.clone();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            for changed in changed_paths(event) {
+                let is_sibling = PRECOMPRESSED
+                    .iter()
+                    .any(|(extension, _)| changed == files::sibling(&file, extension));
+                if changed == file || is_sibling {
+                    refill(&service, &file, headers.clone());
+                }
+            }
+        })?;
+        // the directory, so that a file replaced by a rename is still followed
+        let dir = path.parent().unwrap_or(&path);
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        Ok(Watch { _watcher: watcher })
+    }
+}
+
+impl StaticDir {
+    /// Re-fills a file's service every time the file or its pre-compressed sibling is
+    /// written. Files added after `open` are not picked up.
+    pub fn watch(self: &Arc<Self>) -> notify::Result<Watch> {
+        let dir = Arc::downgrade(self);
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let Some(dir) = dir.upgrade() else {
+                return;
+            };
+            for changed in changed_paths(event) {
+                dir.reload(&changed);
+            }
+        })?;
+        watcher.watch(&self.root, RecursiveMode::Recursive)?;
+        Ok(Watch { _watcher: watcher })
+    }
+
+    /// Re-fills the file at `path`, or the one it is a pre-compressed sibling of, along with
+    /// all its siblings.
+    fn reload(&self, path: &Path) {
+        let mut paths = vec![path.to_owned()];
+        for (extension, _) in PRECOMPRESSED {
+            if path.extension().is_some_and(|e| e == extension) {
+                paths.push(path.with_extension(""));
+            }
+        }
+        for path in paths {
+            let route = files::route(&self.root, &path);
+            let Some(service) = self.router.get(&route) else {
+                continue;
+            };
+            log(&path, static_dir::fill(service, &path, HeaderMap::new()));
+            let dir = self
+                .indexes
+                .get(&route)
+                .and_then(|dir| self.router.get(dir));
+            if let Some(dir) = dir {
+                dir.fill_shared(service);
+            }
+        }
+    }
+}
+
+fn changed_paths(event: notify::Result<Event>) -> Vec<PathBuf> {
+    match event {
+        Ok(event) if event.kind.is_create() || event.kind.is_modify() => event.paths,
+        Ok(_) => Vec::new(),
+        Err(err) => {
+            warn!("watch error: {err}");
+            Vec::new()
+        }
+    }
+}
+
+fn refill(service: &Service<Bytes>, path: &Path, headers: HeaderMap) {
+    log(path, static_dir::fill(service, path, headers));
+}
+
+fn log(path: &Path, filled: io::Result<()>) {
+    match filled {
+        Ok(()) => info!("refilled from {}", path.display()),
+        Err(err) => warn!("failed to refill from {}: {err}", path.display()),
+    }
+}